@@ -1,5 +1,11 @@
 use ballista_executor::executor_process::{start_executor_process, ExecutorProcessConfig};
+use ballista_python::capabilities::PythonCapabilities;
 use ballista_python::codec::PyPhysicalCodec;
+use ballista_python::env::PythonEnvConfig;
+use ballista_python::flight::PythonFlightService;
+use ballista_python::stateful::close_instances;
+use ballista_python::warmup::PythonWarmUp;
+use ballista_python::worker::{close_workers, run_if_worker};
 use std::sync::Arc;
 ///
 /// # Custom Ballista Executor
@@ -9,20 +15,37 @@ use std::sync::Arc;
 ///
 #[tokio::main]
 async fn main() -> ballista_core::error::Result<()> {
+    // executor is started again as worker process
+    // of functions shipped with python environments
+    run_if_worker();
+
     let _ = env_logger::builder()
         .filter_level(log::LevelFilter::Info)
         .parse_filters("ballista_python=debug,ballista_core=debug,ballista_executor=debug,ballista_scheduler=debug")
         .is_test(true)
         .try_init();
 
-    ballista_python::setup_python().expect("python environment to be set");
+    // python environment configured using `BALLISTA_PYTHON_*` environment variables,
+    // applied before codecs get to initialize the interpreter
+    let mut config = PythonEnvConfig::from_env().configure_executor(ExecutorProcessConfig::default())?;
+    // logical codec is not needed at the executor
+    // config.override_logical_codec = Some(Arc::new(PyLogicalCodec::default()));
+    // executor is its own worker command, it calls `run_if_worker`
+    let codec = PyPhysicalCodec::default().with_worker_command(std::env::current_exe()?);
+    config.override_physical_codec = Some(Arc::new(codec));
+    // modules and warm-up callables configured using
+    // `BALLISTA_PYTHON_PRELOAD_MODULES` and `BALLISTA_PYTHON_WARM_UP`
+    PythonWarmUp::from_env()?.run()?;
+
+    // advertises python version and installed packages to the scheduler
+    let capabilities = PythonCapabilities::collect()?;
+    config.override_arrow_flight_service = Some(PythonFlightService::provider(&capabilities, &config));
 
-    let config: ExecutorProcessConfig = ExecutorProcessConfig {
-        // logical codec is not needed at the executor
-        // override_logical_codec: Some(Arc::new(PyLogicalCodec::default())),
-        override_physical_codec: Some(Arc::new(PyPhysicalCodec::default())),
-        ..Default::default()
-    };
+    let result = start_executor_process(Arc::new(config)).await;
+    // closes class based function instances shared by the process
+    close_instances();
+    // stops worker processes of shipped environments
+    close_workers();
 
-    start_executor_process(Arc::new(config)).await
+    result
 }
@@ -1,8 +1,10 @@
 use ballista_core::error::BallistaError;
+use ballista_python::cluster::ClusterFunctionRegistry;
 use ballista_python::codec::{PyLogicalCodec, PyPhysicalCodec};
-use ballista_python::setup_python;
+use ballista_python::env::PythonEnvConfig;
+use ballista_python::placement::PythonDistributionPolicy;
 use ballista_scheduler::cluster::BallistaCluster;
-use ballista_scheduler::config::SchedulerConfig;
+use ballista_scheduler::config::{SchedulerConfig, TaskDistributionPolicy};
 use ballista_scheduler::scheduler_process::start_server;
 use std::net::AddrParseError;
 use std::sync::Arc;
@@ -21,13 +23,21 @@ async fn main() -> ballista_core::error::Result<()> {
         .is_test(true)
         .try_init();
 
-    setup_python().expect("python environment to be set");
-
-    let config: SchedulerConfig = SchedulerConfig {
-        override_logical_codec: Some(Arc::new(PyLogicalCodec::default())),
-        override_physical_codec: Some(Arc::new(PyPhysicalCodec::default())),
-        ..Default::default()
-    };
+    // python environment configured using `BALLISTA_PYTHON_*` environment variables,
+    // applied before codecs get to initialize the interpreter
+    let mut config = PythonEnvConfig::from_env().configure_scheduler(SchedulerConfig::default())?;
+    // requirements are checked by executors, scheduler
+    // places tasks on executors satisfying them
+    config.override_logical_codec = Some(Arc::new(PyLogicalCodec::default().with_requirements_check(false)));
+    config.override_physical_codec = Some(Arc::new(PyPhysicalCodec::default().with_requirements_check(false)));
+    // functions created by clients with cluster registry enabled
+    // are shared with other sessions of the cluster, and kept
+    // when the scheduler restarts
+    let registry = ClusterFunctionRegistry::in_directory(std::env::temp_dir().join("ballista_python_registry"));
+    let config = Arc::new(registry).configure(config);
+    // jobs which can't be run by any registered executor fail
+    let policy = Arc::new(PythonDistributionPolicy::new());
+    let mut config = policy.configure(config);
 
     let addr = format!("{}:{}", config.bind_host, config.bind_port);
     let addr = addr
@@ -35,6 +45,9 @@ async fn main() -> ballista_core::error::Result<()> {
         .map_err(|e: AddrParseError| BallistaError::Configuration(e.to_string()))?;
 
     let cluster = BallistaCluster::new_from_config(&config).await?;
+    // capabilities are obtained from executors when they register
+    policy.watch(cluster.cluster_state()).await?;
+    config.task_distribution = TaskDistributionPolicy::Custom(policy);
     start_server(cluster, addr, Arc::new(config)).await?;
 
     Ok(())
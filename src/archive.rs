@@ -0,0 +1,282 @@
+use crate::artifacts::PythonArtifactStore;
+use datafusion::common::{exec_datafusion_err, exec_err, Result};
+use pyo3::ffi::c_str;
+use pyo3::types::{PyAnyMethods, PyModule};
+use pyo3::{Bound, PyResult, Python};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// environment variable with directory where shipped environments are unpacked
+pub static ENV_ARCHIVE_CACHE: &str = "BALLISTA_PYTHON_ENV_CACHE";
+
+static ACTIVATION_CODE: &std::ffi::CStr = c_str!(
+    r#"
+import os
+import subprocess
+import sys
+import tarfile
+import zipfile
+
+TAR_MODES = {"tar": "r:", "gztar": "r:gz", "bztar": "r:bz2", "xztar": "r:xz"}
+
+def python_executable():
+    # sys.executable points to embedding process
+    version = f"python{sys.version_info.major}.{sys.version_info.minor}"
+    candidate = os.path.join(sys.exec_prefix, "bin", version)
+    return candidate if os.path.exists(candidate) else "python3"
+
+def is_inside(root, name):
+    path = os.path.realpath(os.path.join(root, name))
+    return not os.path.isabs(name) and (path == root or path.startswith(root + os.sep))
+
+def check_member(root, name):
+    if not is_inside(root, name):
+        raise ValueError(f"archive member: {name} is outside of target directory")
+
+def unpack_zip(archive, target):
+    root = os.path.realpath(target)
+    with zipfile.ZipFile(archive) as z:
+        # all members are checked before anything is extracted
+        for name in z.namelist():
+            check_member(root, name)
+        z.extractall(target)
+
+def unpack_tar(archive, target, format):
+    root = os.path.realpath(target)
+    with tarfile.open(archive, TAR_MODES[format]) as t:
+        members = []
+        for member in t.getmembers():
+            check_member(root, member.name)
+            if member.issym() or member.islnk():
+                link = member.linkname if member.islnk() else os.path.join(os.path.dirname(member.name), member.linkname)
+                if not is_inside(root, link):
+                    # like interpreter links of virtual environments,
+                    # not needed on module path
+                    print(f"skipping archive link: {member.name} -> {member.linkname}", file=sys.stderr)
+                    continue
+            elif not (member.isfile() or member.isdir()):
+                raise ValueError(f"archive member: {member.name} is not a file or directory")
+            members.append(member)
+        if hasattr(tarfile, "data_filter"):
+            t.extractall(target, members=members, filter="data")
+        else:
+            t.extractall(target, members=members)
+
+def unpack(archive, target, format):
+    if format == "zip":
+        unpack_zip(archive, target)
+    elif format in TAR_MODES:
+        unpack_tar(archive, target, format)
+    else:
+        raise ValueError(f"unsupported archive format: {format}")
+    requirements = os.path.join(target, "requirements.txt")
+    if os.path.exists(requirements):
+        # wheelhouse, installs requirements from shipped wheels only
+        subprocess.check_call([
+            python_executable(), "-m", "pip", "install", "--quiet", "--no-index",
+            "--find-links", target, "--target", os.path.join(target, "site-packages"),
+            "-r", requirements,
+        ])
+
+def module_path(target):
+    site_packages = os.path.join(target, "site-packages")
+    if os.path.isdir(site_packages):
+        return site_packages
+    # virtual environment
+    version = f"python{sys.version_info.major}.{sys.version_info.minor}"
+    for lib in [os.path.join(target, "lib", version, "site-packages"), os.path.join(target, "Lib", "site-packages")]:
+        if os.path.isdir(lib):
+            return lib
+    # plain modules
+    return target
+"#
+);
+
+/// Packed python environment shipped with a job.
+///
+/// Archive can contain a virtual environment, plain python modules,
+/// or a wheelhouse with `requirements.txt` which is installed from
+/// shipped wheels only. Archive content is published to
+/// [PythonArtifactStore] once, functions refer to it by digest.
+/// Executors unpack it into a content addressed cache and call
+/// functions shipped with it in a worker process of the environment,
+/// see [crate::worker].
+#[derive(Debug, Clone)]
+pub struct PythonEnvArchive {
+    /// archive format, as expected by `shutil.unpack_archive`
+    pub format: String,
+    /// sha256 digest of the archive content
+    pub digest: String,
+    /// archive content, not set for archive referenced by digest,
+    /// whose content is fetched from [PythonArtifactStore]
+    pub content: Option<Arc<Vec<u8>>>,
+}
+
+impl PythonEnvArchive {
+    /// creates archive from `zip`, `tar`, `tar.gz`, `tar.bz2` or `tar.xz` file
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+        let format = match name {
+            n if n.ends_with(".zip") || n.ends_with(".whl") => "zip",
+            n if n.ends_with(".tar") => "tar",
+            n if n.ends_with(".tar.gz") || n.ends_with(".tgz") => "gztar",
+            n if n.ends_with(".tar.bz2") => "bztar",
+            n if n.ends_with(".tar.xz") => "xztar",
+            _ => return exec_err!("unsupported python environment archive: {}", path.display()),
+        };
+        let content = std::fs::read(path)?;
+
+        Ok(Self::new(format, content))
+    }
+
+    pub fn new(format: impl Into<String>, content: Vec<u8>) -> Self {
+        let digest = sha256_hex(&content);
+
+        Self {
+            format: format.into(),
+            digest,
+            content: Some(Arc::new(content)),
+        }
+    }
+
+    /// archive referenced by digest, published to [PythonArtifactStore],
+    /// fails if digest is not a hex digest
+    pub fn from_digest(format: impl Into<String>, digest: impl Into<String>) -> Result<Self> {
+        let digest = digest.into();
+        check_digest("environment", &digest)?;
+
+        Ok(Self {
+            format: format.into(),
+            digest,
+            content: None,
+        })
+    }
+
+    /// publishes archive content to the store, if the content is known
+    pub fn publish(&self, store: &PythonArtifactStore) -> Result<()> {
+        match &self.content {
+            Some(content) => store.put(&self.digest, content),
+            None => Ok(()),
+        }
+    }
+
+    /// unpacks archive into content addressed `cache_dir`, if not already
+    /// unpacked, and returns module path of the environment. Archive
+    /// content is fetched from the store if it is not known.
+    ///
+    /// Members of the archive are validated before anything is extracted,
+    /// wheelhouse requirements are installed once, when it is unpacked.
+    pub fn activate(&self, store: &PythonArtifactStore, cache_dir: &Path) -> Result<PathBuf> {
+        // digest is used as directory name, existing directory is trusted
+        check_digest("environment", &self.digest)?;
+        let target = cache_dir.join(&self.digest);
+        Python::with_gil(|py| {
+            let module = PyModule::from_code(
+                py,
+                ACTIVATION_CODE,
+                c_str!("ballista_env.py"),
+                c_str!("ballista_python_env"),
+            )
+            .map_err(|e| exec_datafusion_err!("python environment activation failed: {e}"))?;
+
+            if !target.is_dir() {
+                self.unpack(&module, store, cache_dir, &target)?;
+            }
+
+            module
+                .getattr("module_path")
+                .and_then(|module_path| module_path.call1((&target,)))
+                .and_then(|path| path.extract::<PathBuf>())
+                .map_err(|e| exec_datafusion_err!("python environment {} can't be activated: {e}", self.digest))
+        })
+    }
+
+    fn unpack(
+        &self,
+        module: &Bound<'_, PyModule>,
+        store: &PythonArtifactStore,
+        cache_dir: &Path,
+        target: &Path,
+    ) -> Result<()> {
+        let content = match &self.content {
+            Some(content) => content.clone(),
+            None => Arc::new(store.get(&self.digest)?),
+        };
+        if sha256_hex(&content) != self.digest {
+            return exec_err!("python environment archive digest mismatch: {}", self.digest);
+        }
+
+        log::info!("unpacking python environment: {} ...", self.digest);
+        std::fs::create_dir_all(cache_dir)?;
+        // unpacked to temporary directory and renamed,
+        // so partially unpacked environment is never used
+        let suffix = format!("{}-{:?}", std::process::id(), std::thread::current().id());
+        let archive = cache_dir.join(format!("{}.{suffix}.archive", self.digest));
+        let staging = cache_dir.join(format!("{}.{suffix}.staging", self.digest));
+        std::fs::write(&archive, content.as_slice())?;
+
+        let unpacked = module
+            .getattr("unpack")
+            .and_then(|unpack| unpack.call1((&archive, &staging, &self.format)));
+        let _ = std::fs::remove_file(&archive);
+        if let Err(e) = unpacked {
+            let _ = std::fs::remove_dir_all(&staging);
+            return exec_err!("python environment {} can't be unpacked: {e}", self.digest);
+        }
+        if let Err(e) = std::fs::rename(&staging, target) {
+            let _ = std::fs::remove_dir_all(&staging);
+            // unpacked concurrently by another process
+            if !target.is_dir() {
+                return exec_err!("python environment {} can't be unpacked: {e}", self.digest);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// archives are identified by their format and content digest
+impl PartialEq for PythonEnvArchive {
+    fn eq(&self, other: &Self) -> bool {
+        self.format == other.format && self.digest == other.digest
+    }
+}
+
+pub(crate) fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// checks that digest shipped with a function is a sha256 hex
+/// digest, before it is used as file or directory name
+pub(crate) fn check_digest(kind: &str, digest: &str) -> Result<()> {
+    if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return exec_err!("invalid python {kind} digest: {digest}");
+    }
+    Ok(())
+}
+
+/// default cache directory for unpacked environments,
+/// [ENV_ARCHIVE_CACHE] or `ballista_python/envs` in temp directory
+pub fn default_cache_dir() -> PathBuf {
+    std::env::var_os(ENV_ARCHIVE_CACHE)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("ballista_python").join("envs"))
+}
+
+/// runs `f` with `module_path` prepended to `sys.path`
+pub fn with_module_path<T>(py: Python<'_>, module_path: Option<&Path>, f: impl FnOnce() -> PyResult<T>) -> PyResult<T> {
+    match module_path {
+        None => f(),
+        Some(module_path) => {
+            // entries have to be strings, path objects are ignored by importers
+            let module_path = module_path.to_string_lossy();
+            let path = py.import("sys")?.getattr("path")?;
+            path.call_method1("insert", (0, module_path.as_ref()))?;
+            let result = f();
+            path.call_method1("remove", (module_path.as_ref(),))?;
+            result
+        }
+    }
+}
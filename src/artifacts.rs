@@ -0,0 +1,114 @@
+use crate::archive::{check_digest, sha256_hex};
+use datafusion::common::{exec_err, Result};
+use std::path::{Path, PathBuf};
+
+/// environment variable with directory of [PythonArtifactStore]
+pub static ENV_ARTIFACT_STORE: &str = "BALLISTA_PYTHON_ARTIFACTS";
+
+/// Content addressed store of artifacts, like python environment
+/// archives, shipped with jobs.
+///
+/// Store shared by clients and executors of a cluster, like a shared
+/// volume, lets clients publish artifact content once, functions refer
+/// to it by digest only, and executors fetch it from the store when it
+/// is needed. Artifacts of local store, the default if [ENV_ARTIFACT_STORE]
+/// is not set, are shipped with functions, as executors can't reach it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PythonArtifactStore {
+    root: PathBuf,
+    shared: bool,
+}
+
+impl PythonArtifactStore {
+    /// store shared by clients and executors
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            shared: true,
+        }
+    }
+
+    /// store reachable by this host only, artifacts are shipped with functions
+    pub fn local(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            shared: false,
+        }
+    }
+
+    /// shared store in [ENV_ARTIFACT_STORE] directory,
+    /// or local store `ballista_python/artifacts` in temp directory
+    pub fn from_env() -> Self {
+        match std::env::var_os(ENV_ARTIFACT_STORE) {
+            Some(root) => Self::new(root),
+            None => Self::local(std::env::temp_dir().join("ballista_python").join("artifacts")),
+        }
+    }
+
+    /// `true` if artifacts are published to the store and shipped
+    /// by digest, `false` if they are shipped with functions
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// stores content under its digest, if it is not stored already
+    pub fn put(&self, digest: &str, content: &[u8]) -> Result<()> {
+        let path = self.path(digest)?;
+        if path.is_file() {
+            return Ok(());
+        }
+        if sha256_hex(content) != digest {
+            return exec_err!("python artifact digest mismatch: {digest}");
+        }
+        std::fs::create_dir_all(&self.root)?;
+        // written to temporary file and renamed,
+        // so partially written artifact is never read
+        let suffix = format!("{}-{:?}", std::process::id(), std::thread::current().id());
+        let staging = self.root.join(format!("{digest}.{suffix}.staging"));
+        std::fs::write(&staging, content)?;
+        if let Err(e) = std::fs::rename(&staging, &path) {
+            let _ = std::fs::remove_file(&staging);
+            // published concurrently by another session
+            if !path.is_file() {
+                return exec_err!("python artifact {digest} can't be stored: {e}");
+            }
+        }
+        log::debug!("artifact_store::put - artifact: {digest} stored");
+
+        Ok(())
+    }
+
+    /// reads content stored under the digest, verifying it
+    pub fn get(&self, digest: &str) -> Result<Vec<u8>> {
+        let path = self.path(digest)?;
+        let content = std::fs::read(&path).or_else(|e| {
+            exec_err!(
+                "python artifact {digest} not found in store: {} ({e}), store has to be shared by clients \
+                 and executors, or not configured ({ENV_ARTIFACT_STORE}) so artifacts are shipped with functions",
+                self.root.display()
+            )
+        })?;
+        if sha256_hex(&content) != digest {
+            return exec_err!("python artifact digest mismatch: {digest}");
+        }
+
+        Ok(content)
+    }
+
+    fn path(&self, digest: &str) -> Result<PathBuf> {
+        // digest comes from shipped function, it is used as file name
+        check_digest("artifact", digest)?;
+
+        Ok(self.root.join(digest))
+    }
+}
+
+impl Default for PythonArtifactStore {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
@@ -0,0 +1,13 @@
+//!
+//! # Python Worker
+//!
+//! Worker process calling functions shipped with python environments,
+//! configured with [ballista_python::worker::ENV_WORKER_COMMAND] for
+//! executors which are not able to start themselves as workers.
+//!
+fn main() {
+    ballista_python::worker::run_if_worker();
+
+    eprintln!("ballista-python-worker is started by executors, it should not be started directly");
+    std::process::exit(2);
+}
@@ -0,0 +1,195 @@
+use crate::archive::{check_digest, sha256_hex, with_module_path};
+use crate::artifacts::PythonArtifactStore;
+use datafusion::common::{exec_datafusion_err, exec_err, Result};
+use pyo3::ffi::c_str;
+use pyo3::types::{PyAnyMethods, PyBytes, PyModule};
+use pyo3::{Bound, PyObject, Python};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+static BUNDLE_CODE: &std::ffi::CStr = c_str!(
+    r#"
+import importlib
+import io
+import os
+import zipfile
+
+def pack(directory):
+    # entries have fixed timestamps, so the same
+    # modules always make the same bundle
+    buffer = io.BytesIO()
+    with zipfile.ZipFile(buffer, "w", zipfile.ZIP_DEFLATED) as archive:
+        for root, dirs, files in os.walk(directory):
+            dirs[:] = sorted(d for d in dirs if d != "__pycache__")
+            for file in sorted(files):
+                if file.endswith(".pyc"):
+                    continue
+                path = os.path.join(root, file)
+                entry = zipfile.ZipInfo(os.path.relpath(path, directory).replace(os.sep, "/"), (1980, 1, 1, 0, 0, 0))
+                entry.compress_type = zipfile.ZIP_DEFLATED
+                with open(path, "rb") as f:
+                    archive.writestr(entry, f.read())
+    return buffer.getvalue()
+
+def unpack(content, target, package):
+    package_dir = os.path.join(target, package)
+    with zipfile.ZipFile(io.BytesIO(content)) as archive:
+        archive.extractall(package_dir)
+    init = os.path.join(package_dir, "__init__.py")
+    if not os.path.exists(init):
+        open(init, "w").close()
+
+def load(package, module, function):
+    return getattr(importlib.import_module(f"{package}.{module}"), function)
+"#
+);
+
+/// Bundle of python modules (zip archive of `.py` files) function
+/// is loaded from. Bundle content is published to [PythonArtifactStore]
+/// once, functions refer to it by digest.
+///
+/// Bundle is unpacked into content addressed cache and imported as
+/// package with unique name, derived from bundle digest, so bundles
+/// with the same module names do not clash. Modules of the bundle
+/// import each other relatively, like `from . import helpers`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PythonBundle {
+    /// sha256 digest of the bundle content
+    pub digest: String,
+    /// zip archive with bundle modules, not set for bundle referenced
+    /// by digest, whose content is fetched from [PythonArtifactStore]
+    pub content: Option<Arc<Vec<u8>>>,
+    /// function loaded from the bundle, in `module:function` format
+    pub entry_point: String,
+}
+
+impl PythonBundle {
+    pub fn try_new(content: Vec<u8>, entry_point: impl Into<String>) -> Result<Self> {
+        let entry_point = entry_point.into();
+        if parse_entry_point(&entry_point).is_none() {
+            return exec_err!("invalid bundle entry point: {entry_point}, expected `module:function`");
+        }
+
+        Ok(Self {
+            digest: sha256_hex(&content),
+            content: Some(Arc::new(content)),
+            entry_point,
+        })
+    }
+
+    /// bundle referenced by digest, published to [PythonArtifactStore]
+    pub fn from_digest(digest: impl Into<String>, entry_point: impl Into<String>) -> Self {
+        Self {
+            digest: digest.into(),
+            content: None,
+            entry_point: entry_point.into(),
+        }
+    }
+
+    /// publishes bundle content to the store, if the content is known
+    pub fn publish(&self, store: &PythonArtifactStore) -> Result<()> {
+        match &self.content {
+            Some(content) => store.put(&self.digest, content),
+            None => Ok(()),
+        }
+    }
+
+    /// creates bundle from a zip file or a directory with python modules
+    pub fn from_path(path: impl AsRef<Path>, entry_point: impl Into<String>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = match path.is_dir() {
+            true => Python::with_gil(|py| {
+                bundle_module(py)?
+                    .getattr("pack")
+                    .and_then(|pack| pack.call1((path,)))
+                    .and_then(|content| content.extract::<Vec<u8>>())
+                    .map_err(|e| exec_datafusion_err!("python bundle: {} can't be packed: {e}", path.display()))
+            })?,
+            false => std::fs::read(path)?,
+        };
+
+        Self::try_new(content, entry_point)
+    }
+
+    /// name of the package bundle modules are imported from
+    pub fn package(&self) -> String {
+        format!("ballista_bundle_{}", &self.digest[..self.digest.len().min(16)])
+    }
+
+    /// unpacks bundle into content addressed `cache_dir`, if not
+    /// already unpacked, and returns its entry point function.
+    /// Bundle content is fetched from the store if it is not known.
+    pub fn load(&self, py: Python<'_>, store: &PythonArtifactStore, cache_dir: &Path) -> Result<PyObject> {
+        let module = bundle_module(py)?;
+        let package = self.package();
+
+        let target = self.unpacked_path(cache_dir)?;
+        if !target.is_dir() {
+            let content = match &self.content {
+                Some(content) => content.clone(),
+                None => Arc::new(store.get(&self.digest)?),
+            };
+            if sha256_hex(&content) != self.digest {
+                return exec_err!("python bundle digest mismatch: {}", self.digest);
+            }
+            log::info!("unpacking python bundle: {} ...", self.digest);
+            std::fs::create_dir_all(cache_dir)?;
+            // unpacked to temporary directory and renamed,
+            // so partially unpacked bundle is never used
+            let suffix = format!("{}-{:?}", std::process::id(), std::thread::current().id());
+            let staging = cache_dir.join(format!("bundle-{}.{suffix}.staging", self.digest));
+            let content = PyBytes::new(py, &content);
+            if let Err(e) = module
+                .getattr("unpack")
+                .and_then(|unpack| unpack.call1((content, &staging, &package)))
+            {
+                let _ = std::fs::remove_dir_all(&staging);
+                return exec_err!("python bundle {} can't be unpacked: {e}", self.digest);
+            }
+            if std::fs::rename(&staging, &target).is_err() {
+                // unpacked concurrently by another task
+                let _ = std::fs::remove_dir_all(&staging);
+            }
+        }
+
+        let (module_name, function) = parse_entry_point(&self.entry_point)
+            .ok_or_else(|| exec_datafusion_err!("invalid bundle entry point: {}", self.entry_point))?;
+        // package stays imported, so bundle is not needed
+        // on module path when the function is called
+        let func = with_module_path(py, Some(&target), || {
+            module.getattr("load")?.call1((&package, module_name, function))
+        })
+        .map_err(|e| exec_datafusion_err!("function {} can't be loaded from python bundle: {e}", self.entry_point))?;
+        log::debug!(
+            "bundle::load - function: {} loaded from package: {package}",
+            self.entry_point
+        );
+
+        Ok(func.unbind())
+    }
+
+    /// directory bundle is unpacked to
+    fn unpacked_path(&self, cache_dir: &Path) -> Result<PathBuf> {
+        // digest comes from shipped function, it is used as directory name
+        check_digest("bundle", &self.digest)?;
+
+        Ok(cache_dir.join(format!("bundle-{}", self.digest)))
+    }
+}
+
+fn bundle_module(py: Python<'_>) -> Result<Bound<'_, PyModule>> {
+    PyModule::from_code(
+        py,
+        BUNDLE_CODE,
+        c_str!("ballista_bundle.py"),
+        c_str!("ballista_python_bundle"),
+    )
+    .map_err(|e| exec_datafusion_err!("python bundle support can't be loaded: {e}"))
+}
+
+fn parse_entry_point(entry_point: &str) -> Option<(&str, &str)> {
+    entry_point
+        .split_once(':')
+        .filter(|(module, function)| !module.trim().is_empty() && !function.trim().is_empty())
+        .map(|(module, function)| (module.trim(), function.trim()))
+}
@@ -0,0 +1,135 @@
+use crate::requirements::PythonRequirement;
+use datafusion::common::{exec_datafusion_err, Result};
+use prost::Message;
+use pyo3::types::{PyAnyMethods, PyStringMethods};
+use pyo3::Python;
+use std::collections::{BTreeMap, HashMap};
+
+/// flight action returning python capabilities of an executor
+pub static ACTION_PYTHON_CAPABILITIES: &str = "BALLISTA_PYTHON_CAPABILITIES";
+
+/// package name which can be used in requirements
+/// to require python version, like `python>=3.11`
+pub static PYTHON_PACKAGE: &str = "python";
+
+/// Python version and packages installed at an executor,
+/// advertised to schedulers so tasks running python functions
+/// are placed on executors satisfying their requirements.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PythonCapabilities {
+    pub python_version: String,
+    /// installed package versions, keyed by normalized package name
+    pub packages: BTreeMap<String, String>,
+}
+
+impl PythonCapabilities {
+    /// collects python version and packages installed
+    /// in current python environment
+    pub fn collect() -> Result<Self> {
+        Python::with_gil(|py| {
+            let python_version = py
+                .import("platform")?
+                .getattr("python_version")?
+                .call0()?
+                .extract::<String>()?;
+            let mut packages = BTreeMap::new();
+            for distribution in py
+                .import("importlib.metadata")?
+                .getattr("distributions")?
+                .call0()?
+                .try_iter()?
+            {
+                let distribution = distribution?;
+                // broken distributions may have no metadata
+                let name = distribution.getattr("metadata").and_then(|m| m.get_item("Name"));
+                let version = distribution.getattr("version");
+                if let (Ok(name), Ok(version)) = (name, version) {
+                    if let (Ok(name), Ok(version)) = (name.str(), version.str()) {
+                        packages.insert(
+                            normalize_name(&name.to_string_lossy()),
+                            version.to_string_lossy().to_string(),
+                        );
+                    }
+                }
+            }
+
+            Ok(Self {
+                python_version,
+                packages,
+            })
+        })
+        .map_err(|e: pyo3::PyErr| exec_datafusion_err!("python capabilities can't be collected: {e}"))
+    }
+
+    /// checks if all requirements are satisfied by installed packages
+    pub fn satisfies(&self, requirements: &[PythonRequirement]) -> bool {
+        requirements.iter().all(|requirement| {
+            let name = normalize_name(&requirement.package);
+            let installed = match name == PYTHON_PACKAGE {
+                true => Some(&self.python_version),
+                false => self.packages.get(&name),
+            };
+            installed.is_some_and(|version| requirement.is_satisfied_by(version))
+        })
+    }
+
+    pub fn encode_to_vec(&self) -> Vec<u8> {
+        CapabilitiesProto {
+            python_version: self.python_version.clone(),
+            packages: self.packages.clone().into_iter().collect(),
+        }
+        .encode_to_vec()
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let proto = CapabilitiesProto::decode(buf)
+            .map_err(|e| exec_datafusion_err!("python capabilities can't be decoded: {e}"))?;
+
+        Ok(Self {
+            python_version: proto.python_version,
+            packages: proto.packages.into_iter().collect(),
+        })
+    }
+}
+
+/// normalized package name, as defined by PEP 503
+fn normalize_name(name: &str) -> String {
+    name.split(['-', '_', '.'])
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+        .to_lowercase()
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct CapabilitiesProto {
+    #[prost(string, tag = 1)]
+    python_version: String,
+    #[prost(map = "string, string", tag = 2)]
+    packages: HashMap<String, String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::PythonCapabilities;
+    use crate::requirements::PythonRequirement;
+
+    #[test]
+    fn should_check_requirements() {
+        let capabilities = PythonCapabilities {
+            python_version: "3.12.1".to_string(),
+            packages: [("scikit-learn".to_string(), "1.5.2".to_string())]
+                .into_iter()
+                .collect(),
+        };
+        let capabilities = PythonCapabilities::decode(&capabilities.encode_to_vec()).unwrap();
+
+        let satisfied = |r: &str| capabilities.satisfies(&PythonRequirement::parse_list(r).unwrap());
+        assert!(satisfied(""));
+        assert!(satisfied("scikit_learn>=1.3,<2"));
+        assert!(satisfied("Scikit.Learn; python>=3.11"));
+        assert!(!satisfied("scikit-learn>=2"));
+        assert!(!satisfied("python<3.12"));
+        assert!(!satisfied("pandas"));
+    }
+}
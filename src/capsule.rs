@@ -0,0 +1,92 @@
+use datafusion::arrow::array::{make_array, ArrayData, ArrayRef};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::ffi::{to_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+use datafusion::arrow::pyarrow::{FromPyArrow, ToPyArrow};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyCapsule, PyDict, PyTuple};
+use std::ffi::CString;
+
+/// Arrow array passed to python functions without `pyarrow`,
+/// implementing [Arrow PyCapsule interface](https://arrow.apache.org/docs/format/CDataInterface/PyCapsuleInterface.html),
+/// so it can be consumed by any arrow library, like
+/// `pyarrow.array(array)`, `polars.Series(array)` or `nanoarrow.Array(array)`.
+#[pyclass(name = "ArrowArray", module = "ballista_python", frozen)]
+pub struct PyArrowArray {
+    data: ArrayData,
+}
+
+impl PyArrowArray {
+    pub fn new(data: ArrayData) -> Self {
+        Self { data }
+    }
+}
+
+#[pymethods]
+impl PyArrowArray {
+    /// exports array as `arrow_schema` and `arrow_array` capsules,
+    /// requested schema is not supported, array is exported as it is
+    #[pyo3(signature = (requested_schema = None))]
+    fn __arrow_c_array__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<PyObject>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let _ = requested_schema;
+        let (array, schema): (FFI_ArrowArray, FFI_ArrowSchema) =
+            to_ffi(&self.data).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        // capsules own exported structs, released ones
+        // are moved out by consumer, others are released on drop
+        let schema = PyCapsule::new(py, schema, Some(CString::new("arrow_schema")?))?;
+        let array = PyCapsule::new(py, array, Some(CString::new("arrow_array")?))?;
+
+        PyTuple::new(py, [schema.into_any(), array.into_any()])
+    }
+
+    fn __len__(&self) -> usize {
+        self.data.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ArrowArray(type={}, len={})", self.data.data_type(), self.data.len())
+    }
+}
+
+/// Converts value returned by python function to array of `data_type`.
+///
+/// Accepts objects implementing arrow PyCapsule interface (`__arrow_c_array__`
+/// or `__arrow_c_stream__`), `pyarrow` arrays and chunked arrays, which are
+/// concatenated, and anything `pyarrow.array` accepts, like `numpy` arrays
+/// or lists, converted with declared type. Arrays of other type are cast.
+pub fn to_array(py: Python<'_>, value: &Bound<'_, PyAny>, data_type: &DataType) -> PyResult<ArrayRef> {
+    let data = if value.hasattr("__arrow_c_array__")? || is_pyarrow_array(value)? {
+        ArrayData::from_pyarrow_bound(value)?
+    } else if value.hasattr("combine_chunks")? {
+        ArrayData::from_pyarrow_bound(&value.call_method0("combine_chunks")?)?
+    } else if value.hasattr("__arrow_c_stream__")? {
+        let chunked = py.import("pyarrow")?.getattr("chunked_array")?.call1((value,))?;
+        ArrayData::from_pyarrow_bound(&chunked.call_method0("combine_chunks")?)?
+    } else {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("type", data_type.to_pyarrow(py)?)?;
+        let array = py.import("pyarrow")?.getattr("array")?.call((value,), Some(&kwargs))?;
+        ArrayData::from_pyarrow_bound(&array)?
+    };
+
+    let array = make_array(data);
+    match array.data_type() == data_type {
+        true => Ok(array),
+        false => cast(&array, data_type).map_err(|e| {
+            PyValueError::new_err(format!(
+                "function returned array of type: {}, which can't be cast to: {data_type}: {e}",
+                array.data_type()
+            ))
+        }),
+    }
+}
+
+/// older `pyarrow` arrays, exported using `_export_to_c`
+fn is_pyarrow_array(value: &Bound<'_, PyAny>) -> PyResult<bool> {
+    Ok(value.hasattr("_export_to_c")? && !value.hasattr("combine_chunks")?)
+}
@@ -0,0 +1,437 @@
+use crate::coroutine::AsyncOptions;
+use crate::factory::{
+    FunctionOptions, LANGUAGE_PYTHON, OPTION_CHUNK_SIZE, OPTION_CONCURRENCY, OPTION_DEADLINE, OPTION_HANDLER,
+    OPTION_INSTANCE, OPTION_MODE, OPTION_REQUIREMENTS, OPTION_TIMEOUT,
+};
+use crate::stateful::InstanceScope;
+use crate::udf::{CallMode, PythonUDF};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::{exec_datafusion_err, exec_err, DFSchema, Result, ScalarValue};
+use datafusion::logical_expr::sqlparser::ast::{self, Ident};
+use datafusion::logical_expr::{Cast, CreateFunction, CreateFunctionBody, Expr, OperateFunctionArg, Volatility};
+use datafusion::sql::unparser::Unparser;
+use datafusion_proto::generated::datafusion_common::ArrowType;
+use prost::Message;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Store of function definitions, which outlive sessions
+/// they have been created in.
+pub trait FunctionCatalog: Debug + Send + Sync {
+    /// stores definition, replacing definition with the same name
+    fn save(&self, definition: &FunctionDefinition) -> Result<()>;
+    /// returns all stored definitions
+    fn load(&self) -> Result<Vec<FunctionDefinition>>;
+    /// removes definition, returns `false` if it does not exist
+    fn remove(&self, name: &str) -> Result<bool>;
+}
+
+/// Definition of a function created with `CREATE FUNCTION` statement
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub language: String,
+    /// argument names (empty if not named) and types
+    pub args: Vec<(String, DataType)>,
+    pub return_type: DataType,
+    pub volatility: Option<Volatility>,
+    /// function code, empty for functions loaded from a bundle
+    pub body: String,
+    /// `OPTIONS` clause
+    pub options: Vec<(String, String)>,
+    pub strict: bool,
+}
+
+impl FunctionDefinition {
+    /// creates definition from `CREATE FUNCTION` statement and its options
+    pub fn try_new(statement: &CreateFunction, options: &FunctionOptions) -> Result<Self> {
+        let body = match &statement.params.function_body {
+            Some(Expr::Literal(ScalarValue::Utf8(Some(body)), _)) => body.clone(),
+            // function loaded from a bundle has no definition
+            None => String::new(),
+            _ => return exec_err!("function: {} has no definition which can be stored", statement.name),
+        };
+        let args = statement
+            .args
+            .iter()
+            .flatten()
+            .map(|a| {
+                (
+                    a.name.as_ref().map(|n| n.value.clone()).unwrap_or_default(),
+                    a.data_type.clone(),
+                )
+            })
+            .collect();
+
+        Ok(Self {
+            name: statement.name.clone(),
+            language: statement
+                .params
+                .language
+                .as_ref()
+                .map(|l| l.value.clone())
+                .unwrap_or_default(),
+            args,
+            return_type: statement
+                .return_type
+                .clone()
+                .ok_or_else(|| exec_datafusion_err!("function: {} has no return type", statement.name))?,
+            volatility: statement.params.behavior,
+            body,
+            options: options.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            strict: options.strict(),
+        })
+    }
+
+    /// `CREATE FUNCTION` statement and options the function can be created from again
+    pub fn to_statement(&self) -> (CreateFunction, FunctionOptions) {
+        let args = self
+            .args
+            .iter()
+            .map(|(name, data_type)| OperateFunctionArg {
+                name: (!name.is_empty()).then(|| Ident::new(name)),
+                data_type: data_type.clone(),
+                default_expr: None,
+            })
+            .collect();
+        let statement = CreateFunction {
+            or_replace: true,
+            temporary: false,
+            name: self.name.clone(),
+            args: Some(args),
+            return_type: Some(self.return_type.clone()),
+            params: CreateFunctionBody {
+                language: Some(Ident::new(&self.language)),
+                behavior: self.volatility,
+                function_body: (!self.body.is_empty())
+                    .then(|| Expr::Literal(ScalarValue::Utf8(Some(self.body.clone())), None)),
+            },
+            schema: Arc::new(DFSchema::empty()),
+        };
+        let options = FunctionOptions::new(self.options.clone()).with_strict(self.strict);
+
+        (statement, options)
+    }
+
+    /// definition of python function created from source code,
+    /// `None` if function source is not known, or function is
+    /// a class instantiated with constructor arguments
+    pub fn from_udf(udf: &PythonUDF) -> Option<Self> {
+        let source = udf.source.as_ref()?;
+        if udf.instance.as_ref().is_some_and(|i| !i.arguments().is_empty()) {
+            return None;
+        }
+        let args = udf
+            .input_types
+            .iter()
+            .enumerate()
+            .map(|(position, data_type)| {
+                let name = udf.argument_names.get(position).cloned().unwrap_or_default();
+                (name, data_type.clone())
+            })
+            .collect();
+        let mut options = vec![];
+        if source.entry_point != udf.name {
+            options.push((OPTION_HANDLER.to_string(), source.entry_point.clone()));
+        }
+        if udf.mode != CallMode::default() {
+            options.push((OPTION_MODE.to_string(), udf.mode.to_string()));
+        }
+        let async_options = &udf.async_options;
+        if async_options.concurrency != AsyncOptions::default().concurrency {
+            options.push((OPTION_CONCURRENCY.to_string(), async_options.concurrency.to_string()));
+        }
+        if let Some(timeout) = async_options.timeout {
+            options.push((OPTION_TIMEOUT.to_string(), timeout.as_secs_f64().to_string()));
+        }
+        if async_options.deadline != AsyncOptions::default().deadline {
+            options.push((
+                OPTION_DEADLINE.to_string(),
+                async_options.deadline.as_secs_f64().to_string(),
+            ));
+        }
+        if let Some(chunk_size) = async_options.chunk_size {
+            options.push((OPTION_CHUNK_SIZE.to_string(), chunk_size.to_string()));
+        }
+        if let Some(instance) = udf.instance.as_ref().filter(|i| i.scope() != InstanceScope::default()) {
+            options.push((OPTION_INSTANCE.to_string(), instance.scope().to_string()));
+        }
+        if !udf.requirements.is_empty() {
+            let requirements = udf.requirements.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+            options.push((OPTION_REQUIREMENTS.to_string(), requirements.join("; ")));
+        }
+        options.sort();
+
+        Some(Self {
+            name: udf.name.clone(),
+            language: LANGUAGE_PYTHON.to_string(),
+            args,
+            return_type: udf.return_type.clone(),
+            volatility: Some(udf.signature.volatility),
+            body: source.code.clone(),
+            options,
+            strict: udf.strict,
+        })
+    }
+
+    /// `CREATE FUNCTION` statement creating the function,
+    /// to be executed with [crate::sql::PythonSessionExt::python_sql]
+    pub fn to_sql(&self) -> Result<String> {
+        let args = self
+            .args
+            .iter()
+            .map(|(name, data_type)| match name.is_empty() {
+                true => sql_type(data_type),
+                false => Ok(format!("{} {}", Ident::with_quote('"', name), sql_type(data_type)?)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut sql = format!(
+            "CREATE FUNCTION {}({})\nRETURNS {}\nLANGUAGE {}",
+            Ident::with_quote('"', &self.name),
+            args.join(", "),
+            sql_type(&self.return_type)?,
+            self.language.to_uppercase()
+        );
+        if let Some(volatility) = &self.volatility {
+            sql.push_str(&format!("\n{}", volatility_name(volatility).to_uppercase()));
+        }
+        if self.strict {
+            sql.push_str("\nSTRICT");
+        }
+        if !self.options.is_empty() {
+            let options = self
+                .options
+                .iter()
+                .map(|(k, v)| format!("{k} {}", quote(v)))
+                .collect::<Vec<_>>();
+            sql.push_str(&format!("\nOPTIONS ({})", options.join(", ")));
+        }
+        if !self.body.is_empty() {
+            sql.push_str(&format!("\nAS {}", quote(&self.body)));
+        }
+
+        Ok(sql)
+    }
+
+    pub fn encode_to_vec(&self) -> Result<Vec<u8>> {
+        let args = self
+            .args
+            .iter()
+            .map(|(name, data_type)| {
+                Ok(ArgumentProto {
+                    name: name.clone(),
+                    data_type: Some(data_type.try_into()?),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let proto = DefinitionProto {
+            name: self.name.clone(),
+            language: self.language.clone(),
+            args,
+            return_type: Some((&self.return_type).try_into()?),
+            volatility: self
+                .volatility
+                .as_ref()
+                .map(volatility_name)
+                .unwrap_or_default()
+                .to_string(),
+            body: self.body.clone(),
+            options: self.options.iter().cloned().collect(),
+            strict: self.strict,
+        };
+
+        Ok(proto.encode_to_vec())
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let proto = DefinitionProto::decode(buf)
+            .map_err(|e| exec_datafusion_err!("function definition can't be decoded: {e}"))?;
+        let args = proto
+            .args
+            .iter()
+            .map(|a| {
+                Ok((
+                    a.name.clone(),
+                    a.data_type.as_ref().unwrap_or(&ArrowType::default()).try_into()?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let volatility = match proto.volatility.as_str() {
+            "" => None,
+            "immutable" => Some(Volatility::Immutable),
+            "stable" => Some(Volatility::Stable),
+            "volatile" => Some(Volatility::Volatile),
+            v => return exec_err!("function: {} has unknown volatility: {v}", proto.name),
+        };
+        let mut options = proto.options.into_iter().collect::<Vec<_>>();
+        options.sort();
+
+        Ok(Self {
+            name: proto.name,
+            language: proto.language,
+            args,
+            return_type: proto.return_type.as_ref().unwrap_or(&ArrowType::default()).try_into()?,
+            volatility,
+            body: proto.body,
+            options,
+            strict: proto.strict,
+        })
+    }
+}
+
+/// single quoted sql string literal
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// sql name of the data type, like `DOUBLE` for `Float64`
+fn sql_type(data_type: &DataType) -> Result<String> {
+    let cast = Expr::Cast(Cast::new(
+        Box::new(Expr::Literal(ScalarValue::Null, None)),
+        data_type.clone(),
+    ));
+    match Unparser::default().expr_to_sql(&cast)? {
+        ast::Expr::Cast { data_type, .. } => Ok(data_type.to_string()),
+        _ => exec_err!("sql type of: {data_type} can't be found"),
+    }
+}
+
+fn volatility_name(volatility: &Volatility) -> &'static str {
+    match volatility {
+        Volatility::Immutable => "immutable",
+        Volatility::Stable => "stable",
+        Volatility::Volatile => "volatile",
+    }
+}
+
+/// Catalog storing function definitions as files in a local directory,
+/// one file per function.
+#[derive(Debug, Clone)]
+pub struct DirectoryCatalog {
+    directory: PathBuf,
+}
+
+impl DirectoryCatalog {
+    /// creates catalog, creating the directory if it does not exist
+    pub fn try_new(directory: impl Into<PathBuf>) -> Result<Self> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+
+        Ok(Self { directory })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.directory.join(format!("{}.function", escape_file_name(name)))
+    }
+}
+
+/// escapes name, so any name makes a valid file name
+pub(crate) fn escape_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+            true => c.to_string(),
+            false => format!("%{:04x}", c as u32),
+        })
+        .collect()
+}
+
+impl FunctionCatalog for DirectoryCatalog {
+    fn save(&self, definition: &FunctionDefinition) -> Result<()> {
+        let path = self.path(&definition.name);
+        // written to temporary file and renamed, so readers
+        // never see partially written definition
+        let staging = path.with_extension(format!("{}.staging", std::process::id()));
+        std::fs::write(&staging, definition.encode_to_vec()?)?;
+        std::fs::rename(&staging, &path)?;
+        log::debug!(
+            "catalog::save - function: {} saved to: {}",
+            definition.name,
+            path.display()
+        );
+
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<FunctionDefinition>> {
+        let mut definitions = vec![];
+        for entry in std::fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "function") {
+                // corrupt file does not prevent loading other functions
+                match std::fs::read(&path)
+                    .map_err(Into::into)
+                    .and_then(|b| FunctionDefinition::decode(&b))
+                {
+                    Ok(definition) => definitions.push(definition),
+                    Err(e) => log::warn!("function file: {} can't be loaded, skipping it: {e}", path.display()),
+                }
+            }
+        }
+        definitions.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(definitions)
+    }
+
+    fn remove(&self, name: &str) -> Result<bool> {
+        match std::fs::remove_file(self.path(name)) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Catalog keeping function definitions in memory,
+/// definitions are lost when the process ends.
+#[derive(Debug, Default)]
+pub struct MemoryCatalog {
+    definitions: Mutex<BTreeMap<String, FunctionDefinition>>,
+}
+
+impl FunctionCatalog for MemoryCatalog {
+    fn save(&self, definition: &FunctionDefinition) -> Result<()> {
+        self.definitions
+            .lock()
+            .unwrap()
+            .insert(definition.name.clone(), definition.clone());
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<FunctionDefinition>> {
+        Ok(self.definitions.lock().unwrap().values().cloned().collect())
+    }
+
+    fn remove(&self, name: &str) -> Result<bool> {
+        Ok(self.definitions.lock().unwrap().remove(name).is_some())
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct DefinitionProto {
+    #[prost(string, tag = 1)]
+    name: String,
+    #[prost(string, tag = 2)]
+    language: String,
+    #[prost(message, repeated, tag = 3)]
+    args: Vec<ArgumentProto>,
+    #[prost(message, optional, tag = 4)]
+    return_type: Option<ArrowType>,
+    #[prost(string, tag = 5)]
+    volatility: String,
+    #[prost(string, tag = 6)]
+    body: String,
+    #[prost(map = "string, string", tag = 7)]
+    options: HashMap<String, String>,
+    #[prost(bool, tag = 8)]
+    strict: bool,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct ArgumentProto {
+    #[prost(string, tag = 1)]
+    name: String,
+    #[prost(message, optional, tag = 2)]
+    data_type: Option<ArrowType>,
+}
@@ -0,0 +1,701 @@
+use crate::archive::sha256_hex;
+use crate::catalog::{escape_file_name, DirectoryCatalog, FunctionCatalog, FunctionDefinition, MemoryCatalog};
+use crate::map_batches::PythonQueryPlanner;
+use ballista_core::utils::default_session_builder;
+use ballista_scheduler::config::SchedulerConfig;
+use ballista_scheduler::scheduler_server::SessionBuilder;
+use datafusion::arrow::array::{ArrayRef, BinaryArray, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::common::tree_node::TreeNodeRecursion;
+use datafusion::common::{exec_datafusion_err, exec_err, DFSchema, DFSchemaRef, Result};
+use datafusion::execution::context::QueryPlanner;
+use datafusion::execution::{SendableRecordBatchStream, SessionState, SessionStateBuilder, TaskContext};
+use datafusion::logical_expr::{Expr, Extension, LogicalPlan, UserDefinedLogicalNodeCore};
+use datafusion::physical_expr::EquivalenceProperties;
+use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
+use datafusion::physical_plan::memory::MemoryStream;
+use datafusion::physical_plan::{DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, PlanProperties};
+use datafusion::prelude::DataFrame;
+use prost::Message;
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Debug, Display};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// column with function name, in results of cluster registry commands
+pub static COLUMN_NAME: &str = "name";
+/// column with encoded [FunctionDefinition], null for removed functions
+pub static COLUMN_DEFINITION: &str = "definition";
+
+static RESULT_SCHEMA: LazyLock<SchemaRef> = LazyLock::new(|| {
+    Arc::new(Schema::new(vec![
+        Field::new(COLUMN_NAME, DataType::Utf8, false),
+        Field::new(COLUMN_DEFINITION, DataType::Binary, true),
+    ]))
+});
+
+/// Namespace of cluster functions. Sessions share functions
+/// created in sessions with the same default catalog and schema.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Namespace {
+    pub catalog: String,
+    pub schema: String,
+}
+
+impl Namespace {
+    pub fn new(catalog: impl Into<String>, schema: impl Into<String>) -> Self {
+        Self {
+            catalog: catalog.into(),
+            schema: schema.into(),
+        }
+    }
+
+    /// namespace of session's default catalog and schema
+    pub fn of(state: &SessionState) -> Self {
+        let options = &state.config().options().catalog;
+        Self::new(&options.default_catalog, &options.default_schema)
+    }
+}
+
+impl Display for Namespace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.catalog, self.schema)
+    }
+}
+
+/// environment variable with token of the client, required to
+/// modify functions of a namespace in [ClusterFunctionRegistry]
+pub static ENV_REGISTRY_TOKEN: &str = "BALLISTA_PYTHON_REGISTRY_TOKEN";
+
+/// number of executed commands whose results are kept, so
+/// commands planned again are not applied twice
+const EXECUTED_COMMANDS: usize = 1024;
+
+/// provides catalog storing functions of given namespace
+pub type NamespaceCatalogProvider = Arc<dyn Fn(&Namespace) -> Result<Arc<dyn FunctionCatalog>> + Send + Sync>;
+
+/// functions affected by a command, with their definitions,
+/// definition is `None` for removed functions
+pub type CommandResult = Vec<(String, Option<FunctionDefinition>)>;
+
+/// [CommandResult] with encoded definitions
+pub type EncodedCommandResult = Vec<(String, Option<Vec<u8>>)>;
+
+/// Function registry shared by all sessions of a ballista cluster,
+/// hosted by the scheduler.
+///
+/// Clients reach the registry with [ClusterFunctionNode] plans, which
+/// are executed by the scheduler when the plan is submitted on its own
+/// (see [ClusterFunctionRegistry::configure]) and return affected
+/// definitions. Functions are namespaced per default catalog and schema
+/// of the client session, each namespace stored in its own [FunctionCatalog].
+///
+/// Registry is not kept in ballista scheduler state, which holds jobs
+/// and executors only. Functions survive scheduler restarts, and are
+/// shared by schedulers, only if its catalogs do, like catalogs of
+/// [ClusterFunctionRegistry::in_directory] on a shared directory.
+///
+/// Namespace is owned by the client which modified it first, only
+/// commands with the same registry token ([ENV_REGISTRY_TOKEN])
+/// can modify its functions, so sessions loading functions of a
+/// namespace compile definitions of its owner only.
+pub struct ClusterFunctionRegistry {
+    provider: NamespaceCatalogProvider,
+    owners: NamespaceOwners,
+    catalogs: Mutex<HashMap<Namespace, Arc<dyn FunctionCatalog>>>,
+    /// results of executed commands, locked while a command is applied,
+    /// so commands are applied one at a time
+    executed: Mutex<VecDeque<(String, CommandResult)>>,
+}
+
+impl ClusterFunctionRegistry {
+    /// registry with functions stored in catalogs of the provider,
+    /// namespace owners are kept in scheduler's memory
+    pub fn new(provider: NamespaceCatalogProvider) -> Self {
+        Self::with_owners(provider, NamespaceOwners::Memory(Mutex::new(HashMap::new())))
+    }
+
+    fn with_owners(provider: NamespaceCatalogProvider, owners: NamespaceOwners) -> Self {
+        Self {
+            provider,
+            owners,
+            catalogs: Mutex::new(HashMap::new()),
+            executed: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// registry keeping functions in scheduler's memory, functions are
+    /// lost when the scheduler restarts and are not shared with other
+    /// schedulers. Meant for tests and single scheduler clusters
+    pub fn in_memory() -> Self {
+        Self::new(Arc::new(|_| Ok(Arc::new(MemoryCatalog::default()))))
+    }
+
+    /// registry storing functions and namespace owners in a directory,
+    /// with `catalog/schema` sub-directory per namespace. Functions survive
+    /// scheduler restarts, and are shared by schedulers using the same
+    /// (shared) directory
+    pub fn in_directory(directory: impl Into<PathBuf>) -> Self {
+        let directory = directory.into();
+        let catalogs = directory.clone();
+        Self::with_owners(
+            Arc::new(move |namespace| {
+                let directory = catalogs
+                    .join(escape_file_name(&namespace.catalog))
+                    .join(escape_file_name(&namespace.schema));
+                Ok(Arc::new(DirectoryCatalog::try_new(directory)?))
+            }),
+            NamespaceOwners::Directory(directory),
+        )
+    }
+
+    /// catalog storing functions of the namespace
+    pub fn catalog(&self, namespace: &Namespace) -> Result<Arc<dyn FunctionCatalog>> {
+        let mut catalogs = self.catalogs.lock().unwrap();
+        if let Some(catalog) = catalogs.get(namespace) {
+            return Ok(catalog.clone());
+        }
+        let catalog = (self.provider)(namespace)?;
+        catalogs.insert(namespace.clone(), catalog.clone());
+
+        Ok(catalog)
+    }
+
+    /// executes command of the node, returning affected functions with their
+    /// definitions, definition is `None` for removed functions. Command which
+    /// has already been executed is not applied again, its result is returned
+    pub fn execute(&self, node: &ClusterFunctionNode) -> Result<CommandResult> {
+        // lock is held until the command is applied, so namespace owner
+        // can't change between authorization and modification
+        let mut executed = self.executed.lock().unwrap();
+        if let Some((_, result)) = executed.iter().find(|(id, _)| *id == node.id) {
+            log::debug!("cluster::execute - command: {} already executed", node.id);
+            return Ok(result.clone());
+        }
+
+        let result = self.apply(&node.namespace, &node.command, &node.token)?;
+        if executed.len() >= EXECUTED_COMMANDS {
+            executed.pop_front();
+        }
+        executed.push_back((node.id.clone(), result.clone()));
+
+        Ok(result)
+    }
+
+    fn apply(&self, namespace: &Namespace, command: &ClusterFunctionCommand, token: &str) -> Result<CommandResult> {
+        let catalog = self.catalog(namespace)?;
+        log::debug!("cluster::execute - namespace: {namespace}, {command}");
+        if !matches!(command, ClusterFunctionCommand::List) {
+            self.owners.authorize(namespace, token)?;
+        }
+        match command {
+            ClusterFunctionCommand::Save(definition) => {
+                catalog.save(definition)?;
+                Ok(vec![(definition.name.clone(), Some(definition.clone()))])
+            }
+            ClusterFunctionCommand::Remove(name) => {
+                // dropped function is not decoded any more at the scheduler
+                crate::codec::evict_decoded(name);
+                match catalog.remove(name)? {
+                    true => Ok(vec![(name.clone(), None)]),
+                    false => Ok(vec![]),
+                }
+            }
+            ClusterFunctionCommand::List => {
+                Ok(catalog.load()?.into_iter().map(|d| (d.name.clone(), Some(d))).collect())
+            }
+        }
+    }
+
+    /// scheduler session builder, building sessions which
+    /// plan [ClusterFunctionNode] against this registry.
+    ///
+    /// Other plans are planned by query planner of sessions `builder`
+    /// builds, which should plan python extensions with
+    /// [crate::map_batches::python_extension_planners]. Without `builder`,
+    /// ballista default sessions are built, planned by [PythonQueryPlanner].
+    pub fn session_builder(self: &Arc<Self>, builder: Option<SessionBuilder>) -> SessionBuilder {
+        let registry = self.clone();
+        Arc::new(move |config| {
+            let (state, inner) = match &builder {
+                Some(builder) => {
+                    let state = builder(config)?;
+                    let inner = state.query_planner().clone();
+                    (state, inner)
+                }
+                None => {
+                    let inner: Arc<dyn QueryPlanner + Send + Sync> = Arc::new(PythonQueryPlanner::default());
+                    (default_session_builder(config)?, inner)
+                }
+            };
+            Ok(SessionStateBuilder::new_from_existing(state)
+                .with_query_planner(Arc::new(ClusterQueryPlanner {
+                    registry: registry.clone(),
+                    inner,
+                }))
+                .build())
+        })
+    }
+
+    /// configures scheduler to host the registry, wrapping
+    /// `override_session_builder` if it has been set.
+    /// scheduler has to use [crate::codec::PyLogicalCodec].
+    pub fn configure(self: &Arc<Self>, config: SchedulerConfig) -> SchedulerConfig {
+        let builder = self.session_builder(config.override_session_builder.clone());
+        SchedulerConfig {
+            override_session_builder: Some(builder),
+            ..config
+        }
+    }
+}
+
+/// Owners of namespaces, by digest of their registry token
+enum NamespaceOwners {
+    Memory(Mutex<HashMap<Namespace, String>>),
+    /// `catalog/schema.owner` files in the directory
+    Directory(PathBuf),
+}
+
+impl NamespaceOwners {
+    /// checks if token owns the namespace,
+    /// namespace without owner is claimed by the token
+    fn authorize(&self, namespace: &Namespace, token: &str) -> Result<()> {
+        if token.is_empty() {
+            return exec_err!("functions of namespace: {namespace} can't be modified without registry token");
+        }
+        let digest = sha256_hex(token.as_bytes());
+        let owner = match self {
+            Self::Memory(owners) => owners
+                .lock()
+                .unwrap()
+                .entry(namespace.clone())
+                .or_insert_with(|| digest.clone())
+                .clone(),
+            Self::Directory(directory) => {
+                let directory = directory.join(escape_file_name(&namespace.catalog));
+                let path = directory.join(format!("{}.owner", escape_file_name(&namespace.schema)));
+                std::fs::create_dir_all(&directory)?;
+                // linked from staging file, so the owner is claimed
+                // once and readers never see partially written file
+                let staging = path.with_extension(format!("{}.staging", std::process::id()));
+                std::fs::write(&staging, &digest)?;
+                let claimed = std::fs::hard_link(&staging, &path);
+                std::fs::remove_file(&staging)?;
+                match claimed {
+                    Ok(_) => digest.clone(),
+                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => std::fs::read_to_string(&path)?,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        };
+        match owner == digest {
+            true => Ok(()),
+            false => exec_err!("functions of namespace: {namespace} are owned by other client"),
+        }
+    }
+}
+
+impl Debug for ClusterFunctionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClusterFunctionRegistry").finish()
+    }
+}
+
+/// Command of [ClusterFunctionRegistry]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd)]
+pub enum ClusterFunctionCommand {
+    /// stores definition, replacing definition with the same name
+    Save(FunctionDefinition),
+    /// removes function with given name
+    Remove(String),
+    /// returns all functions of the namespace
+    List,
+}
+
+impl Display for ClusterFunctionCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Save(definition) => write!(f, "save: {}", definition.name),
+            Self::Remove(name) => write!(f, "remove: {name}"),
+            Self::List => write!(f, "list"),
+        }
+    }
+}
+
+/// Logical plan node executing [ClusterFunctionCommand]
+/// at the scheduler hosting [ClusterFunctionRegistry].
+///
+/// Command is executed only when the node is the whole plan,
+/// once per command id.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ClusterFunctionNode {
+    pub namespace: Namespace,
+    pub command: ClusterFunctionCommand,
+    /// unique id of the command
+    pub id: String,
+    /// registry token of the client, see [ENV_REGISTRY_TOKEN]
+    token: String,
+    schema: DFSchemaRef,
+}
+
+impl ClusterFunctionNode {
+    /// node with new command id, without registry token
+    pub fn try_new(namespace: Namespace, command: ClusterFunctionCommand) -> Result<Self> {
+        Self::try_new_with_id(namespace, command, new_command_id())
+    }
+
+    fn try_new_with_id(namespace: Namespace, command: ClusterFunctionCommand, id: String) -> Result<Self> {
+        Ok(Self {
+            namespace,
+            command,
+            id,
+            token: String::new(),
+            schema: Arc::new(DFSchema::try_from(RESULT_SCHEMA.as_ref().clone())?),
+        })
+    }
+
+    /// registry token authorizing modifications of the namespace
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = token.into();
+        self
+    }
+
+    pub fn encode_to_vec(&self) -> Result<Vec<u8>> {
+        let (command, definition) = match &self.command {
+            ClusterFunctionCommand::Save(definition) => ("save", definition.encode_to_vec()?),
+            ClusterFunctionCommand::Remove(name) => ("remove", name.as_bytes().to_vec()),
+            ClusterFunctionCommand::List => ("list", vec![]),
+        };
+        let proto = CommandProto {
+            catalog: self.namespace.catalog.clone(),
+            schema: self.namespace.schema.clone(),
+            command: command.to_string(),
+            argument: definition,
+            id: self.id.clone(),
+            token: self.token.clone(),
+        };
+
+        Ok(proto.encode_to_vec())
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let proto = CommandProto::decode(buf)
+            .map_err(|e| exec_datafusion_err!("cluster function command can't be decoded: {e}"))?;
+        let command = match proto.command.as_str() {
+            "save" => ClusterFunctionCommand::Save(FunctionDefinition::decode(&proto.argument)?),
+            "remove" => ClusterFunctionCommand::Remove(
+                String::from_utf8(proto.argument).map_err(|e| exec_datafusion_err!("invalid function name: {e}"))?,
+            ),
+            "list" => ClusterFunctionCommand::List,
+            c => return exec_err!("unknown cluster function command: {c}"),
+        };
+
+        Ok(
+            Self::try_new_with_id(Namespace::new(proto.catalog, proto.schema), command, proto.id)?
+                .with_token(proto.token),
+        )
+    }
+}
+
+impl Debug for ClusterFunctionNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // token is redacted, plans are logged and displayed
+        f.debug_struct("ClusterFunctionNode")
+            .field("namespace", &self.namespace)
+            .field("command", &self.command)
+            .field("id", &self.id)
+            .field("token", &"<redacted>")
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialOrd for ClusterFunctionNode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        (&self.namespace, &self.command, &self.id).partial_cmp(&(&other.namespace, &other.command, &other.id))
+    }
+}
+
+impl UserDefinedLogicalNodeCore for ClusterFunctionNode {
+    fn name(&self) -> &str {
+        "ClusterFunction"
+    }
+
+    fn inputs(&self) -> Vec<&LogicalPlan> {
+        vec![]
+    }
+
+    fn schema(&self) -> &DFSchemaRef {
+        &self.schema
+    }
+
+    fn expressions(&self) -> Vec<Expr> {
+        vec![]
+    }
+
+    fn fmt_for_explain(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // token is not displayed
+        write!(f, "ClusterFunction: namespace={}, {}", self.namespace, self.command)
+    }
+
+    fn with_exprs_and_inputs(&self, _exprs: Vec<Expr>, _inputs: Vec<LogicalPlan>) -> Result<Self> {
+        Ok(self.clone())
+    }
+}
+
+/// unique command id
+fn new_command_id() -> String {
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    sha256_hex(format!("{}-{}-{sequence}", std::process::id(), time.as_nanos()).as_bytes())
+}
+
+/// executes command against registry hosted by the scheduler
+/// session is connected to, with registry token authorizing
+/// modifications. Returns affected functions with their
+/// definitions, definition is `None` for removed functions
+pub async fn execute_command(
+    state: &SessionState,
+    command: ClusterFunctionCommand,
+    token: &str,
+) -> Result<CommandResult> {
+    let node = ClusterFunctionNode::try_new(Namespace::of(state), command)?.with_token(token);
+    let plan = LogicalPlan::Extension(Extension { node: Arc::new(node) });
+    let batches = DataFrame::new(state.clone(), plan).collect().await?;
+
+    let mut functions = vec![];
+    for batch in batches {
+        let names = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .ok_or_else(|| exec_datafusion_err!("function names expected"))?;
+        let definitions = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<BinaryArray>()
+            .ok_or_else(|| exec_datafusion_err!("function definitions expected"))?;
+        for (name, definition) in names.iter().zip(definitions.iter()) {
+            let definition = definition.map(FunctionDefinition::decode).transpose()?;
+            functions.push((name.unwrap_or_default().to_string(), definition));
+        }
+    }
+
+    Ok(functions)
+}
+
+/// Query planner of scheduler sessions, planning [ClusterFunctionNode]
+/// into [ClusterFunctionExec] executing its command against the registry,
+/// other plans are planned by `inner` planner
+#[derive(Debug)]
+struct ClusterQueryPlanner {
+    registry: Arc<ClusterFunctionRegistry>,
+    inner: Arc<dyn QueryPlanner + Send + Sync>,
+}
+
+#[async_trait::async_trait]
+impl QueryPlanner for ClusterQueryPlanner {
+    async fn create_physical_plan(
+        &self,
+        logical_plan: &LogicalPlan,
+        session_state: &SessionState,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        if let LogicalPlan::Extension(Extension { node }) = logical_plan {
+            if let Some(node) = node.as_any().downcast_ref::<ClusterFunctionNode>() {
+                return Ok(Arc::new(ClusterFunctionExec::pending(
+                    self.registry.clone(),
+                    node.clone(),
+                )));
+            }
+        }
+        // commands which are part of other plans, like `EXPLAIN`, are rejected
+        let mut nested = None;
+        logical_plan.apply_with_subqueries(|plan| {
+            if let LogicalPlan::Extension(Extension { node }) = plan {
+                if let Some(node) = node.as_any().downcast_ref::<ClusterFunctionNode>() {
+                    nested = Some(node.command.clone());
+                    return Ok(TreeNodeRecursion::Stop);
+                }
+            }
+            Ok(TreeNodeRecursion::Continue)
+        })?;
+        if let Some(command) = nested {
+            return exec_err!(
+                "cluster function command: {command} can't be part of other plan, it has to be executed on its own"
+            );
+        }
+        self.inner.create_physical_plan(logical_plan, session_state).await
+    }
+}
+
+/// Execution plan returning result of [ClusterFunctionCommand],
+/// functions with encoded definitions.
+///
+/// Plan planned by the scheduler applies its command to the registry when
+/// it is executed, or encoded to be sent to executors, as executors can't
+/// reach the registry. Planning alone, like `EXPLAIN`, has no side effects.
+/// Executors decode the result and return it.
+#[derive(Debug, Clone)]
+pub struct ClusterFunctionExec {
+    /// command to apply, unless the result is known
+    command: Option<(Arc<ClusterFunctionRegistry>, ClusterFunctionNode)>,
+    functions: Arc<OnceLock<EncodedCommandResult>>,
+    properties: PlanProperties,
+}
+
+impl ClusterFunctionExec {
+    pub fn new(functions: EncodedCommandResult) -> Self {
+        Self::with_command(None, Arc::new(OnceLock::from(functions)))
+    }
+
+    /// plan applying command of the node when executed
+    fn pending(registry: Arc<ClusterFunctionRegistry>, node: ClusterFunctionNode) -> Self {
+        Self::with_command(Some((registry, node)), Arc::new(OnceLock::new()))
+    }
+
+    fn with_command(
+        command: Option<(Arc<ClusterFunctionRegistry>, ClusterFunctionNode)>,
+        functions: Arc<OnceLock<EncodedCommandResult>>,
+    ) -> Self {
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(RESULT_SCHEMA.clone()),
+            Partitioning::UnknownPartitioning(1),
+            EmissionType::Incremental,
+            Boundedness::Bounded,
+        );
+        Self {
+            command,
+            functions,
+            properties,
+        }
+    }
+
+    /// result of the command, applying it if it has not been applied yet
+    fn functions(&self) -> Result<&EncodedCommandResult> {
+        if let Some(functions) = self.functions.get() {
+            return Ok(functions);
+        }
+        let Some((registry, node)) = &self.command else {
+            return exec_err!("cluster function command result is missing");
+        };
+        // registry applies the command once per id, so concurrent
+        // executions get the same result
+        let functions = registry
+            .execute(node)?
+            .into_iter()
+            .map(|(name, definition)| Ok((name, definition.map(|d| d.encode_to_vec()).transpose()?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(self.functions.get_or_init(|| functions))
+    }
+
+    /// encodes result of the command, applying it if it has not been applied yet
+    pub fn encode_to_vec(&self) -> Result<Vec<u8>> {
+        let proto = ResultProto {
+            functions: self
+                .functions()?
+                .iter()
+                .map(|(name, definition)| FunctionProto {
+                    name: name.clone(),
+                    definition: definition.clone(),
+                })
+                .collect(),
+        };
+
+        Ok(proto.encode_to_vec())
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let proto = ResultProto::decode(buf)
+            .map_err(|e| exec_datafusion_err!("cluster function result can't be decoded: {e}"))?;
+
+        Ok(Self::new(
+            proto.functions.into_iter().map(|f| (f.name, f.definition)).collect(),
+        ))
+    }
+}
+
+impl DisplayAs for ClusterFunctionExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (self.functions.get(), &self.command) {
+            (Some(functions), _) => write!(f, "ClusterFunctionExec: functions={}", functions.len()),
+            (None, Some((_, node))) => write!(f, "ClusterFunctionExec: namespace={}, {}", node.namespace, node.command),
+            (None, None) => write!(f, "ClusterFunctionExec"),
+        }
+    }
+}
+
+impl ExecutionPlan for ClusterFunctionExec {
+    fn name(&self) -> &str {
+        "ClusterFunctionExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(self: Arc<Self>, _children: Vec<Arc<dyn ExecutionPlan>>) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(self)
+    }
+
+    fn execute(&self, partition: usize, _context: Arc<TaskContext>) -> Result<SendableRecordBatchStream> {
+        if partition != 0 {
+            return exec_err!("cluster function result has single partition, partition: {partition} requested");
+        }
+        let functions = self.functions()?;
+        let names = StringArray::from_iter_values(functions.iter().map(|(name, _)| name));
+        let definitions = BinaryArray::from_iter(functions.iter().map(|(_, definition)| definition.as_ref()));
+        let batch = RecordBatch::try_new(
+            RESULT_SCHEMA.clone(),
+            vec![Arc::new(names) as ArrayRef, Arc::new(definitions) as ArrayRef],
+        )?;
+
+        Ok(Box::pin(MemoryStream::try_new(
+            vec![batch],
+            RESULT_SCHEMA.clone(),
+            None,
+        )?))
+    }
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct CommandProto {
+    #[prost(string, tag = 1)]
+    catalog: String,
+    #[prost(string, tag = 2)]
+    schema: String,
+    #[prost(string, tag = 3)]
+    command: String,
+    /// encoded definition for `save`, function name for `remove`
+    #[prost(bytes, tag = 4)]
+    argument: Vec<u8>,
+    #[prost(string, tag = 5)]
+    id: String,
+    #[prost(string, tag = 6)]
+    token: String,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct ResultProto {
+    #[prost(message, repeated, tag = 1)]
+    functions: Vec<FunctionProto>,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct FunctionProto {
+    #[prost(string, tag = 1)]
+    name: String,
+    #[prost(bytes, optional, tag = 2)]
+    definition: Option<Vec<u8>>,
+}
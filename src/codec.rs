@@ -1,30 +1,177 @@
-use crate::pickle::CloudPickle;
-use crate::udf::PythonUDF;
+use crate::archive::{default_cache_dir, sha256_hex, PythonEnvArchive};
+use crate::artifacts::PythonArtifactStore;
+use crate::bundle::PythonBundle;
+use crate::cluster::{ClusterFunctionExec, ClusterFunctionNode};
+use crate::compat;
+use crate::coroutine::AsyncOptions;
+use crate::grouped_map::{ApplyPythonExec, ApplyPythonNode};
+use crate::map_batches::{MapBatchesExec, MapBatchesFunction, MapBatchesNode};
+use crate::pickle::{serializer_for_format, PickleSerializer, PySerializer, FORMAT_CLOUDPICKLE};
+use crate::registry::{FunctionReference, PyFunctionRegistry};
+use crate::requirements::{check_requirements, PythonRequirement};
+use crate::signing::UdfSigner;
+use crate::stateful::PythonInstance;
+use crate::udf::{CallMode, PythonSource, PythonUDF, SOURCE_MODULE_PREFIX};
+use crate::worker::{WorkerFunction, WorkerOptions};
 use ballista_core::serde::{BallistaLogicalExtensionCodec, BallistaPhysicalExtensionCodec};
-use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::datatypes::{DataType, Schema};
+use datafusion::common::exec_err;
 use datafusion::error::DataFusionError;
 use datafusion::logical_expr::{ScalarUDF, Volatility};
+use datafusion_proto::logical_plan::from_proto::parse_exprs;
+use datafusion_proto::logical_plan::to_proto::serialize_exprs;
 use datafusion_proto::logical_plan::LogicalExtensionCodec;
+use datafusion_proto::physical_plan::from_proto::parse_physical_expr;
+use datafusion_proto::physical_plan::to_proto::serialize_physical_expr;
 use datafusion_proto::physical_plan::PhysicalExtensionCodec;
 use datafusion_proto::protobuf::FromProtoError;
 use prost::Message;
-use pyo3::{PyResult, Python};
-use serde::UdfProto;
+use pyo3::types::PyAnyMethods;
+use pyo3::{PyObject, PyResult, Python};
+use serde::{
+    ApplyPythonExecProto, ApplyPythonNodeProto, AsyncProto, BundleProto, EnvironmentProto, ExtensionProto,
+    InstanceProto, MapBatchesProto, SignedUdfProto, SourceProto, UdfProto,
+};
+use std::collections::HashMap;
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// builders of python codec options, shared by [PyLogicalCodec]
+/// and [PyPhysicalCodec], which keep the options in `codec` field
+macro_rules! py_codec_builders {
+    () => {
+        /// signs encoded functions and rejects decoded functions
+        /// which are not signed with the same key
+        pub fn with_signer(mut self, signer: UdfSigner) -> Self {
+            self.codec.signer = Some(signer);
+            self
+        }
+
+        /// serializer used to pickle functions, functions pickled
+        /// using other formats are unpickled using built-in serializers.
+        pub fn with_serializer(mut self, serializer: Arc<dyn PySerializer>) -> Self {
+            self.codec.serializer = serializer;
+            self
+        }
+
+        /// registry used to resolve functions shipped by reference
+        pub fn with_function_registry(mut self, registry: PyFunctionRegistry) -> Self {
+            self.codec.function_registry = Some(registry);
+            self
+        }
+
+        /// allows functions shipped by value (pickled or as source),
+        /// if disabled only functions shipped by reference are accepted
+        /// and nothing gets unpickled.
+        pub fn with_by_value(mut self, allow_by_value: bool) -> Self {
+            self.codec.allow_by_value = allow_by_value;
+            self
+        }
+
+        /// python environment shipped with functions shipped by value,
+        /// these functions are called in worker process of the environment
+        /// at executors, see [crate::worker::WorkerFunction]
+        pub fn with_environment(mut self, environment: PythonEnvArchive) -> Self {
+            self.codec.environment = Some(environment);
+            self
+        }
+
+        /// directory where shipped python environments are unpacked,
+        /// defaults to [crate::archive::default_cache_dir]
+        pub fn with_env_cache_dir(mut self, env_cache_dir: impl Into<PathBuf>) -> Self {
+            self.codec.env_cache_dir = env_cache_dir.into();
+            self
+        }
+
+        /// store environment archives are published to, once, and fetched
+        /// from, defaults to [PythonArtifactStore::from_env]
+        pub fn with_artifact_store(mut self, artifact_store: PythonArtifactStore) -> Self {
+            self.codec.artifact_store = artifact_store;
+            self
+        }
+
+        /// command started as worker process of shipped environments,
+        /// configured from [crate::worker::ENV_WORKER_COMMAND] by default
+        pub fn with_worker_command(mut self, worker_command: impl Into<PathBuf>) -> Self {
+            self.codec.worker_command = Some(worker_command.into());
+            self
+        }
+
+        /// functions with known source are shipped as source by default,
+        /// if enabled they are pickled as well, and source is used only
+        /// if function can't be unpickled.
+        pub fn with_source_fallback(mut self, source_fallback: bool) -> Self {
+            self.codec.source_fallback = source_fallback;
+            self
+        }
+
+        /// checks requirements of decoded functions, enabled by default.
+        /// should be disabled at schedulers, which place tasks on
+        /// executors satisfying the requirements instead.
+        pub fn with_requirements_check(mut self, check_requirements: bool) -> Self {
+            self.codec.check_requirements = check_requirements;
+            self
+        }
+
+        /// caches decoded functions by name, enabled by default.
+        /// cached function is decoded again if function with
+        /// the same name but different content is decoded.
+        pub fn with_decode_cache(mut self, cache_decoded: bool) -> Self {
+            self.codec.cache_decoded = cache_decoded;
+            self
+        }
+
+        /// maximum number of cached decoded functions, least recently
+        /// used ones are evicted, defaults to [DEFAULT_DECODE_CACHE_SIZE]
+        pub fn with_decode_cache_size(self, size: usize) -> Self {
+            self.codec.decoded.lock().unwrap().size = size;
+            self
+        }
+
+        /// evicts cached functions not used for given time, like dropped ones,
+        /// defaults to [DEFAULT_DECODE_CACHE_TTL]
+        pub fn with_decode_cache_ttl(self, ttl: Duration) -> Self {
+            self.codec.decoded.lock().unwrap().ttl = ttl;
+            self
+        }
+
+        /// encodes functions in pickled tuple format, see
+        /// [compat::decode_pickled_tuple_udf]. Only pickled function and
+        /// its types are shipped, functions with other state, or encoded
+        /// by codecs with a signer, are rejected. Functions in pickled
+        /// tuple format are decoded regardless of this option.
+        pub fn with_pickled_tuple_format(mut self, pickled_tuple_format: bool) -> Self {
+            self.codec.pickled_tuple_format = pickled_tuple_format;
+            self
+        }
+
+        /// removes decoded function from the cache,
+        /// returns `false` if it has not been cached
+        pub fn invalidate(&self, name: &str) -> bool {
+            self.codec.invalidate(name)
+        }
+    };
+}
 
 pub struct PyLogicalCodec {
     inner: BallistaLogicalExtensionCodec,
-    cloud_pickle: CloudPickle,
+    codec: PyCodec,
 }
 
 impl PyLogicalCodec {
+    /// creates new codec, signing key is configured from
+    /// environment if [crate::signing::ENV_SIGNING_KEY] is set.
     pub fn try_new(py: Python<'_>) -> PyResult<Self> {
         Ok(Self {
             inner: BallistaLogicalExtensionCodec::default(),
-            cloud_pickle: CloudPickle::try_new(py)?,
+            codec: PyCodec::try_new(py)?,
         })
     }
+
+    py_codec_builders!();
 }
 
 impl Default for PyLogicalCodec {
@@ -46,7 +193,36 @@ impl LogicalExtensionCodec for PyLogicalCodec {
         inputs: &[datafusion::logical_expr::LogicalPlan],
         ctx: &datafusion::prelude::SessionContext,
     ) -> datafusion::error::Result<datafusion::logical_expr::Extension> {
-        self.inner.try_decode(buf, inputs, ctx)
+        match decode_extension(buf)? {
+            Some(ExtensionProto { kind, payload }) if kind == EXTENSION_CLUSTER_FUNCTION => {
+                Ok(datafusion::logical_expr::Extension {
+                    node: Arc::new(ClusterFunctionNode::decode(&payload)?),
+                })
+            }
+            Some(ExtensionProto { kind, payload }) if kind == EXTENSION_MAP_BATCHES => {
+                let [input] = inputs else {
+                    return exec_err!("map batches node expects single input");
+                };
+                let function = self.codec.try_decode_map_batches(&payload)?;
+                Ok(datafusion::logical_expr::Extension {
+                    node: Arc::new(MapBatchesNode::try_new(input.clone(), function)?),
+                })
+            }
+            Some(ExtensionProto { kind, payload }) if kind == EXTENSION_APPLY_PYTHON => {
+                let [input] = inputs else {
+                    return exec_err!("apply python node expects single input");
+                };
+                let proto = ApplyPythonNodeProto::decode(payload.as_slice())
+                    .map_err(|e| DataFusionError::Execution(e.to_string()))?;
+                let function = self.codec.try_decode_map_batches(&proto.function)?;
+                let keys = parse_exprs(&proto.keys, ctx, self)?;
+                Ok(datafusion::logical_expr::Extension {
+                    node: Arc::new(ApplyPythonNode::try_new(input.clone(), keys, function)?),
+                })
+            }
+            Some(ExtensionProto { kind, .. }) => exec_err!("unknown logical extension: {kind}"),
+            None => self.inner.try_decode(buf, inputs, ctx),
+        }
     }
 
     fn try_encode(
@@ -54,7 +230,26 @@ impl LogicalExtensionCodec for PyLogicalCodec {
         node: &datafusion::logical_expr::Extension,
         buf: &mut Vec<u8>,
     ) -> datafusion::error::Result<()> {
-        self.inner.try_encode(node, buf)
+        if let Some(node) = node.node.as_any().downcast_ref::<ClusterFunctionNode>() {
+            return encode_extension(EXTENSION_CLUSTER_FUNCTION, node.encode_to_vec()?, buf);
+        }
+        if let Some(node) = node.node.as_any().downcast_ref::<MapBatchesNode>() {
+            return encode_extension(
+                EXTENSION_MAP_BATCHES,
+                self.codec.try_encode_map_batches(&node.function)?,
+                buf,
+            );
+        }
+        match node.node.as_any().downcast_ref::<ApplyPythonNode>() {
+            Some(node) => {
+                let proto = ApplyPythonNodeProto {
+                    function: self.codec.try_encode_map_batches(&node.function)?,
+                    keys: serialize_exprs(&node.keys, self)?,
+                };
+                encode_extension(EXTENSION_APPLY_PYTHON, proto.encode_to_vec(), buf)
+            }
+            None => self.inner.try_encode(node, buf),
+        }
     }
 
     fn try_decode_table_provider(
@@ -95,7 +290,7 @@ impl LogicalExtensionCodec for PyLogicalCodec {
     fn try_decode_udf(&self, name: &str, buf: &[u8]) -> datafusion::common::Result<Arc<ScalarUDF>> {
         log::debug!("logical::try_decode_udf - for function: {name} started ... ");
         if !buf.is_empty() {
-            let function = PyCodec::try_decode_udf(&self.cloud_pickle, name, buf)?;
+            let function = self.codec.try_decode_udf(name, buf)?;
             log::debug!("logical::try_decode_udf ... DONE");
 
             Ok(function)
@@ -108,7 +303,7 @@ impl LogicalExtensionCodec for PyLogicalCodec {
         log::debug!("logical::try_encode_udf - for function: {} started ...", node.name());
         match node.inner().as_any().downcast_ref::<PythonUDF>() {
             Some(udf) => {
-                PyCodec::try_encode_udf(&self.cloud_pickle, udf, &node.signature().volatility, buf)?;
+                self.codec.try_encode_udf(udf, &node.signature().volatility, buf)?;
                 log::debug!("logical::try_encode_udf ... DONE");
                 Ok(())
             }
@@ -151,7 +346,7 @@ impl LogicalExtensionCodec for PyLogicalCodec {
 
 pub struct PyPhysicalCodec {
     inner: BallistaPhysicalExtensionCodec,
-    cloudpickle: CloudPickle,
+    codec: PyCodec,
 }
 
 impl Default for PyPhysicalCodec {
@@ -168,12 +363,28 @@ impl Debug for PyPhysicalCodec {
 }
 
 impl PyPhysicalCodec {
+    /// creates new codec, signing key is configured from
+    /// environment if [crate::signing::ENV_SIGNING_KEY] is set.
     pub fn try_new(py: Python<'_>) -> PyResult<Self> {
         Ok(Self {
             inner: BallistaPhysicalExtensionCodec::default(),
-            cloudpickle: CloudPickle::try_new(py)?,
+            codec: PyCodec::try_new(py)?,
         })
     }
+
+    /// codec of worker process, functions are decoded with environment
+    /// already on module path. signatures have been verified by the
+    /// executor, and the worker caches functions itself
+    pub(crate) fn for_worker(py: Python<'_>) -> PyResult<Self> {
+        let mut codec = Self::try_new(py)?;
+        codec.codec.signer = None;
+        codec.codec.in_worker = true;
+        codec.codec.cache_decoded = false;
+
+        Ok(codec)
+    }
+
+    py_codec_builders!();
 }
 
 impl PhysicalExtensionCodec for PyPhysicalCodec {
@@ -183,7 +394,35 @@ impl PhysicalExtensionCodec for PyPhysicalCodec {
         inputs: &[std::sync::Arc<dyn datafusion::physical_plan::ExecutionPlan>],
         registry: &dyn datafusion::execution::FunctionRegistry,
     ) -> datafusion::error::Result<std::sync::Arc<dyn datafusion::physical_plan::ExecutionPlan>> {
-        self.inner.try_decode(buf, inputs, registry)
+        match decode_extension(buf)? {
+            Some(ExtensionProto { kind, payload }) if kind == EXTENSION_CLUSTER_FUNCTION => {
+                Ok(Arc::new(ClusterFunctionExec::decode(&payload)?))
+            }
+            Some(ExtensionProto { kind, payload }) if kind == EXTENSION_MAP_BATCHES => {
+                let [input] = inputs else {
+                    return exec_err!("map batches plan expects single input");
+                };
+                let function = self.codec.try_decode_map_batches(&payload)?;
+                Ok(Arc::new(MapBatchesExec::new(input.clone(), function)))
+            }
+            Some(ExtensionProto { kind, payload }) if kind == EXTENSION_APPLY_PYTHON => {
+                let [input] = inputs else {
+                    return exec_err!("apply python plan expects single input");
+                };
+                let proto = ApplyPythonExecProto::decode(payload.as_slice())
+                    .map_err(|e| DataFusionError::Execution(e.to_string()))?;
+                let function = self.codec.try_decode_map_batches(&proto.function)?;
+                let schema = input.schema();
+                let keys = proto
+                    .keys
+                    .iter()
+                    .map(|key| parse_physical_expr(key, registry, &schema, self))
+                    .collect::<datafusion::common::Result<Vec<_>>>()?;
+                Ok(Arc::new(ApplyPythonExec::try_new(input.clone(), keys, function)?))
+            }
+            Some(ExtensionProto { kind, .. }) => exec_err!("unknown physical extension: {kind}"),
+            None => self.inner.try_decode(buf, inputs, registry),
+        }
     }
 
     fn try_encode(
@@ -191,13 +430,36 @@ impl PhysicalExtensionCodec for PyPhysicalCodec {
         node: std::sync::Arc<dyn datafusion::physical_plan::ExecutionPlan>,
         buf: &mut Vec<u8>,
     ) -> datafusion::error::Result<()> {
-        self.inner.try_encode(node, buf)
+        if let Some(exec) = node.as_any().downcast_ref::<ClusterFunctionExec>() {
+            return encode_extension(EXTENSION_CLUSTER_FUNCTION, exec.encode_to_vec()?, buf);
+        }
+        if let Some(exec) = node.as_any().downcast_ref::<MapBatchesExec>() {
+            return encode_extension(
+                EXTENSION_MAP_BATCHES,
+                self.codec.try_encode_map_batches(exec.function())?,
+                buf,
+            );
+        }
+        match node.as_any().downcast_ref::<ApplyPythonExec>() {
+            Some(exec) => {
+                let proto = ApplyPythonExecProto {
+                    function: self.codec.try_encode_map_batches(exec.function())?,
+                    keys: exec
+                        .keys()
+                        .iter()
+                        .map(|key| serialize_physical_expr(key, self))
+                        .collect::<datafusion::common::Result<Vec<_>>>()?,
+                };
+                encode_extension(EXTENSION_APPLY_PYTHON, proto.encode_to_vec(), buf)
+            }
+            None => self.inner.try_encode(node, buf),
+        }
     }
 
     fn try_decode_udf(&self, name: &str, buf: &[u8]) -> datafusion::common::Result<Arc<ScalarUDF>> {
         log::debug!("physical::try_decode_udf - for function: {name} started ... ");
         if !buf.is_empty() {
-            let function = PyCodec::try_decode_udf(&self.cloudpickle, name, buf)?;
+            let function = self.codec.try_decode_udf(name, buf)?;
             log::debug!("physical::try_decode_udf ... DONE");
 
             Ok(function)
@@ -210,7 +472,7 @@ impl PhysicalExtensionCodec for PyPhysicalCodec {
         log::debug!("physical::try_encode_udf - for function: {} started ...", node.name());
         match node.inner().as_any().downcast_ref::<PythonUDF>() {
             Some(udf) => {
-                PyCodec::try_encode_udf(&self.cloudpickle, udf, &node.signature().volatility, buf)?;
+                self.codec.try_encode_udf(udf, &node.signature().volatility, buf)?;
                 log::debug!("physical::try_encode_udf ... DONE");
                 Ok(())
             }
@@ -219,22 +481,353 @@ impl PhysicalExtensionCodec for PyPhysicalCodec {
     }
 }
 
-struct PyCodec {}
+/// function has been compiled from source, in a module which
+/// can be imported only in the process it has been compiled in
+fn is_compiled_from_source(py: Python<'_>, func: &PyObject) -> bool {
+    func.bind(py)
+        .getattr("__module__")
+        .and_then(|module| module.extract::<String>())
+        .is_ok_and(|module| module.starts_with(SOURCE_MODULE_PREFIX))
+}
+
+/// prefix of plan extensions encoded by this crate,
+/// distinguishing them from ballista extensions
+const EXTENSION_MAGIC: &[u8] = b"BALLISTA_PYTHON:";
+
+const EXTENSION_CLUSTER_FUNCTION: &str = "cluster_function";
+
+const EXTENSION_MAP_BATCHES: &str = "map_batches";
+
+const EXTENSION_APPLY_PYTHON: &str = "apply_python";
+
+fn encode_extension(kind: &str, payload: Vec<u8>, buf: &mut Vec<u8>) -> datafusion::common::Result<()> {
+    let proto = ExtensionProto {
+        kind: kind.to_string(),
+        payload,
+    };
+    buf.extend_from_slice(EXTENSION_MAGIC);
+    buf.append(&mut proto.encode_to_vec());
+    Ok(())
+}
+
+/// decodes extension encoded by this crate,
+/// `None` if the extension is encoded by ballista
+fn decode_extension(buf: &[u8]) -> datafusion::common::Result<Option<ExtensionProto>> {
+    match buf.strip_prefix(EXTENSION_MAGIC) {
+        Some(buf) => Ok(Some(
+            ExtensionProto::decode(buf).map_err(|e| DataFusionError::Execution(e.to_string()))?,
+        )),
+        None => Ok(None),
+    }
+}
+
+struct PyCodec {
+    serializer: Arc<dyn PySerializer>,
+    signer: Option<UdfSigner>,
+    source_fallback: bool,
+    function_registry: Option<PyFunctionRegistry>,
+    allow_by_value: bool,
+    environment: Option<PythonEnvArchive>,
+    env_cache_dir: PathBuf,
+    artifact_store: PythonArtifactStore,
+    worker_command: Option<PathBuf>,
+    /// codec of worker process, environment of decoded
+    /// functions is already on module path
+    in_worker: bool,
+    check_requirements: bool,
+    cache_decoded: bool,
+    decoded: Arc<Mutex<DecodeCache>>,
+    pickled_tuple_format: bool,
+}
+
+/// default maximum number of cached decoded functions, per codec
+pub const DEFAULT_DECODE_CACHE_SIZE: usize = 1024;
+
+/// default time after which unused decoded functions are evicted
+pub const DEFAULT_DECODE_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// decode caches of codecs in this process, so dropped
+/// functions can be evicted from all of them
+static DECODE_CACHES: Mutex<Vec<Weak<Mutex<DecodeCache>>>> = Mutex::new(Vec::new());
+
+/// removes dropped function from decode caches of all codecs
+/// in this process, returns `false` if it has not been cached.
+///
+/// called when function is dropped by [crate::sql::PythonSessionExt::python_sql],
+/// or from cluster registry at the scheduler. Executors evict dropped
+/// functions once they are not used for decode cache ttl.
+pub fn evict_decoded(name: &str) -> bool {
+    let mut caches = DECODE_CACHES.lock().unwrap();
+    caches.retain(|cache| cache.strong_count() > 0);
+    let mut evicted = false;
+    for cache in caches.iter().filter_map(|cache| cache.upgrade()) {
+        let mut cache = cache.lock().unwrap();
+        evicted |= cache.remove(name);
+        // semaphores of all versions of the function are dropped below
+        cache.take_evicted();
+    }
+    drop(caches);
+    crate::coroutine::evict_semaphores(name, None);
+    evicted
+}
+
+/// drops semaphores of evicted decoded functions
+fn evict_semaphores(evicted: Vec<(String, String)>) {
+    for (name, digest) in evicted {
+        crate::coroutine::evict_semaphores(&name, Some(&digest));
+    }
+}
+
+/// Decoded functions by name, with digest of their encoded form.
+/// Least recently used functions are evicted when cache is full,
+/// and functions not used for `ttl` are evicted on access.
+#[derive(Debug)]
+struct DecodeCache {
+    size: usize,
+    ttl: Duration,
+    functions: HashMap<String, CachedFunction>,
+    /// evicted functions, with their digests, whose semaphores are
+    /// dropped once the cache is unlocked, see [DecodeCache::take_evicted]
+    evicted: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+struct CachedFunction {
+    digest: String,
+    function: Arc<ScalarUDF>,
+    used: Instant,
+}
+
+impl DecodeCache {
+    fn new() -> Self {
+        Self {
+            size: DEFAULT_DECODE_CACHE_SIZE,
+            ttl: DEFAULT_DECODE_CACHE_TTL,
+            functions: HashMap::new(),
+            evicted: vec![],
+        }
+    }
+
+    /// cached function with given digest, `None` if not cached or replaced
+    fn get(&mut self, name: &str, digest: &str) -> Option<Arc<ScalarUDF>> {
+        self.evict_expired();
+        let cached = self.functions.get_mut(name)?;
+        if cached.digest != digest {
+            log::debug!("pycodec::try_decode_udf - function: {name} has been replaced, decoding it again");
+            return None;
+        }
+        cached.used = Instant::now();
+        Some(cached.function.clone())
+    }
+
+    fn insert(&mut self, name: &str, digest: String, function: Arc<ScalarUDF>) {
+        self.remove(name);
+        while !self.functions.is_empty() && self.functions.len() >= self.size {
+            let least_recently_used = self
+                .functions
+                .iter()
+                .min_by_key(|(_, cached)| cached.used)
+                .map(|(name, _)| name.clone())
+                .unwrap_or_default();
+            log::debug!("pycodec::try_decode_udf - function: {least_recently_used} evicted from full cache");
+            self.remove(&least_recently_used);
+        }
+        if self.size > 0 {
+            let used = Instant::now();
+            let cached = CachedFunction { digest, function, used };
+            self.functions.insert(name.to_string(), cached);
+        }
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        match self.functions.remove(name) {
+            Some(cached) => {
+                self.evicted.push((name.to_string(), cached.digest));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn evict_expired(&mut self) {
+        let ttl = self.ttl;
+        let evicted = &mut self.evicted;
+        self.functions.retain(|name, cached| {
+            let expired = cached.used.elapsed() > ttl;
+            if expired {
+                log::debug!("pycodec::try_decode_udf - function: {name} not used for {ttl:?}, evicted");
+                evicted.push((name.clone(), cached.digest.clone()));
+            }
+            !expired
+        });
+    }
+
+    /// evicted functions, with their digests, since last call.
+    /// Their semaphores are dropped without the cache locked,
+    /// as it needs the GIL
+    fn take_evicted(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.evicted)
+    }
+}
 
 impl PyCodec {
-    fn try_decode_udf(
-        cloud_pickle: &CloudPickle,
-        name: &str,
-        buf: &[u8],
-    ) -> datafusion::common::Result<Arc<ScalarUDF>> {
-        let udf_proto: UdfProto = UdfProto::decode(buf).map_err(|e| DataFusionError::Execution(e.to_string()))?;
+    fn try_new(py: Python<'_>) -> PyResult<Self> {
+        Ok(Self {
+            serializer: Arc::new(PickleSerializer::cloudpickle(py)?),
+            signer: UdfSigner::from_env(),
+            source_fallback: false,
+            function_registry: None,
+            allow_by_value: true,
+            environment: None,
+            env_cache_dir: default_cache_dir(),
+            artifact_store: PythonArtifactStore::from_env(),
+            worker_command: None,
+            in_worker: false,
+            check_requirements: true,
+            cache_decoded: true,
+            decoded: Self::decode_cache(),
+            pickled_tuple_format: false,
+        })
+    }
+
+    /// new decode cache, registered for eviction of dropped functions
+    fn decode_cache() -> Arc<Mutex<DecodeCache>> {
+        let cache = Arc::new(Mutex::new(DecodeCache::new()));
+        let mut caches = DECODE_CACHES.lock().unwrap();
+        caches.retain(|cache| cache.strong_count() > 0);
+        caches.push(Arc::downgrade(&cache));
+        cache
+    }
+
+    fn worker_options(&self) -> WorkerOptions {
+        WorkerOptions {
+            store: self.artifact_store.clone(),
+            cache_dir: self.env_cache_dir.clone(),
+            command: self.worker_command.clone(),
+        }
+    }
+
+    fn invalidate(&self, name: &str) -> bool {
+        let (removed, evicted) = {
+            let mut decoded = self.decoded.lock().unwrap();
+            (decoded.remove(name), decoded.take_evicted())
+        };
+        evict_semaphores(evicted);
+        removed
+    }
+
+    fn try_decode_udf(&self, name: &str, buf: &[u8]) -> datafusion::common::Result<Arc<ScalarUDF>> {
+        let digest = sha256_hex(buf);
+        if !self.cache_decoded {
+            return self.decode_udf(name, buf, digest);
+        }
+        // function is cached under its name, replaced function
+        // (CREATE OR REPLACE) has different digest and replaces
+        // stale function in the cache. cached function has been
+        // verified, as its encoded form is the same
+        let (cached, evicted) = {
+            let mut decoded = self.decoded.lock().unwrap();
+            (decoded.get(name, &digest), decoded.take_evicted())
+        };
+        evict_semaphores(evicted);
+        if let Some(function) = cached {
+            log::debug!("pycodec::try_decode_udf - function: {name} found in cache");
+            return Ok(function);
+        }
+        let function = self.decode_udf(name, buf, digest.clone())?;
+        let evicted = {
+            let mut decoded = self.decoded.lock().unwrap();
+            decoded.insert(name, digest, function.clone());
+            decoded.take_evicted()
+        };
+        evict_semaphores(evicted);
+
+        Ok(function)
+    }
+
+    fn decode_udf(&self, name: &str, buf: &[u8], digest: String) -> datafusion::common::Result<Arc<ScalarUDF>> {
+        if compat::is_pickled_tuple_payload(buf) {
+            return self.decode_pickled_tuple_udf(name, buf);
+        }
+        let SignedUdfProto { payload, signature } =
+            SignedUdfProto::decode(buf).map_err(|e| DataFusionError::Execution(e.to_string()))?;
+
+        // signature has to be verified, over the received payload
+        // bytes, before anything gets decoded or unpickled
+        match &self.signer {
+            Some(signer) => signer.verify(name, &payload, &signature)?,
+            None if !signature.is_empty() => {
+                log::warn!(
+                    "pycodec::try_decode_udf - function: {name} is signed but signature verification is not configured"
+                )
+            }
+            None => (),
+        }
+        let udf_proto = UdfProto::decode(payload.as_slice()).map_err(|e| DataFusionError::Execution(e.to_string()))?;
 
-        let func = Python::with_gil(|py| {
-            cloud_pickle
-                .unpickle(py, &udf_proto.blob)
-                .map_err(|e| DataFusionError::Execution(e.to_string()))
+        let source = udf_proto.source.as_ref().map(PythonSource::from);
+        let reference = match udf_proto.reference.is_empty() {
+            true => None,
+            false => Some(FunctionReference::from_str(&udf_proto.reference)?),
+        };
+        let environment = udf_proto
+            .environment
+            .as_ref()
+            .map(PythonEnvArchive::try_from)
+            .transpose()?;
+        let bundle = udf_proto.bundle.as_ref().map(PythonBundle::from);
+        let requirements = udf_proto
+            .requirements
+            .iter()
+            .map(|r| PythonRequirement::from_str(r))
+            .collect::<datafusion::common::Result<Vec<_>>>()?;
+        let checked_requirements = match self.check_requirements {
+            true => requirements.as_slice(),
+            false => &[],
+        };
+        let mode = match udf_proto.mode.is_empty() {
+            true => CallMode::default(),
+            false => CallMode::from_str(&udf_proto.mode)?,
+        };
+        let instance = udf_proto.instance.as_ref().map(PythonInstance::try_from).transpose()?;
+        // constructor arguments are unpickled, even if the class is referenced
+        if !self.allow_by_value && instance.as_ref().is_some_and(|i| !i.arguments().is_empty()) {
+            return exec_err!(
+                "python function: {name} has pickled constructor arguments, functions shipped by value are rejected"
+            );
+        }
+        let mut worker = None;
+        let func = Python::with_gil(|py| match &reference {
+            Some(reference) => {
+                check_requirements(py, name, checked_requirements)?;
+                self.resolve_function(py, reference)
+            }
+            None if !self.allow_by_value => {
+                exec_err!(
+                    "python function: {name} is not shipped by reference, functions shipped by value are rejected"
+                )
+            }
+            None => match &environment {
+                // function is loaded, and its requirements checked, by
+                // worker process of the environment, once it is called
+                Some(environment) if !self.in_worker => {
+                    worker = Some(WorkerFunction::new(
+                        payload.clone(),
+                        environment.clone(),
+                        self.worker_options(),
+                    ));
+                    log::debug!("pycodec::try_decode_udf - function: {name} is called by environment worker");
+                    Ok(py.None())
+                }
+                _ => {
+                    check_requirements(py, name, checked_requirements)?;
+                    match &bundle {
+                        Some(bundle) => bundle.load(py, &self.artifact_store, &self.env_cache_dir),
+                        None => self.load_function(py, name, &udf_proto, source.as_ref()),
+                    }
+                }
+            },
         });
-        log::debug!("pycodec::try_decode_udf - function unpickled");
 
         let volatility = (&udf_proto.volatility()).into();
         let return_type = (&udf_proto.result_type.unwrap_or_default()).try_into()?;
@@ -247,34 +840,303 @@ impl PyCodec {
             })
             .collect();
 
-        let function = PythonUDF::new(name, input_types?, return_type, volatility, func?);
+        let mut function = PythonUDF::new(name, input_types?, return_type, volatility, func?);
+        // source, reference and environment are kept
+        // so the function can be shipped further the same way
+        function.source = source;
+        function.reference = reference;
+        function.environment = environment;
+        function.bundle = bundle;
+        function.worker = worker;
+        function.requirements = requirements;
+        function.strict = udf_proto.strict;
+        function.mode = mode;
+        if let Some(async_options) = &udf_proto.async_options {
+            function.async_options = AsyncOptions::from(async_options);
+        }
+        function.instance = instance;
+        function.digest = Some(digest);
         let function = ScalarUDF::new_from_impl(function);
 
         Ok(function.into())
     }
 
+    fn resolve_function(&self, py: Python<'_>, reference: &FunctionReference) -> datafusion::common::Result<PyObject> {
+        match &self.function_registry {
+            Some(registry) => {
+                let func = registry.resolve(py, reference)?;
+                log::debug!("pycodec::try_decode_udf - function {reference} resolved");
+                Ok(func)
+            }
+            None => exec_err!("python function: {reference} can't be resolved, function registry is not configured"),
+        }
+    }
+
+    /// unpickles function blob, or compiles function source if blob is not provided.
+    /// source is used as a fallback if function can't be unpickled.
+    fn load_function(
+        &self,
+        py: Python<'_>,
+        name: &str,
+        udf_proto: &UdfProto,
+        source: Option<&PythonSource>,
+    ) -> datafusion::common::Result<PyObject> {
+        let unpickled = match udf_proto.blob.is_empty() {
+            true => None,
+            false => Some(self.unpickle(py, &udf_proto.format, &udf_proto.blob)),
+        };
+
+        match (unpickled, source) {
+            (Some(Ok(func)), _) => {
+                log::debug!("pycodec::try_decode_udf - function unpickled");
+                Ok(func)
+            }
+            (Some(Err(e)), Some(source)) => {
+                log::warn!(
+                    "pycodec::try_decode_udf - function: {name} failed to unpickle ({e}), compiling it from source"
+                );
+                self.compile_function(py, name, source)
+            }
+            (Some(Err(e)), None) => Err(DataFusionError::Execution(e.to_string())),
+            (None, Some(source)) => self.compile_function(py, name, source),
+            (None, None) => exec_err!("python function: {name} has neither pickled body nor source"),
+        }
+    }
+
+    /// unpickles function using configured serializer,
+    /// or built-in serializer if function has been pickled using different format
+    fn unpickle(&self, py: Python<'_>, format: &str, blob: &[u8]) -> PyResult<PyObject> {
+        let format = if format.is_empty() { FORMAT_CLOUDPICKLE } else { format };
+        if format == self.serializer.format() {
+            self.serializer.unpickle(py, blob)
+        } else {
+            log::debug!("pycodec::try_decode_udf - using built-in serializer for format: {format}");
+            serializer_for_format(py, format)?.unpickle(py, blob)
+        }
+    }
+
+    fn compile_function(
+        &self,
+        py: Python<'_>,
+        name: &str,
+        source: &PythonSource,
+    ) -> datafusion::common::Result<PyObject> {
+        let func = source
+            .compile(py, name)
+            .map_err(|e| DataFusionError::Execution(format!("function {name} failed to compile: {e}")))?;
+        log::debug!("pycodec::try_decode_udf - function compiled from source");
+
+        Ok(func)
+    }
+
+    /// decodes function in pickled tuple format, which
+    /// is shipped by value and can't be signed
+    fn decode_pickled_tuple_udf(&self, name: &str, buf: &[u8]) -> datafusion::common::Result<Arc<ScalarUDF>> {
+        if !self.allow_by_value {
+            return exec_err!("python function: {name} is shipped by value in pickled tuple format, it is rejected");
+        }
+        if self.signer.is_some() {
+            return exec_err!("python function: {name} is encoded in pickled tuple format, it can't be verified");
+        }
+        let function = Python::with_gil(|py| compat::decode_pickled_tuple_udf(py, buf))?;
+        log::debug!("pycodec::try_decode_udf - function: {name} decoded from pickled tuple format");
+
+        Ok(Arc::new(ScalarUDF::from(function)))
+    }
+
     fn try_encode_udf(
-        cloud_pickle: &CloudPickle,
+        &self,
         udf: &PythonUDF,
         volatility: &Volatility,
         buf: &mut Vec<u8>,
     ) -> datafusion::common::Result<()> {
-        let data = Python::with_gil(|py| {
-            cloud_pickle
-                .pickle(py, &udf.func)
-                .map_err(|e| DataFusionError::Execution(e.to_string()))
-        })?;
-        log::debug!("pycodec::try_encode_udf - function pickled");
-        let udf_proto = UdfProto::try_from_udf(volatility, &udf.input_types, &udf.return_type, data)?;
-
-        buf.append(&mut udf_proto.encode_to_vec());
+        if self.pickled_tuple_format {
+            self.check_pickled_tuple(udf)?;
+            buf.append(&mut Python::with_gil(|py| {
+                compat::encode_pickled_tuple_udf(py, udf, volatility)
+            })?);
+            log::debug!("pycodec::try_encode_udf - function encoded in pickled tuple format");
+            return Ok(());
+        }
+        self.encode_udf(udf, volatility, buf)
+    }
+
+    /// pickled tuple format carries python function and its types only,
+    /// functions would silently lose any other state, so they are rejected,
+    /// as are functions the codec would sign or reject when decoding
+    fn check_pickled_tuple(&self, udf: &PythonUDF) -> datafusion::common::Result<()> {
+        let name = &udf.name;
+        if self.signer.is_some() {
+            return exec_err!("python function: {name} can't be signed in pickled tuple format");
+        }
+        if !self.allow_by_value {
+            return exec_err!(
+                "python function: {name} is shipped by value in pickled tuple format, functions shipped by value are rejected"
+            );
+        }
+        let state = [
+            ("strict", udf.strict),
+            ("mode", udf.mode != CallMode::default()),
+            ("requirements", !udf.requirements.is_empty()),
+            ("async options", udf.async_options != AsyncOptions::default()),
+            ("instance", udf.instance.is_some()),
+            ("reference", udf.reference.is_some()),
+            ("bundle", udf.bundle.is_some()),
+            (
+                "environment",
+                udf.environment.is_some() || udf.worker.is_some() || self.environment.is_some(),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(state, is_set)| is_set.then_some(state))
+        .collect::<Vec<_>>();
+        if !state.is_empty() {
+            return exec_err!(
+                "python function: {name} can't be encoded in pickled tuple format, it has: {}",
+                state.join(", ")
+            );
+        }
+
         Ok(())
     }
+
+    fn encode_udf(
+        &self,
+        udf: &PythonUDF,
+        volatility: &Volatility,
+        buf: &mut Vec<u8>,
+    ) -> datafusion::common::Result<()> {
+        // function called by environment worker is shipped
+        // further with the payload it has been shipped with
+        let payload = match &udf.worker {
+            Some(worker) => worker.payload().to_vec(),
+            None => self.encode_payload(udf, volatility)?,
+        };
+
+        // signature covers payload bytes exactly as they are shipped
+        let signature = match &self.signer {
+            Some(signer) => {
+                log::debug!("pycodec::try_encode_udf - function signed");
+                signer.sign(&udf.name, &payload)
+            }
+            None => vec![],
+        };
+
+        buf.append(&mut SignedUdfProto { payload, signature }.encode_to_vec());
+        Ok(())
+    }
+
+    fn encode_payload(&self, udf: &PythonUDF, volatility: &Volatility) -> datafusion::common::Result<Vec<u8>> {
+        // functions with known source are shipped as source,
+        // optionally pickled as well if source fallback is configured.
+        // referenced functions are shipped by reference only
+        let data = match &udf.source {
+            _ if udf.reference.is_some() || udf.bundle.is_some() => vec![],
+            Some(_) if !self.source_fallback => vec![],
+            _ => {
+                let data = Python::with_gil(|py| {
+                    // modules of compiled functions can't be imported where they
+                    // are unpickled, only cloudpickle pickles them by value
+                    if self.serializer.format() != FORMAT_CLOUDPICKLE && is_compiled_from_source(py, &udf.func) {
+                        return exec_err!(
+                            "python function: {} compiled from source can't be pickled with serializer: {}, use {FORMAT_CLOUDPICKLE}",
+                            udf.name,
+                            self.serializer.format()
+                        );
+                    }
+                    self.serializer
+                        .pickle(py, &udf.func)
+                        .map_err(|e| DataFusionError::Execution(e.to_string()))
+                })?;
+                log::debug!("pycodec::try_encode_udf - function pickled");
+                data
+            }
+        };
+        let mut udf_proto = UdfProto::try_from_udf(volatility, &udf.input_types, &udf.return_type, data)?;
+        udf_proto.requirements = udf.requirements.iter().map(|r| r.to_string()).collect();
+        udf_proto.strict = udf.strict;
+        if udf.async_options != AsyncOptions::default() {
+            udf_proto.async_options = Some(AsyncProto::from(&udf.async_options));
+        }
+        udf_proto.mode = udf.mode.to_string();
+        udf_proto.instance = udf.instance.as_ref().map(InstanceProto::from);
+        match &udf.reference {
+            Some(reference) => udf_proto.reference = reference.to_string(),
+            None => {
+                udf_proto.source = udf.source.as_ref().map(SourceProto::from);
+                // bundle and environment content is published once to shared
+                // store, and shipped by digest, otherwise shipped with the function
+                let shared = self.artifact_store.is_shared();
+                if let Some(bundle) = &udf.bundle {
+                    if shared {
+                        bundle.publish(&self.artifact_store)?;
+                    }
+                    udf_proto.bundle = Some(BundleProto::new(bundle, !shared)?);
+                }
+                // function keeps environment it has been shipped with
+                let environment = udf.environment.as_ref().or(self.environment.as_ref());
+                if let Some(environment) = environment {
+                    if shared {
+                        environment.publish(&self.artifact_store)?;
+                    }
+                    udf_proto.environment = Some(EnvironmentProto::new(environment, !shared)?);
+                }
+            }
+        }
+        if !udf_proto.blob.is_empty() {
+            udf_proto.format = self.serializer.format().to_string();
+        }
+
+        Ok(udf_proto.encode_to_vec())
+    }
+
+    /// encodes map batches function, python function is encoded
+    /// like scalar functions, in this crate's format only
+    fn try_encode_map_batches(&self, function: &MapBatchesFunction) -> datafusion::common::Result<Vec<u8>> {
+        let mut encoded = vec![];
+        self.encode_udf(function.python_udf(), &Volatility::Volatile, &mut encoded)?;
+        let proto = MapBatchesProto {
+            name: function.name().to_string(),
+            function: encoded,
+            schema: Some(function.schema().as_ref().try_into()?),
+            iterator: function.iterator(),
+        };
+        log::debug!(
+            "pycodec::try_encode_map_batches - function: {} encoded",
+            function.name()
+        );
+
+        Ok(proto.encode_to_vec())
+    }
+
+    fn try_decode_map_batches(&self, buf: &[u8]) -> datafusion::common::Result<MapBatchesFunction> {
+        let proto = MapBatchesProto::decode(buf).map_err(|e| DataFusionError::Execution(e.to_string()))?;
+        let schema: Schema = proto
+            .schema
+            .as_ref()
+            .ok_or_else(|| DataFusionError::Execution(format!("function: {} has no schema", proto.name)))?
+            .try_into()
+            .map_err(|e: FromProtoError| DataFusionError::Execution(e.to_string()))?;
+        let function = self.try_decode_udf(&proto.name, &proto.function)?;
+        log::debug!("pycodec::try_decode_map_batches - function: {} decoded", proto.name);
+
+        MapBatchesFunction::try_from_udf(function, Arc::new(schema), proto.iterator)
+    }
 }
+
 pub mod serde {
+    use crate::archive::PythonEnvArchive;
+    use crate::bundle::PythonBundle;
+    use crate::coroutine::AsyncOptions;
+    use crate::stateful::{InstanceScope, PythonInstance};
+    use crate::udf::PythonSource;
     use datafusion::arrow::datatypes::DataType;
+    use datafusion::common::exec_err;
     use datafusion::error::Result;
     use datafusion_proto::protobuf::ToProtoError;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::Duration;
 
     #[derive(Clone, PartialEq, ::prost::Message)]
     pub struct UdfProto {
@@ -286,6 +1148,280 @@ pub mod serde {
         pub result_type: Option<datafusion_proto::generated::datafusion_common::ArrowType>,
         #[prost(bytes, tag = 5)]
         pub blob: Vec<u8>,
+        // tag 6 was signature of the proto, replaced by [SignedUdfProto]
+        /// function source, compiled at the executor
+        /// if blob is empty or it can't be unpickled
+        #[prost(message, optional, tag = 7)]
+        pub source: Option<SourceProto>,
+        /// serialization format of the blob,
+        /// cloudpickle is assumed if not set
+        #[prost(string, tag = 8)]
+        pub format: String,
+        /// reference to function pre-installed at executors,
+        /// in `module:function@version` format
+        #[prost(string, tag = 9)]
+        pub reference: String,
+        /// python environment shipped with the function
+        #[prost(message, optional, tag = 10)]
+        pub environment: Option<EnvironmentProto>,
+        /// python packages required by the function,
+        /// like `scikit-learn>=1.3,<2`
+        #[prost(string, repeated, tag = 11)]
+        pub requirements: Vec<String>,
+        /// how arguments are passed to the function,
+        /// arrow is assumed if not set
+        #[prost(string, tag = 12)]
+        pub mode: String,
+        /// function is not called for rows with null argument
+        #[prost(bool, tag = 13)]
+        pub strict: bool,
+        /// bundle of python modules function is loaded from
+        #[prost(message, optional, tag = 14)]
+        pub bundle: Option<BundleProto>,
+        /// how `async def` function is called, if not default
+        #[prost(message, optional, tag = 15)]
+        pub async_options: Option<AsyncProto>,
+        /// constructor arguments of class based function
+        #[prost(message, optional, tag = 16)]
+        pub instance: Option<InstanceProto>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct InstanceProto {
+        #[prost(string, tag = 1)]
+        pub scope: String,
+        /// `(args, kwargs)` pickled with `cloudpickle`, empty if there are none
+        #[prost(bytes, tag = 2)]
+        pub arguments: Vec<u8>,
+        #[prost(string, tag = 3)]
+        pub digest: String,
+    }
+
+    impl From<&PythonInstance> for InstanceProto {
+        fn from(value: &PythonInstance) -> Self {
+            InstanceProto {
+                scope: value.scope().to_string(),
+                arguments: value.arguments().to_vec(),
+                digest: value.digest().to_string(),
+            }
+        }
+    }
+
+    impl TryFrom<&InstanceProto> for PythonInstance {
+        type Error = datafusion::error::DataFusionError;
+
+        fn try_from(value: &InstanceProto) -> Result<Self> {
+            let scope = match value.scope.is_empty() {
+                true => InstanceScope::default(),
+                false => InstanceScope::from_str(&value.scope)?,
+            };
+
+            Ok(PythonInstance::from_parts(
+                scope,
+                value.arguments.clone(),
+                value.digest.clone(),
+            ))
+        }
+    }
+
+    /// encoded [UdfProto] and its signature, verified
+    /// over the payload bytes before the payload is decoded
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct SignedUdfProto {
+        #[prost(bytes, tag = 1)]
+        pub payload: Vec<u8>,
+        /// empty if function is not signed
+        #[prost(bytes, tag = 2)]
+        pub signature: Vec<u8>,
+    }
+
+    /// environment archive, referenced by digest, its content is
+    /// published to shared [crate::artifacts::PythonArtifactStore],
+    /// or shipped with the function
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct EnvironmentProto {
+        #[prost(string, tag = 1)]
+        pub format: String,
+        #[prost(string, tag = 2)]
+        pub digest: String,
+        /// archive content, empty if it is in shared artifact store
+        #[prost(bytes, tag = 3)]
+        pub content: Vec<u8>,
+    }
+
+    impl EnvironmentProto {
+        /// environment shipped by digest, with its content if `inline`
+        pub fn new(value: &PythonEnvArchive, inline: bool) -> Result<Self> {
+            Ok(EnvironmentProto {
+                format: value.format.clone(),
+                digest: value.digest.clone(),
+                content: inline_content(&value.digest, value.content.as_deref(), inline)?,
+            })
+        }
+    }
+
+    impl TryFrom<&EnvironmentProto> for PythonEnvArchive {
+        type Error = datafusion::error::DataFusionError;
+
+        fn try_from(value: &EnvironmentProto) -> Result<Self> {
+            let mut archive = PythonEnvArchive::from_digest(&value.format, &value.digest)?;
+            // verified against the digest when it is unpacked
+            archive.content = (!value.content.is_empty()).then(|| Arc::new(value.content.clone()));
+            Ok(archive)
+        }
+    }
+
+    /// content of artifact shipped with the function, empty if it is
+    /// shipped by digest only. artifact referenced by digest, whose
+    /// content is not known, can't be shipped with the function
+    fn inline_content(digest: &str, content: Option<&Vec<u8>>, inline: bool) -> Result<Vec<u8>> {
+        match (content, inline) {
+            (_, false) => Ok(vec![]),
+            (Some(content), true) => Ok(content.clone()),
+            (None, true) => exec_err!(
+                "python artifact {digest} is referenced by digest, it can't be shipped with function \
+                 without shared artifact store"
+            ),
+        }
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct AsyncProto {
+        #[prost(uint64, tag = 1)]
+        pub concurrency: u64,
+        /// zero if there is no timeout
+        #[prost(uint64, tag = 2)]
+        pub timeout_millis: u64,
+        /// zero if function is called once per row
+        #[prost(uint64, tag = 3)]
+        pub chunk_size: u64,
+        /// zero for the default deadline
+        #[prost(uint64, tag = 4)]
+        pub deadline_millis: u64,
+    }
+
+    impl From<&AsyncOptions> for AsyncProto {
+        fn from(value: &AsyncOptions) -> Self {
+            AsyncProto {
+                concurrency: value.concurrency as u64,
+                timeout_millis: value.timeout.map(|t| t.as_millis() as u64).unwrap_or_default(),
+                chunk_size: value.chunk_size.unwrap_or_default() as u64,
+                deadline_millis: value.deadline.as_millis() as u64,
+            }
+        }
+    }
+
+    impl From<&AsyncProto> for AsyncOptions {
+        fn from(value: &AsyncProto) -> Self {
+            AsyncOptions::default()
+                .with_concurrency(value.concurrency as usize)
+                .with_timeout((value.timeout_millis > 0).then(|| Duration::from_millis(value.timeout_millis)))
+                .with_chunk_size(Some(value.chunk_size as usize))
+                .with_deadline(Duration::from_millis(value.deadline_millis))
+        }
+    }
+
+    /// bundle of python modules, referenced by digest, its content is
+    /// published to shared [crate::artifacts::PythonArtifactStore],
+    /// or shipped with the function
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct BundleProto {
+        #[prost(string, tag = 1)]
+        pub digest: String,
+        /// bundle content, empty if it is in shared artifact store
+        #[prost(bytes, tag = 2)]
+        pub content: Vec<u8>,
+        #[prost(string, tag = 3)]
+        pub entry_point: String,
+    }
+
+    impl BundleProto {
+        /// bundle shipped by digest, with its content if `inline`
+        pub fn new(value: &PythonBundle, inline: bool) -> Result<Self> {
+            Ok(BundleProto {
+                digest: value.digest.clone(),
+                content: inline_content(&value.digest, value.content.as_deref(), inline)?,
+                entry_point: value.entry_point.clone(),
+            })
+        }
+    }
+
+    impl From<&BundleProto> for PythonBundle {
+        fn from(value: &BundleProto) -> Self {
+            let mut bundle = PythonBundle::from_digest(&value.digest, &value.entry_point);
+            // verified against the digest when it is unpacked
+            bundle.content = (!value.content.is_empty()).then(|| Arc::new(value.content.clone()));
+            bundle
+        }
+    }
+
+    /// plan extension (logical or physical node) encoded by this crate
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct ExtensionProto {
+        #[prost(string, tag = 1)]
+        pub kind: String,
+        #[prost(bytes, tag = 2)]
+        pub payload: Vec<u8>,
+    }
+
+    /// python function applied to record batches,
+    /// see [crate::map_batches::MapBatchesFunction]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct MapBatchesProto {
+        #[prost(string, tag = 1)]
+        pub name: String,
+        /// encoded [UdfProto]
+        #[prost(bytes, tag = 2)]
+        pub function: Vec<u8>,
+        #[prost(message, optional, tag = 3)]
+        pub schema: Option<datafusion_proto::generated::datafusion_common::Schema>,
+        #[prost(bool, tag = 4)]
+        pub iterator: bool,
+    }
+
+    /// python function applied to groups of rows,
+    /// see [crate::grouped_map::ApplyPythonNode]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct ApplyPythonNodeProto {
+        /// encoded [MapBatchesProto]
+        #[prost(bytes, tag = 1)]
+        pub function: Vec<u8>,
+        #[prost(message, repeated, tag = 2)]
+        pub keys: Vec<datafusion_proto::protobuf::LogicalExprNode>,
+    }
+
+    /// python function applied to groups of rows,
+    /// see [crate::grouped_map::ApplyPythonExec]
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct ApplyPythonExecProto {
+        /// encoded [MapBatchesProto]
+        #[prost(bytes, tag = 1)]
+        pub function: Vec<u8>,
+        #[prost(message, repeated, tag = 2)]
+        pub keys: Vec<datafusion_proto::protobuf::PhysicalExprNode>,
+    }
+
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct SourceProto {
+        #[prost(string, tag = 1)]
+        pub code: String,
+        #[prost(string, tag = 2)]
+        pub entry_point: String,
+    }
+
+    impl From<&PythonSource> for SourceProto {
+        fn from(value: &PythonSource) -> Self {
+            SourceProto {
+                code: value.code.clone(),
+                entry_point: value.entry_point.clone(),
+            }
+        }
+    }
+
+    impl From<&SourceProto> for PythonSource {
+        fn from(value: &SourceProto) -> Self {
+            PythonSource::new(value.code.clone(), value.entry_point.clone())
+        }
     }
 
     impl UdfProto {
@@ -307,6 +1443,16 @@ pub mod serde {
                 result_type: Some(return_type),
                 input_types: input_types?,
                 blob,
+                source: None,
+                format: String::new(),
+                reference: String::new(),
+                environment: None,
+                requirements: vec![],
+                mode: String::new(),
+                strict: false,
+                bundle: None,
+                async_options: None,
+                instance: None,
             })
         }
     }
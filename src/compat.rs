@@ -0,0 +1,227 @@
+use crate::pickle::{PickleSerializer, PySerializer};
+use crate::udf::PythonUDF;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::pyarrow::{FromPyArrow, ToPyArrow};
+use datafusion::common::{exec_datafusion_err, exec_err, Result};
+use datafusion::logical_expr::Volatility;
+use pyo3::ffi::c_str;
+use pyo3::types::{PyAnyMethods, PyModule};
+use pyo3::{Bound, IntoPyObject, PyAny, PyObject, Python};
+
+static COMPAT_CODE: &std::ffi::CStr = c_str!(
+    r#"
+class Function:
+    # python function with its types, returned by `udf`
+    # if datafusion-python is not installed
+    def __init__(self, name, func, input_types, return_type, volatility):
+        self.name = name
+        self.func = func
+        self.input_types = input_types
+        self.return_type = return_type
+        self.volatility = volatility
+
+    def __call__(self, *args, **kwargs):
+        return self.func(*args, **kwargs)
+
+def udf(*args, **kwargs):
+    # same arguments as `datafusion.udf`, used as decorator if function is not given
+    if (args and callable(args[0])) or "func" in kwargs:
+        return create(*args, **kwargs)
+    return lambda func: create(func, *args, **kwargs)
+
+def create(func, input_types, return_type, volatility="volatile", name=None):
+    # datafusion-python keeps only native wrapper of the python
+    # function, so its definition is kept next to it
+    definition = normalize(name, func, input_types, return_type, volatility)
+    try:
+        import datafusion
+    except ImportError:
+        return Function(*definition)
+    function = datafusion.udf(func, input_types, return_type, volatility, name)
+    function.__ballista_definition__ = definition
+    return function
+
+def definition(udf):
+    recorded = getattr(udf, "__ballista_definition__", None)
+    if recorded is not None:
+        return normalize(*recorded)
+    func = getattr(udf, "func", None) or getattr(udf, "_func", None)
+    if func is None or not hasattr(udf, "input_types") or not hasattr(udf, "return_type"):
+        return None
+    volatility = getattr(udf, "volatility", "volatile")
+    return normalize(getattr(udf, "name", None), func, udf.input_types, udf.return_type, volatility)
+
+def payload(value):
+    # (name, func, input_types, return_type, volatility),
+    # arguments of `datafusion.udf` in the same order
+    if not isinstance(value, tuple) or len(value) != 5:
+        raise ValueError(f"unexpected pickled tuple function payload: {type(value).__name__}")
+    return normalize(*value)
+
+def data_type(value):
+    # types can be given as fields as well
+    if hasattr(value, "type") and hasattr(value, "nullable"):
+        return value.type
+    return value
+
+def normalize(name, func, input_types, return_type, volatility):
+    if not isinstance(input_types, (list, tuple)):
+        # single type or schema
+        input_types = list(input_types) if hasattr(input_types, "names") else [input_types]
+    name = name or func.__qualname__.lower()
+    # datafusion-python `Volatility` enum prints as `immutable`,
+    # python enums print as `Volatility.Immutable`
+    volatility = str(volatility or "volatile").rsplit(".", 1)[-1].lower()
+    return name, func, [data_type(t) for t in input_types], data_type(return_type), volatility
+"#
+);
+
+/// prefix of functions encoded in pickled tuple format,
+/// distinguishing them from [crate::codec::serde::UdfProto]
+pub const PICKLED_TUPLE_MAGIC: &[u8] = b"BALLISTA_PYTHON_PICKLED_TUPLE:";
+
+/// attribute of datafusion-python functions exposing
+/// native function as `PyCapsule`
+pub static SCALAR_UDF_CAPSULE: &str = "__datafusion_scalar_udf__";
+
+/// Returns python `udf` function, taking the same arguments as
+/// datafusion-python `datafusion.udf`, also usable as decorator.
+///
+/// It creates datafusion-python `ScalarUDF`, if datafusion-python is
+/// installed, keeping python function and types next to it, so it can be
+/// converted by [from_datafusion_udf]. Exposed as `ballista_python.udf`.
+pub fn udf_function(py: Python<'_>) -> Result<Bound<'_, PyAny>> {
+    compat_module(py)?
+        .getattr("udf")
+        .map_err(|e| exec_datafusion_err!("python udf function can't be loaded: {e}"))
+}
+
+/// Converts function defined with datafusion-python (`datafusion.udf(...)`)
+/// to shippable [PythonUDF].
+///
+/// Accepts datafusion-python `ScalarUDF`s created with [udf_function],
+/// or any object exposing python function and its types as `func`,
+/// `input_types`, `return_type`, optionally `name` and `volatility`
+/// attributes. datafusion-python does not expose python function of
+/// functions created with `datafusion.udf`, only native function
+/// capsule, which can't be shipped to executors, so they are rejected.
+pub fn from_datafusion_udf(udf: &Bound<'_, PyAny>) -> Result<PythonUDF> {
+    let py = udf.py();
+    let definition = compat_module(py)?
+        .getattr("definition")
+        .and_then(|definition| definition.call1((udf,)))
+        .map_err(|e| exec_datafusion_err!("python function definition can't be read: {e}"))?;
+    if definition.is_none() {
+        return match udf.hasattr(SCALAR_UDF_CAPSULE).unwrap_or_default() {
+            true => exec_err!(
+                "function: {udf} exposes only native `{SCALAR_UDF_CAPSULE}` capsule, its python function \
+                 can't be shipped, create it with `ballista_python.udf` instead of `datafusion.udf`, \
+                 or register python function with its types"
+            ),
+            false => exec_err!("function: {udf} is not a datafusion-python function"),
+        };
+    }
+
+    from_definition(&definition)
+}
+
+/// checks if encoded function is in pickled tuple format
+pub fn is_pickled_tuple_payload(buf: &[u8]) -> bool {
+    buf.starts_with(PICKLED_TUPLE_MAGIC)
+}
+
+/// Decodes function encoded in pickled tuple format, [PICKLED_TUPLE_MAGIC]
+/// followed by `(name, func, input_types, return_type, volatility)` tuple,
+/// arguments of `datafusion.udf`, pickled with `cloudpickle`. Types are
+/// `pyarrow` types, volatility is `immutable`, `stable` or `volatile`.
+///
+/// Function is unpickled, so it has to come from trusted client.
+pub fn decode_pickled_tuple_udf(py: Python<'_>, buf: &[u8]) -> Result<PythonUDF> {
+    let Some(buf) = buf.strip_prefix(PICKLED_TUPLE_MAGIC) else {
+        return exec_err!("function is not encoded in pickled tuple format");
+    };
+    let value = PickleSerializer::cloudpickle(py)
+        .and_then(|serializer| serializer.unpickle(py, buf))
+        .map_err(|e| exec_datafusion_err!("pickled tuple function can't be unpickled: {e}"))?;
+    let definition = compat_module(py)?
+        .getattr("payload")
+        .and_then(|payload| payload.call1((value,)))
+        .map_err(|e| exec_datafusion_err!("invalid pickled tuple function: {e}"))?;
+
+    from_definition(&definition)
+}
+
+/// Encodes function in pickled tuple format, see [decode_pickled_tuple_udf].
+/// Only python function, its name, types and volatility are encoded.
+pub fn encode_pickled_tuple_udf(py: Python<'_>, udf: &PythonUDF, volatility: &Volatility) -> Result<Vec<u8>> {
+    let input_types = udf
+        .input_types
+        .iter()
+        .map(|t| t.to_pyarrow(py))
+        .collect::<pyo3::PyResult<Vec<_>>>()
+        .map_err(|e| exec_datafusion_err!("function: {} has invalid input types: {e}", udf.name))?;
+    let return_type = udf
+        .return_type
+        .to_pyarrow(py)
+        .map_err(|e| exec_datafusion_err!("function: {} has invalid return type: {e}", udf.name))?;
+    let volatility = match volatility {
+        Volatility::Immutable => "immutable",
+        Volatility::Stable => "stable",
+        Volatility::Volatile => "volatile",
+    };
+    let payload = (
+        udf.name.as_str(),
+        udf.func.clone_ref(py),
+        input_types,
+        return_type,
+        volatility,
+    )
+        .into_pyobject(py)
+        .map_err(|e| exec_datafusion_err!("function: {} can't be encoded: {e}", udf.name))?;
+
+    let pickle = PickleSerializer::cloudpickle(py)
+        .and_then(|serializer| serializer.pickle(py, &payload.into_any().unbind()))
+        .map_err(|e| exec_datafusion_err!("function: {} can't be pickled: {e}", udf.name))?;
+
+    Ok([PICKLED_TUPLE_MAGIC, &pickle].concat())
+}
+
+/// creates function from normalized
+/// `(name, func, input_types, return_type, volatility)`
+fn from_definition(definition: &Bound<'_, PyAny>) -> Result<PythonUDF> {
+    let (name, func, input_types, return_type, volatility): (
+        String,
+        PyObject,
+        Vec<Bound<'_, PyAny>>,
+        Bound<'_, PyAny>,
+        String,
+    ) = definition
+        .extract()
+        .map_err(|e| exec_datafusion_err!("invalid python function definition: {e}"))?;
+    let input_types = input_types
+        .iter()
+        .map(DataType::from_pyarrow_bound)
+        .collect::<pyo3::PyResult<Vec<_>>>()
+        .map_err(|e| exec_datafusion_err!("function: {name} has invalid input types: {e}"))?;
+    let return_type = DataType::from_pyarrow_bound(&return_type)
+        .map_err(|e| exec_datafusion_err!("function: {name} has invalid return type: {e}"))?;
+    let volatility = match volatility.as_str() {
+        "immutable" => Volatility::Immutable,
+        "stable" => Volatility::Stable,
+        "volatile" => Volatility::Volatile,
+        v => return exec_err!("function: {name} has unknown volatility: {v}"),
+    };
+    log::debug!("compat::from_definition - function: {name}");
+
+    Ok(PythonUDF::new(name, input_types, return_type, volatility, func))
+}
+
+fn compat_module(py: Python<'_>) -> Result<Bound<'_, PyModule>> {
+    PyModule::from_code(
+        py,
+        COMPAT_CODE,
+        c_str!("ballista_compat.py"),
+        c_str!("ballista_python_compat"),
+    )
+    .map_err(|e| exec_datafusion_err!("datafusion-python compatibility can't be loaded: {e}"))
+}
@@ -0,0 +1,337 @@
+use crate::capsule::to_array;
+use datafusion::arrow::array::{Array, ArrayRef};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::pyarrow::ToPyArrow;
+use datafusion::common::ScalarValue;
+use pyo3::exceptions::PyValueError;
+use pyo3::ffi::c_str;
+use pyo3::prelude::*;
+use pyo3::types::{PyList, PyModule, PyTuple};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+static COROUTINE_CODE: &std::ffi::CStr = c_str!(
+    r#"
+import asyncio
+import inspect
+import threading
+
+_loop = None
+_lock = threading.Lock()
+_semaphores = {}
+
+def is_coroutine_function(func):
+    return inspect.iscoroutinefunction(func) or inspect.iscoroutinefunction(getattr(func, "__call__", None))
+
+def loop():
+    # single event loop per process, running in its own thread,
+    # so coroutines of all tasks are interleaved on it
+    global _loop
+    with _lock:
+        if _loop is None:
+            _loop = asyncio.new_event_loop()
+            threading.Thread(target=_loop.run_forever, name="ballista-python-asyncio", daemon=True).start()
+        return _loop
+
+def evict(keys):
+    # calls in progress keep the semaphore they use
+    for key in keys:
+        _semaphores.pop(key, None)
+
+async def _call_all(name, key, func, calls, concurrency, timeout, deadline):
+    # limit is shared by all calls of the function, semaphore
+    # is keyed by function digest and created in the loop it is used by
+    semaphore = _semaphores.get(key)
+    if semaphore is None:
+        semaphore = _semaphores.setdefault(key, asyncio.Semaphore(concurrency))
+
+    async def call(args):
+        async with semaphore:
+            if timeout is None:
+                return await func(*args)
+            try:
+                return await asyncio.wait_for(func(*args), timeout)
+            except asyncio.TimeoutError:
+                raise TimeoutError(f"function {name} did not complete in {timeout} seconds") from None
+
+    if not calls:
+        return []
+    tasks = [asyncio.ensure_future(call(args)) for args in calls]
+    done, pending = await asyncio.wait(tasks, timeout=deadline, return_when=asyncio.FIRST_EXCEPTION)
+    # calls fail together, so calls still running
+    # after the first failure, or the deadline, are cancelled
+    for task in pending:
+        task.cancel()
+    if pending:
+        await asyncio.gather(*pending, return_exceptions=True)
+    for task in tasks:
+        if task in done and task.exception() is not None:
+            raise task.exception()
+    if pending:
+        raise TimeoutError(f"calls of function {name} did not complete in {deadline} seconds")
+    return [task.result() for task in tasks]
+
+def call_all(name, key, func, calls, concurrency, timeout, deadline):
+    # waiting for the result releases the GIL
+    future = asyncio.run_coroutine_threadsafe(_call_all(name, key, func, calls, concurrency, timeout, deadline), loop())
+    return future.result()
+"#
+);
+
+static COROUTINE_MODULE: OnceLock<Py<PyModule>> = OnceLock::new();
+
+/// keys of semaphores limiting concurrent calls, by function
+/// name, so semaphores of evicted functions can be dropped
+static SEMAPHORE_KEYS: Mutex<BTreeMap<String, BTreeSet<String>>> = Mutex::new(BTreeMap::new());
+
+/// default time calls of a function, made for one batch, have to complete in
+pub const DEFAULT_ASYNC_DEADLINE: Duration = Duration::from_secs(600);
+
+/// How `async def` function is called.
+///
+/// Coroutines are run on event loop shared by all functions of the
+/// process (executor), running in its own thread, so I/O bound
+/// functions do not hold the GIL while awaiting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsyncOptions {
+    /// maximum number of concurrent calls of the function, per process
+    pub concurrency: usize,
+    /// function call fails if it does not complete in time
+    pub timeout: Option<Duration>,
+    /// calls of the function made for one batch fail, and those still
+    /// running are cancelled, if they do not complete in time, even
+    /// if there is no timeout of a single call
+    pub deadline: Duration,
+    /// function is called with chunks of this many rows, converted
+    /// according to function mode, instead of once per row
+    pub chunk_size: Option<usize>,
+}
+
+impl Default for AsyncOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 16,
+            timeout: None,
+            deadline: DEFAULT_ASYNC_DEADLINE,
+            chunk_size: None,
+        }
+    }
+}
+
+impl AsyncOptions {
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// sets deadline of calls made for one batch, zero sets the default
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = match deadline.is_zero() {
+            true => DEFAULT_ASYNC_DEADLINE,
+            false => deadline,
+        };
+        self
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: Option<usize>) -> Self {
+        self.chunk_size = chunk_size.filter(|c| *c > 0);
+        self
+    }
+}
+
+/// checks if function is `async def` function
+pub fn is_coroutine_function(py: Python<'_>, func: &PyObject) -> PyResult<bool> {
+    coroutine_module(py)?
+        .getattr("is_coroutine_function")?
+        .call1((func,))?
+        .extract()
+}
+
+/// calls coroutine function with each of `calls` arguments,
+/// returning results in order of the calls. The first failing
+/// call fails all of them, calls still running are cancelled.
+///
+/// concurrent calls are limited by semaphore under `key`, digest
+/// of the function, dropped by [evict_semaphores]
+pub fn call_all(
+    py: Python<'_>,
+    name: &str,
+    key: &str,
+    func: &PyObject,
+    calls: Vec<Bound<'_, PyTuple>>,
+    options: &AsyncOptions,
+) -> PyResult<Vec<PyObject>> {
+    let timeout = options.timeout.map(|t| t.as_secs_f64());
+    log::debug!(
+        "coroutine::call_all - function: {name}, calls: {}, concurrency: {}",
+        calls.len(),
+        options.concurrency
+    );
+    SEMAPHORE_KEYS
+        .lock()
+        .unwrap()
+        .entry(name.to_string())
+        .or_default()
+        .insert(key.to_string());
+    let results = coroutine_module(py)?.getattr("call_all")?.call1((
+        name,
+        key,
+        func,
+        PyList::new(py, calls)?,
+        options.concurrency,
+        timeout,
+        options.deadline.as_secs_f64(),
+    ))?;
+
+    results.extract()
+}
+
+/// drops semaphores of function with given name, of its version
+/// with given digest only, if set. Called when decoded function
+/// is evicted, calls in progress keep their semaphore
+pub(crate) fn evict_semaphores(name: &str, digest: Option<&str>) {
+    let keys = {
+        let mut semaphore_keys = SEMAPHORE_KEYS.lock().unwrap();
+        let Some(keys) = semaphore_keys.get_mut(name) else {
+            return;
+        };
+        let evicted = match digest {
+            Some(digest) => keys.take(digest).into_iter().collect::<Vec<_>>(),
+            None => std::mem::take(keys).into_iter().collect(),
+        };
+        if keys.is_empty() {
+            semaphore_keys.remove(name);
+        }
+        evicted
+    };
+    // semaphores exist only if the module has been loaded
+    let Some(module) = COROUTINE_MODULE.get() else {
+        return;
+    };
+    if keys.is_empty() {
+        return;
+    }
+    log::debug!(
+        "coroutine::evict_semaphores - function: {name}, semaphores: {}",
+        keys.len()
+    );
+    if let Err(e) = Python::with_gil(|py| module.bind(py).getattr("evict")?.call1((keys,)).map(|_| ())) {
+        log::warn!("semaphores of function: {name} can't be evicted: {e}");
+    }
+}
+
+/// python values of array rows, simple types are converted
+/// without `pyarrow`, `pyarrow` is used for other types
+pub fn to_py_values(py: Python<'_>, array: &ArrayRef) -> PyResult<Vec<PyObject>> {
+    if !is_simple_type(array.data_type()) {
+        return array
+            .into_data()
+            .to_pyarrow(py)?
+            .call_method0(py, "to_pylist")?
+            .extract(py);
+    }
+    (0..array.len())
+        .map(|row| {
+            let value = ScalarValue::try_from_array(array, row).map_err(|e| PyValueError::new_err(e.to_string()))?;
+            Ok(match value {
+                v if v.is_null() => py.None(),
+                ScalarValue::Boolean(Some(v)) => v.into_pyobject(py)?.to_owned().into_any().unbind(),
+                ScalarValue::Int8(Some(v)) => v.into_pyobject(py)?.into_any().unbind(),
+                ScalarValue::Int16(Some(v)) => v.into_pyobject(py)?.into_any().unbind(),
+                ScalarValue::Int32(Some(v)) => v.into_pyobject(py)?.into_any().unbind(),
+                ScalarValue::Int64(Some(v)) => v.into_pyobject(py)?.into_any().unbind(),
+                ScalarValue::UInt8(Some(v)) => v.into_pyobject(py)?.into_any().unbind(),
+                ScalarValue::UInt16(Some(v)) => v.into_pyobject(py)?.into_any().unbind(),
+                ScalarValue::UInt32(Some(v)) => v.into_pyobject(py)?.into_any().unbind(),
+                ScalarValue::UInt64(Some(v)) => v.into_pyobject(py)?.into_any().unbind(),
+                ScalarValue::Float32(Some(v)) => v.into_pyobject(py)?.into_any().unbind(),
+                ScalarValue::Float64(Some(v)) => v.into_pyobject(py)?.into_any().unbind(),
+                ScalarValue::Utf8(Some(v)) | ScalarValue::LargeUtf8(Some(v)) | ScalarValue::Utf8View(Some(v)) => {
+                    v.into_pyobject(py)?.into_any().unbind()
+                }
+                v => return Err(PyValueError::new_err(format!("unsupported value: {v:?}"))),
+            })
+        })
+        .collect()
+}
+
+/// array of python values, simple types are converted
+/// without `pyarrow`, `pyarrow` is used for other types
+pub fn from_py_values(py: Python<'_>, values: Vec<PyObject>, data_type: &DataType) -> PyResult<ArrayRef> {
+    if !is_simple_type(data_type) {
+        return to_array(py, PyList::new(py, values)?.as_any(), data_type);
+    }
+    let scalars = values
+        .iter()
+        .map(|value| {
+            let value = value.bind(py);
+            if value.is_none() {
+                return ScalarValue::try_from(data_type).map_err(|e| PyValueError::new_err(e.to_string()));
+            }
+            Ok(match data_type {
+                DataType::Boolean => ScalarValue::Boolean(Some(value.extract()?)),
+                DataType::Int8 => ScalarValue::Int8(Some(value.extract()?)),
+                DataType::Int16 => ScalarValue::Int16(Some(value.extract()?)),
+                DataType::Int32 => ScalarValue::Int32(Some(value.extract()?)),
+                DataType::Int64 => ScalarValue::Int64(Some(value.extract()?)),
+                DataType::UInt8 => ScalarValue::UInt8(Some(value.extract()?)),
+                DataType::UInt16 => ScalarValue::UInt16(Some(value.extract()?)),
+                DataType::UInt32 => ScalarValue::UInt32(Some(value.extract()?)),
+                DataType::UInt64 => ScalarValue::UInt64(Some(value.extract()?)),
+                DataType::Float32 => ScalarValue::Float32(Some(value.extract()?)),
+                DataType::Float64 => ScalarValue::Float64(Some(value.extract()?)),
+                DataType::Utf8 => ScalarValue::Utf8(Some(value.extract()?)),
+                DataType::LargeUtf8 => ScalarValue::LargeUtf8(Some(value.extract()?)),
+                DataType::Utf8View => ScalarValue::Utf8View(Some(value.extract()?)),
+                t => return Err(PyValueError::new_err(format!("unsupported type: {t}"))),
+            })
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+
+    match scalars.is_empty() {
+        true => Ok(datafusion::arrow::array::new_empty_array(data_type)),
+        false => ScalarValue::iter_to_array(scalars).map_err(|e| PyValueError::new_err(e.to_string())),
+    }
+}
+
+/// types converted from and to python values without `pyarrow`
+fn is_simple_type(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Boolean
+            | DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Utf8
+            | DataType::LargeUtf8
+            | DataType::Utf8View
+    )
+}
+
+fn coroutine_module(py: Python<'_>) -> PyResult<&Bound<'_, PyModule>> {
+    // module keeps the event loop, so it is loaded once
+    if COROUTINE_MODULE.get().is_none() {
+        let module = PyModule::from_code(
+            py,
+            COROUTINE_CODE,
+            c_str!("ballista_coroutine.py"),
+            c_str!("ballista_python_coroutine"),
+        )?;
+        let _ = COROUTINE_MODULE.set(module.unbind());
+    }
+
+    Ok(COROUTINE_MODULE.get().expect("coroutine module loaded").bind(py))
+}
@@ -0,0 +1,260 @@
+use crate::stateful::PythonExecutionEngine;
+use ballista_executor::executor_process::ExecutorProcessConfig;
+use ballista_scheduler::config::SchedulerConfig;
+use datafusion::common::Result;
+use datafusion::error::DataFusionError;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::types::PyAnyMethods;
+use pyo3::{ffi, PyResult, Python};
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// environment variable with path of virtual environment to use
+pub static ENV_VENV: &str = "BALLISTA_PYTHON_VENV";
+/// environment variable with extra `sys.path` entries,
+/// separated by platform path separator
+pub static ENV_EXTRA_PATH: &str = "BALLISTA_PYTHON_EXTRA_PATH";
+/// environment variable with python home to use
+pub static ENV_PYTHON_HOME: &str = "BALLISTA_PYTHON_HOME";
+/// environment variable enabling isolated mode (`true` or `1`)
+pub static ENV_ISOLATED: &str = "BALLISTA_PYTHON_ISOLATED";
+
+/// Python environment used by clients, schedulers and executors.
+///
+/// Python home and isolated mode are set on interpreter configuration
+/// when the interpreter is initialized, so configuration should be
+/// applied at process start, before any other python related call.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PythonEnvConfig {
+    /// virtual environment whose `site-packages` is added to `sys.path`,
+    /// not used (with a warning) if it has no `site-packages` of the interpreter
+    pub venv: Option<PathBuf>,
+    /// extra `sys.path` entries
+    pub extra_paths: Vec<PathBuf>,
+    /// `PYTHONHOME` of the interpreter
+    pub python_home: Option<PathBuf>,
+    /// ignores user site-packages, current directory
+    /// and python environment variables
+    pub isolated: bool,
+}
+
+impl PythonEnvConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// creates configuration from environment variables.
+    ///
+    /// virtual environment is looked up from [ENV_VENV], `VIRTUAL_ENV`,
+    /// or `.venv` in current directory, if it exists.
+    pub fn from_env() -> Self {
+        let venv = std::env::var_os(ENV_VENV)
+            .or_else(|| std::env::var_os("VIRTUAL_ENV"))
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(".venv")).filter(|p| p.is_dir()));
+        let extra_paths = std::env::var_os(ENV_EXTRA_PATH)
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        let python_home = std::env::var_os(ENV_PYTHON_HOME).map(PathBuf::from);
+        let isolated = std::env::var(ENV_ISOLATED)
+            .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+            .unwrap_or_default();
+
+        Self {
+            venv,
+            extra_paths,
+            python_home,
+            isolated,
+        }
+    }
+
+    pub fn with_venv(mut self, venv: impl Into<PathBuf>) -> Self {
+        self.venv = Some(venv.into());
+        self
+    }
+
+    pub fn with_extra_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.extra_paths.push(path.into());
+        self
+    }
+
+    pub fn with_python_home(mut self, python_home: impl Into<PathBuf>) -> Self {
+        self.python_home = Some(python_home.into());
+        self
+    }
+
+    pub fn with_isolated(mut self, isolated: bool) -> Self {
+        self.isolated = isolated;
+        self
+    }
+
+    /// checks if configured paths exist
+    pub fn validate(&self) -> PyResult<()> {
+        if let Some(venv) = &self.venv {
+            check_dir("virtual environment", venv)?;
+        }
+        if let Some(python_home) = &self.python_home {
+            check_dir("python home", python_home)?;
+        }
+        for path in &self.extra_paths {
+            check_dir("extra python path", path)?;
+        }
+        Ok(())
+    }
+
+    /// validates and applies configuration, initializing interpreter
+    pub fn apply(&self) -> PyResult<()> {
+        log::debug!("setting up python environment: {self:?}");
+        self.validate()?;
+
+        // python home and isolated mode have to be configured
+        // before interpreter is initialized
+        if unsafe { ffi::Py_IsInitialized() } == 0 {
+            self.initialize()?;
+        } else if let Some(python_home) = &self.python_home {
+            let base_prefix: PathBuf = Python::with_gil(|py| py.import("sys")?.getattr("base_prefix")?.extract())?;
+            if !same_path(&base_prefix, python_home) {
+                return Err(PyValueError::new_err(
+                    "python interpreter already initialized, python home can't be configured",
+                ));
+            }
+        }
+
+        Python::with_gil(|py| -> PyResult<()> {
+            let sys = py.import("sys")?;
+            let site = py.import("site")?;
+
+            if self.isolated {
+                // removes current directory and user site-packages
+                // if they made it to the path before configuration
+                let user_site: String = site.getattr("getusersitepackages")?.call0()?.extract()?;
+                let path: Vec<String> = sys.getattr("path")?.extract()?;
+                let path = path
+                    .into_iter()
+                    .filter(|p| !p.is_empty() && p != "." && p != &user_site)
+                    .collect::<Vec<_>>();
+                sys.setattr("path", path)?;
+            }
+
+            if let Some(venv) = &self.venv {
+                let version = py.version_info();
+                match site_packages(venv, version.major, version.minor) {
+                    // site dir, so `.pth` files are processed
+                    Some(site_packages) => {
+                        site.getattr("addsitedir")?.call1((site_packages,))?;
+                    }
+                    None => log::warn!(
+                        "virtual environment: {} has no site-packages for python {}.{}, it is not used",
+                        venv.display(),
+                        version.major,
+                        version.minor
+                    ),
+                }
+            }
+
+            let path = sys.getattr("path")?;
+            for extra_path in &self.extra_paths {
+                // entries have to be strings, path objects are ignored by importers
+                let extra_path = extra_path.to_string_lossy();
+                if !path.contains(extra_path.as_ref())? {
+                    path.call_method1("append", (extra_path.as_ref(),))?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// initializes interpreter using `PyConfig`, so python home and
+    /// isolated mode do not leak to child processes as environment variables
+    fn initialize(&self) -> PyResult<()> {
+        let mut config = std::mem::MaybeUninit::<ffi::PyConfig>::uninit();
+        let config = config.as_mut_ptr();
+        unsafe {
+            if self.isolated {
+                // ignores environment variables, user site-packages
+                // and script directory, like `python -I`
+                ffi::PyConfig_InitIsolatedConfig(config);
+            } else {
+                ffi::PyConfig_InitPythonConfig(config);
+            }
+            // signal handlers are not installed, as with auto-initialized interpreter
+            (*config).install_signal_handlers = 0;
+
+            let mut status = ffi::PyStatus_Ok();
+            if let Some(python_home) = &self.python_home {
+                let python_home = CString::new(python_home.as_os_str().as_encoded_bytes())
+                    .map_err(|e| PyValueError::new_err(format!("invalid python home: {e}")))?;
+                status = ffi::PyConfig_SetBytesString(config, &raw mut (*config).home, python_home.as_ptr());
+            }
+            if ffi::PyStatus_Exception(status) == 0 {
+                status = ffi::Py_InitializeFromConfig(config);
+            }
+            ffi::PyConfig_Clear(config);
+
+            if ffi::PyStatus_Exception(status) != 0 {
+                let message = match status.err_msg.is_null() {
+                    true => "unknown error".into(),
+                    false => CStr::from_ptr(status.err_msg).to_string_lossy(),
+                };
+                return Err(PyRuntimeError::new_err(format!(
+                    "python interpreter can't be initialized: {message}"
+                )));
+            }
+            // releases GIL held after initialization, as pyo3 does
+            // for auto-initialized interpreter
+            ffi::PyEval_SaveThread();
+        }
+        Ok(())
+    }
+
+    /// applies configuration at scheduler start, before
+    /// scheduler codecs get to decode any function
+    pub fn configure_scheduler(&self, config: SchedulerConfig) -> Result<SchedulerConfig> {
+        crate::setup_python_with(self).map_err(|e| DataFusionError::External(Box::new(e)))?;
+        Ok(config)
+    }
+
+    /// applies configuration at executor start, before
+    /// executor registers with the scheduler. execution engine
+    /// of the executor, if set, is wrapped by [PythonExecutionEngine]
+    pub fn configure_executor(&self, mut config: ExecutorProcessConfig) -> Result<ExecutorProcessConfig> {
+        crate::setup_python_with(self).map_err(|e| DataFusionError::External(Box::new(e)))?;
+        let engine = match config.override_execution_engine.take() {
+            Some(inner) => PythonExecutionEngine::new(inner),
+            None => PythonExecutionEngine::default(),
+        };
+        config.override_execution_engine = Some(Arc::new(engine));
+        Ok(config)
+    }
+}
+
+fn same_path(path: &Path, other: &Path) -> bool {
+    match (path.canonicalize(), other.canonicalize()) {
+        (Ok(path), Ok(other)) => path == other,
+        _ => path == other,
+    }
+}
+
+fn check_dir(what: &str, path: &Path) -> PyResult<()> {
+    if path.is_dir() {
+        Ok(())
+    } else {
+        Err(PyValueError::new_err(format!(
+            "{what}: {} does not exist",
+            path.display()
+        )))
+    }
+}
+
+fn site_packages(venv: &Path, major: u8, minor: u8) -> Option<PathBuf> {
+    [
+        venv.join("lib")
+            .join(format!("python{major}.{minor}"))
+            .join("site-packages"),
+        // windows layout
+        venv.join("Lib").join("site-packages"),
+    ]
+    .into_iter()
+    .find(|p| p.is_dir())
+}
@@ -1,50 +1,557 @@
-use crate::udf::PythonUDF;
+use crate::bundle::PythonBundle;
+use crate::catalog::{FunctionCatalog, FunctionDefinition};
+use crate::cluster::{self, ClusterFunctionCommand};
+use crate::coroutine::AsyncOptions;
+use crate::requirements::PythonRequirement;
+use crate::stateful::{InstanceScope, PythonInstance};
+use crate::udf::{CallMode, PythonUDF};
 use datafusion::arrow::datatypes::DataType;
 use datafusion::common::{exec_err, ScalarValue};
+use datafusion::error::DataFusionError;
 use datafusion::execution::context::{FunctionFactory, RegisterFunction};
 use datafusion::execution::SessionState;
-use datafusion::logical_expr::{CreateFunction, Expr, ScalarUDF};
+use datafusion::logical_expr::{CreateFunction, Expr, ScalarUDF, Volatility};
+use datafusion::sql::sqlparser::ast::FunctionCalledOnNull;
+use pyo3::Python;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
+/// option declaring python packages required by the function,
+/// separated by `;`, like `scikit-learn>=1.3,<2; pandas`
+pub static OPTION_REQUIREMENTS: &str = "requirements";
+
+/// option with name of python function in the body,
+/// if it differs from sql function name
+pub static OPTION_HANDLER: &str = "handler";
+
+/// option with mode in which function is called, `arrow`, `pandas` or `capsule`
+pub static OPTION_MODE: &str = "mode";
+
+/// option with path of a zip file or directory with python modules
+/// function is loaded from, `handler` option declares the function
+/// in `module:function` format
+pub static OPTION_BUNDLE: &str = "bundle";
+
+/// option with maximum number of concurrent calls
+/// of `async def` function, per executor
+pub static OPTION_CONCURRENCY: &str = "concurrency";
+
+/// option with timeout of `async def` function call, in seconds
+pub static OPTION_TIMEOUT: &str = "timeout";
+
+/// option with deadline of `async def` function calls
+/// made for one batch, in seconds
+pub static OPTION_DEADLINE: &str = "deadline";
+
+/// option with number of rows `async def` function is called with,
+/// it is called once per row if not set
+pub static OPTION_CHUNK_SIZE: &str = "chunk_size";
+
+/// option with scope of class based function instance, `process` (default)
+/// or `task`, see [crate::stateful::InstanceScope]
+pub static OPTION_INSTANCE: &str = "instance";
+
+/// language of functions handled by [PythonLanguageHandler]
+pub static LANGUAGE_PYTHON: &str = "python";
+
+tokio::task_local! {
+    /// options of `CREATE FUNCTION` statement executed by
+    /// [crate::sql::PythonSessionExt::python_sql], which can't be passed
+    /// through [FunctionFactory::create]. Taken by [PythonFunctionFactory]
+    /// installed in the session
+    static STATEMENT_OPTIONS: RefCell<Option<FunctionOptions>>;
+}
+
+/// runs `create` with statement options available to [PythonFunctionFactory],
+/// fails if options are not taken by function factory of the session
+pub(crate) async fn create_with_statement_options<F>(
+    options: FunctionOptions,
+    create: F,
+) -> datafusion::common::Result<RegisterFunction>
+where
+    F: std::future::Future<Output = datafusion::common::Result<RegisterFunction>>,
+{
+    let has_options = options != FunctionOptions::default();
+    STATEMENT_OPTIONS
+        .scope(RefCell::new(Some(options)), async move {
+            let function = create.await?;
+            let taken = STATEMENT_OPTIONS.with(|options| options.borrow().is_none());
+            match taken || !has_options {
+                true => Ok(function),
+                false => exec_err!("function factory of the session does not support OPTIONS and STRICT clauses"),
+            }
+        })
+        .await
+}
+
+/// Options of `CREATE FUNCTION` statement, provided using
+/// `OPTIONS (key 'value', ...)` clause.
+///
+/// Datafusion sql planner does not support options clause,
+/// statements with options have to be executed using
+/// [crate::sql::PythonSessionExt::python_sql].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FunctionOptions {
+    options: BTreeMap<String, String>,
+    strict: bool,
+}
+
+impl FunctionOptions {
+    pub fn new(options: impl IntoIterator<Item = (String, String)>) -> Self {
+        Self {
+            options: options.into_iter().map(|(k, v)| (k.to_lowercase(), v)).collect(),
+            strict: false,
+        }
+    }
+
+    /// function returns null on null input (`STRICT` or
+    /// `RETURNS NULL ON NULL INPUT`), which is dropped by
+    /// datafusion sql planner as well
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// maps null input clause of `CREATE FUNCTION` statement,
+    /// function called on null input is not strict
+    pub fn with_called_on_null(self, called_on_null: Option<&FunctionCalledOnNull>) -> Self {
+        let strict = matches!(
+            called_on_null,
+            Some(FunctionCalledOnNull::Strict) | Some(FunctionCalledOnNull::ReturnsNullOnNullInput)
+        );
+        self.with_strict(strict)
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.options.get(key).map(|v| v.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.options.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// rejects options which are not supported by the language handler
+    pub fn validate(&self, language: &str, supported: &[&str]) -> datafusion::common::Result<()> {
+        match self.options.keys().find(|k| !supported.contains(&k.as_str())) {
+            Some(key) => exec_err!("unsupported {language} function option: {key}, supported options: {supported:?}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Handler creating functions of a `CREATE FUNCTION` language,
+/// registered with [PythonFunctionFactory].
+#[async_trait::async_trait]
+pub trait LanguageHandler: Debug + Send + Sync {
+    async fn create(
+        &self,
+        state: &SessionState,
+        statement: CreateFunction,
+        options: &FunctionOptions,
+    ) -> datafusion::common::Result<RegisterFunction>;
+}
+
+/// Handles `LANGUAGE PYTHON` functions
 #[derive(Debug, Default)]
-pub struct PythonFunctionFactory {}
+pub struct PythonLanguageHandler {}
 
 #[async_trait::async_trait]
-impl FunctionFactory for PythonFunctionFactory {
+impl LanguageHandler for PythonLanguageHandler {
     async fn create(
         &self,
         _state: &SessionState,
         statement: CreateFunction,
+        options: &FunctionOptions,
     ) -> datafusion::common::Result<RegisterFunction> {
-        // TODO: check if language is python
-
-        match statement.params.function_body {
-            Some(Expr::Literal(ScalarValue::Utf8(Some(code)), _)) => {
-                let name = statement.name;
-                let return_type = statement.return_type.expect("return type expected");
-                let argument_types = statement
-                    .args
-                    .map(|args| args.into_iter().map(|a| a.data_type).collect::<Vec<DataType>>())
-                    .unwrap_or_default();
-                let udf = PythonUDF::from_code_with_types(&name, &code, argument_types, return_type)?;
-                let udf = ScalarUDF::from(udf);
-                Ok(RegisterFunction::Scalar(Arc::new(udf)))
+        options.validate(
+            LANGUAGE_PYTHON,
+            &[
+                OPTION_REQUIREMENTS,
+                OPTION_HANDLER,
+                OPTION_MODE,
+                OPTION_BUNDLE,
+                OPTION_CONCURRENCY,
+                OPTION_TIMEOUT,
+                OPTION_DEADLINE,
+                OPTION_CHUNK_SIZE,
+                OPTION_INSTANCE,
+            ],
+        )?;
+
+        let name = statement.name;
+        let return_type = statement.return_type.expect("return type expected");
+        let args = statement.args.unwrap_or_default();
+        let argument_names = args
+            .iter()
+            .map(|a| a.name.as_ref().map(|n| n.value.clone()).unwrap_or_default())
+            .collect();
+        let argument_types = args.into_iter().map(|a| a.data_type).collect::<Vec<DataType>>();
+        let requirements = options
+            .get(OPTION_REQUIREMENTS)
+            .map(PythonRequirement::parse_list)
+            .transpose()?
+            .unwrap_or_default();
+        let mode = options.get(OPTION_MODE).map(CallMode::from_str).transpose()?;
+        let volatility = statement.params.behavior.unwrap_or(Volatility::Volatile);
+
+        let udf = match (statement.params.function_body, options.get(OPTION_BUNDLE)) {
+            (None, Some(path)) => {
+                let Some(entry_point) = options.get(OPTION_HANDLER) else {
+                    return exec_err!("function: {name} loaded from bundle requires `{OPTION_HANDLER}` option");
+                };
+                let bundle = PythonBundle::from_path(path, entry_point)?;
+                PythonUDF::from_bundle(&name, bundle, argument_types, return_type, volatility)?
+            }
+            (Some(Expr::Literal(ScalarValue::Utf8(Some(code)), _)), None) => {
+                let entry_point = options.get(OPTION_HANDLER).unwrap_or(&name);
+                PythonUDF::from_code_with_entry_point(&name, &code, entry_point, argument_types, return_type)?
+                    .with_volatility(volatility)
             }
-            None => exec_err!("function definition to be provided")?,
+            (Some(_), Some(_)) => {
+                exec_err!("function: {name} can't have both definition and `{OPTION_BUNDLE}` option")?
+            }
+            (None, None) => exec_err!("function definition to be provided")?,
             _ => exec_err!("invalid function definition provided")?,
+        };
+        let instance_scope = options.get(OPTION_INSTANCE).map(InstanceScope::from_str).transpose()?;
+        // class handler is instantiated without arguments
+        let instance = Python::with_gil(|py| match PythonInstance::is_class(py, &udf.func)? {
+            true => PythonInstance::without_arguments(py, &udf.func).map(Some),
+            false => Ok(None),
+        })
+        .map_err(|e| DataFusionError::Execution(format!("function: {name} can't be inspected: {e}")))?;
+        let udf = match (instance, instance_scope) {
+            (Some(instance), scope) => udf.with_instance(instance.with_scope(scope.unwrap_or_default())),
+            (None, Some(_)) => {
+                return exec_err!("function: {name} is not a class, `{OPTION_INSTANCE}` option can't be used")
+            }
+            (None, None) => udf,
+        };
+        let udf = udf
+            .with_strict(options.strict())
+            .with_mode(mode.unwrap_or_default())
+            .with_requirements(requirements)
+            .with_argument_names(argument_names)
+            .with_async_options(async_options(options)?);
+
+        Ok(RegisterFunction::Scalar(Arc::new(ScalarUDF::from(udf))))
+    }
+}
+
+/// options of `async def` function, defaults are used for missing ones
+fn async_options(options: &FunctionOptions) -> datafusion::common::Result<AsyncOptions> {
+    let mut async_options = AsyncOptions::default();
+    if let Some(concurrency) = options.get(OPTION_CONCURRENCY) {
+        match concurrency.trim().parse::<usize>() {
+            Ok(concurrency) if concurrency > 0 => async_options = async_options.with_concurrency(concurrency),
+            _ => return exec_err!("invalid {OPTION_CONCURRENCY}: {concurrency}, positive number expected"),
         }
     }
+    if let Some(timeout) = options.get(OPTION_TIMEOUT) {
+        match parse_seconds(timeout) {
+            Some(timeout) => async_options = async_options.with_timeout(Some(timeout)),
+            None => return exec_err!("invalid {OPTION_TIMEOUT}: {timeout}, positive number of seconds expected"),
+        }
+    }
+    if let Some(deadline) = options.get(OPTION_DEADLINE) {
+        match parse_seconds(deadline) {
+            Some(deadline) => async_options = async_options.with_deadline(deadline),
+            None => return exec_err!("invalid {OPTION_DEADLINE}: {deadline}, positive number of seconds expected"),
+        }
+    }
+    if let Some(chunk_size) = options.get(OPTION_CHUNK_SIZE) {
+        match chunk_size.trim().parse::<usize>() {
+            Ok(chunk_size) if chunk_size > 0 => async_options = async_options.with_chunk_size(Some(chunk_size)),
+            _ => return exec_err!("invalid {OPTION_CHUNK_SIZE}: {chunk_size}, positive number expected"),
+        }
+    }
+
+    Ok(async_options)
+}
+
+/// positive number of seconds
+fn parse_seconds(value: &str) -> Option<Duration> {
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|t| Duration::try_from_secs_f64(t).ok())
+        .filter(|t| !t.is_zero())
+}
+
+/// Function factory dispatching `CREATE FUNCTION` statements
+/// to handler registered for statement's `LANGUAGE`.
+///
+/// [PythonLanguageHandler] is registered by default, handlers
+/// for other languages can be registered side by side.
+///
+/// If catalog is configured, definitions of functions which are not
+/// `TEMPORARY` are stored in it, and loaded into new sessions
+/// by [crate::sql::PythonSessionExt::register_function_factory].
+/// With cluster registry enabled, definitions are stored in
+/// [crate::cluster::ClusterFunctionRegistry] hosted by the scheduler
+/// as well, sharing them with other sessions of the cluster.
+#[derive(Debug)]
+pub struct PythonFunctionFactory {
+    handlers: BTreeMap<String, Arc<dyn LanguageHandler>>,
+    catalog: Option<Arc<dyn FunctionCatalog>>,
+    cluster_registry: bool,
+    cluster_registry_token: String,
+}
+
+impl Default for PythonFunctionFactory {
+    fn default() -> Self {
+        Self::empty().with_handler(LANGUAGE_PYTHON, Arc::new(PythonLanguageHandler::default()))
+    }
+}
+
+impl PythonFunctionFactory {
+    /// factory without any language handler
+    pub fn empty() -> Self {
+        Self {
+            handlers: BTreeMap::new(),
+            catalog: None,
+            cluster_registry: false,
+            cluster_registry_token: std::env::var(cluster::ENV_REGISTRY_TOKEN).unwrap_or_default(),
+        }
+    }
+
+    /// stores functions in the cluster registry hosted by
+    /// the scheduler session is connected to, disabled by default
+    pub fn with_cluster_registry(mut self, cluster_registry: bool) -> Self {
+        self.cluster_registry = cluster_registry;
+        self
+    }
+
+    /// token authorizing modifications of functions in the cluster
+    /// registry, configured from [cluster::ENV_REGISTRY_TOKEN] by default.
+    /// Namespace is owned by the token which modified it first
+    pub fn with_cluster_registry_token(mut self, token: impl Into<String>) -> Self {
+        self.cluster_registry_token = token.into();
+        self
+    }
+
+    /// catalog storing definitions of created functions
+    pub fn with_catalog(mut self, catalog: Arc<dyn FunctionCatalog>) -> Self {
+        self.catalog = Some(catalog);
+        self
+    }
+
+    /// registers handler for given language (case-insensitive),
+    /// replacing handler previously registered for it
+    pub fn with_handler(mut self, language: &str, handler: Arc<dyn LanguageHandler>) -> Self {
+        self.handlers.insert(language.to_lowercase(), handler);
+        self
+    }
+
+    /// creates function from `CREATE FUNCTION` statement and its options,
+    /// using handler registered for statement's language.
+    ///
+    /// existing function is replaced only by `CREATE OR REPLACE FUNCTION`
+    pub async fn create_with_options(
+        &self,
+        state: &SessionState,
+        statement: CreateFunction,
+        options: &FunctionOptions,
+    ) -> datafusion::common::Result<RegisterFunction> {
+        if !statement.or_replace && function_exists(state, &statement.name) {
+            return exec_err!(
+                "function: {} already exists, use CREATE OR REPLACE FUNCTION to replace it",
+                statement.name
+            );
+        }
+        let stored = self.catalog.is_some() || self.cluster_registry;
+        let definition = match stored && !statement.temporary {
+            true => Some(FunctionDefinition::try_new(&statement, options)?),
+            false => None,
+        };
+        let function = self.dispatch(state, statement, options).await?;
+        // stored only if function has been created
+        if let Some(definition) = definition {
+            if let Some(catalog) = &self.catalog {
+                catalog.save(&definition)?;
+            }
+            if self.cluster_registry {
+                let command = ClusterFunctionCommand::Save(definition);
+                cluster::execute_command(state, command, &self.cluster_registry_token).await?;
+            }
+        }
+
+        Ok(function)
+    }
+
+    /// removes function from the catalog and cluster registry, and from
+    /// decode caches of this process, returns `false` if none of them
+    /// had such function
+    pub async fn drop_function(&self, state: &SessionState, name: &str) -> datafusion::common::Result<bool> {
+        crate::codec::evict_decoded(name);
+        self.remove_stored(state, name).await
+    }
+
+    async fn remove_stored(&self, state: &SessionState, name: &str) -> datafusion::common::Result<bool> {
+        let mut removed = false;
+        if let Some(catalog) = &self.catalog {
+            removed |= catalog.remove(name)?;
+        }
+        if self.cluster_registry {
+            let command = ClusterFunctionCommand::Remove(name.to_string());
+            removed |= !cluster::execute_command(state, command, &self.cluster_registry_token)
+                .await?
+                .is_empty();
+        }
+        log::debug!("factory::remove_stored - function: {name}, removed: {removed}");
+
+        Ok(removed)
+    }
+
+    /// creates functions stored in the catalog and cluster registry,
+    /// functions which can't be created any more are skipped.
+    /// cluster functions replace catalog functions with the same name
+    pub async fn catalog_functions(&self, state: &SessionState) -> datafusion::common::Result<Vec<RegisterFunction>> {
+        let mut definitions = match &self.catalog {
+            Some(catalog) => catalog.load()?,
+            None => vec![],
+        };
+        if self.cluster_registry {
+            let command = ClusterFunctionCommand::List;
+            let cluster_functions = cluster::execute_command(state, command, &self.cluster_registry_token).await?;
+            definitions.extend(cluster_functions.into_iter().filter_map(|(_, d)| d));
+        }
+        let mut functions = vec![];
+        for definition in definitions {
+            let (statement, options) = definition.to_statement();
+            match self.dispatch(state, statement, &options).await {
+                Ok(function) => functions.push(function),
+                Err(e) => log::warn!("function: {} can't be created from catalog: {e}", definition.name),
+            }
+        }
+
+        Ok(functions)
+    }
+
+    async fn dispatch(
+        &self,
+        state: &SessionState,
+        statement: CreateFunction,
+        options: &FunctionOptions,
+    ) -> datafusion::common::Result<RegisterFunction> {
+        let languages = self.handlers.keys().collect::<Vec<_>>();
+        let language = match &statement.params.language {
+            Some(language) => language.value.to_lowercase(),
+            None => {
+                return exec_err!(
+                    "function: {} has no LANGUAGE, supported languages: {languages:?}",
+                    statement.name
+                )
+            }
+        };
+        match self.handlers.get(&language) {
+            Some(handler) => {
+                log::debug!("factory::create - function: {}, language: {language}", statement.name);
+                handler.create(state, statement, options).await
+            }
+            None => exec_err!(
+                "function: {} has unsupported LANGUAGE {language}, supported languages: {languages:?}",
+                statement.name
+            ),
+        }
+    }
+}
+
+fn function_exists(state: &SessionState, name: &str) -> bool {
+    state.scalar_functions().contains_key(name)
+        || state.aggregate_functions().contains_key(name)
+        || state.window_functions().contains_key(name)
+        || state.table_functions().contains_key(name)
+}
+
+#[async_trait::async_trait]
+impl FunctionFactory for PythonFunctionFactory {
+    async fn create(
+        &self,
+        state: &SessionState,
+        statement: CreateFunction,
+    ) -> datafusion::common::Result<RegisterFunction> {
+        // options of statement executed by python_sql
+        let options = STATEMENT_OPTIONS
+            .try_with(|options| options.borrow_mut().take())
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        self.create_with_options(state, statement, &options).await
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::factory::PythonFunctionFactory;
+    use crate::factory::{FunctionOptions, LanguageHandler, PythonFunctionFactory};
     use datafusion::arrow::array::{ArrayRef, Float64Array, RecordBatch};
+    use datafusion::arrow::datatypes::DataType;
     use datafusion::assert_batches_eq;
+    use datafusion::execution::context::RegisterFunction;
+    use datafusion::execution::SessionState;
+    use datafusion::logical_expr::{create_udf, ColumnarValue, CreateFunction, Volatility};
     use datafusion::prelude::SessionContext;
     use std::sync::Arc;
 
+    /// handler creating identity function, whatever the body is
+    #[derive(Debug)]
+    struct IdentityHandler {}
+
+    #[async_trait::async_trait]
+    impl LanguageHandler for IdentityHandler {
+        async fn create(
+            &self,
+            _state: &SessionState,
+            statement: CreateFunction,
+            _options: &FunctionOptions,
+        ) -> datafusion::common::Result<RegisterFunction> {
+            let udf = create_udf(
+                &statement.name,
+                vec![DataType::Int64],
+                DataType::Int64,
+                Volatility::Immutable,
+                Arc::new(|args: &[ColumnarValue]| Ok(args[0].clone())),
+            );
+            Ok(RegisterFunction::Scalar(Arc::new(udf)))
+        }
+    }
+
+    #[tokio::test]
+    async fn should_dispatch_by_language() -> datafusion::common::Result<()> {
+        let factory = PythonFunctionFactory::default().with_handler("Identity", Arc::new(IdentityHandler {}));
+        let ctx = SessionContext::new().with_function_factory(Arc::new(factory));
+
+        ctx.sql("CREATE FUNCTION same(BIGINT) RETURNS BIGINT LANGUAGE IDENTITY AS 'anything'")
+            .await?;
+        let result = ctx.sql("select same(42) as a").await?.collect().await?;
+        let expected = ["+----+", "| a  |", "+----+", "| 42 |", "+----+"];
+        assert_batches_eq!(expected, &result);
+
+        let message = ctx
+            .sql("CREATE FUNCTION other(BIGINT) RETURNS BIGINT LANGUAGE JAVASCRIPT AS 'anything'")
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("unsupported LANGUAGE javascript"));
+        assert!(message.contains("python"));
+
+        assert!(ctx
+            .sql("CREATE FUNCTION other(BIGINT) RETURNS BIGINT AS 'anything'")
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn basic_example() -> datafusion::common::Result<()> {
         crate::setup_python().expect("python environment to be set");
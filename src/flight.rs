@@ -0,0 +1,156 @@
+use crate::capabilities::{PythonCapabilities, ACTION_PYTHON_CAPABILITIES};
+use arrow_flight::flight_service_client::FlightServiceClient;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightInfo, HandshakeRequest, PollInfo,
+    SchemaResult, Ticket,
+};
+use ballista_core::error::BallistaError;
+use ballista_core::utils::{create_grpc_client_connection, create_grpc_server};
+use ballista_executor::executor_process::ExecutorProcessConfig;
+use ballista_executor::flight_service::BallistaFlightService;
+use ballista_executor::shutdown::Shutdown;
+use ballista_executor::ArrowFlightServerProvider;
+use datafusion::common::{exec_datafusion_err, Result};
+use futures::StreamExt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::{Request, Response, Status, Streaming};
+
+type Inner = BallistaFlightService;
+
+/// Ballista arrow flight service, advertising executor's
+/// [PythonCapabilities] with [ACTION_PYTHON_CAPABILITIES] action.
+#[derive(Clone)]
+pub struct PythonFlightService {
+    inner: Inner,
+    capabilities: Arc<Vec<u8>>,
+}
+
+impl PythonFlightService {
+    pub fn new(capabilities: &PythonCapabilities) -> Self {
+        Self {
+            inner: BallistaFlightService::new(),
+            capabilities: Arc::new(capabilities.encode_to_vec()),
+        }
+    }
+
+    /// flight server provider, to be used as executor's
+    /// `override_arrow_flight_service`
+    pub fn provider(
+        capabilities: &PythonCapabilities,
+        config: &ExecutorProcessConfig,
+    ) -> Arc<ArrowFlightServerProvider> {
+        let service = Self::new(capabilities);
+        let max_encoding_message_size = config.grpc_max_encoding_message_size as usize;
+        let max_decoding_message_size = config.grpc_max_decoding_message_size as usize;
+
+        Arc::new(move |address: SocketAddr, mut shutdown: Shutdown| {
+            let service = service.clone();
+            tokio::spawn(async move {
+                log::info!("python arrow flight server listening on: {address:?}");
+                create_grpc_server()
+                    .add_service(
+                        FlightServiceServer::new(service)
+                            .max_encoding_message_size(max_encoding_message_size)
+                            .max_decoding_message_size(max_decoding_message_size),
+                    )
+                    .serve_with_shutdown(address, shutdown.recv())
+                    .await
+                    .map_err(BallistaError::TonicError)
+            })
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl FlightService for PythonFlightService {
+    type HandshakeStream = <Inner as FlightService>::HandshakeStream;
+    type ListFlightsStream = <Inner as FlightService>::ListFlightsStream;
+    type DoGetStream = <Inner as FlightService>::DoGetStream;
+    type DoPutStream = <Inner as FlightService>::DoPutStream;
+    type DoExchangeStream = <Inner as FlightService>::DoExchangeStream;
+    type DoActionStream = <Inner as FlightService>::DoActionStream;
+    type ListActionsStream = <Inner as FlightService>::ListActionsStream;
+
+    async fn handshake(
+        &self,
+        request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        self.inner.handshake(request).await
+    }
+
+    async fn list_flights(&self, request: Request<Criteria>) -> Result<Response<Self::ListFlightsStream>, Status> {
+        self.inner.list_flights(request).await
+    }
+
+    async fn get_flight_info(&self, request: Request<FlightDescriptor>) -> Result<Response<FlightInfo>, Status> {
+        self.inner.get_flight_info(request).await
+    }
+
+    async fn poll_flight_info(&self, request: Request<FlightDescriptor>) -> Result<Response<PollInfo>, Status> {
+        self.inner.poll_flight_info(request).await
+    }
+
+    async fn get_schema(&self, request: Request<FlightDescriptor>) -> Result<Response<SchemaResult>, Status> {
+        self.inner.get_schema(request).await
+    }
+
+    async fn do_get(&self, request: Request<Ticket>) -> Result<Response<Self::DoGetStream>, Status> {
+        self.inner.do_get(request).await
+    }
+
+    async fn do_put(&self, request: Request<Streaming<FlightData>>) -> Result<Response<Self::DoPutStream>, Status> {
+        self.inner.do_put(request).await
+    }
+
+    async fn do_exchange(
+        &self,
+        request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        self.inner.do_exchange(request).await
+    }
+
+    async fn do_action(&self, request: Request<Action>) -> Result<Response<Self::DoActionStream>, Status> {
+        if request.get_ref().r#type == ACTION_PYTHON_CAPABILITIES {
+            let result = arrow_flight::Result {
+                body: self.capabilities.as_ref().clone().into(),
+            };
+            return Ok(Response::new(Box::pin(futures::stream::iter([Ok(result)]))));
+        }
+        self.inner.do_action(request).await
+    }
+
+    async fn list_actions(&self, request: Request<Empty>) -> Result<Response<Self::ListActionsStream>, Status> {
+        let actions = self.inner.list_actions(request).await?.into_inner();
+        let python_actions = futures::stream::iter([Ok(ActionType {
+            r#type: ACTION_PYTHON_CAPABILITIES.to_owned(),
+            description: "python version and installed packages".to_owned(),
+        })]);
+
+        Ok(Response::new(Box::pin(actions.chain(python_actions))))
+    }
+}
+
+/// fetches python capabilities from executor's flight service,
+/// returns `None` if executor does not advertise them
+pub async fn fetch_capabilities(host: &str, port: u16) -> Result<Option<PythonCapabilities>> {
+    let connection = create_grpc_client_connection(format!("http://{host}:{port}"))
+        .await
+        .map_err(|e| exec_datafusion_err!("executor {host}:{port} can't be reached: {e}"))?;
+    let action = Action {
+        r#type: ACTION_PYTHON_CAPABILITIES.to_owned(),
+        body: Default::default(),
+    };
+    let mut results = match FlightServiceClient::new(connection).do_action(action).await {
+        Ok(results) => results.into_inner(),
+        Err(status) if status.code() == tonic::Code::Unimplemented => return Ok(None),
+        Err(status) => return Err(exec_datafusion_err!("executor {host}:{port} capabilities: {status}")),
+    };
+
+    match results.next().await {
+        Some(Ok(result)) => Ok(Some(PythonCapabilities::decode(&result.body)?)),
+        Some(Err(status)) => Err(exec_datafusion_err!("executor {host}:{port} capabilities: {status}")),
+        None => Ok(None),
+    }
+}
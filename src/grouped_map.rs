@@ -0,0 +1,420 @@
+use crate::capsule::PyArrowArray;
+use crate::coroutine::to_py_values;
+use crate::map_batches::{spawn_python, MapBatchesFunction, Output};
+use crate::udf::CallMode;
+use datafusion::arrow::array::{ArrayRef, RecordBatch, StructArray};
+use datafusion::arrow::compute::{concat_batches, partition};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::pyarrow::ToPyArrow;
+use datafusion::common::{exec_err, plan_err, DFSchema, DFSchemaRef, Result, ScalarValue};
+use datafusion::error::DataFusionError;
+use datafusion::execution::{SendableRecordBatchStream, SessionState, TaskContext};
+use datafusion::logical_expr::{Expr, Extension, LogicalPlan, UserDefinedLogicalNode, UserDefinedLogicalNodeCore};
+use datafusion::physical_expr::{
+    Distribution, EquivalenceProperties, LexOrdering, OrderingRequirements, PhysicalExpr, PhysicalSortExpr,
+};
+use datafusion::physical_plan::execution_plan::EmissionType;
+use datafusion::physical_plan::repartition::RepartitionExec;
+use datafusion::physical_plan::sorts::sort::SortExec;
+use datafusion::physical_plan::{DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, PlanProperties};
+use datafusion::physical_planner::{ExtensionPlanner, PhysicalPlanner};
+use datafusion::prelude::DataFrame;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyTuple;
+use std::any::Any;
+use std::sync::Arc;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+/// Logical plan node applying python function to each group of rows
+/// with the same keys (grouped map), like `applyInPandas` of spark.
+///
+/// Function is called with all rows of a group as one `pyarrow` table,
+/// `pandas` data frame, or object implementing arrow PyCapsule interface,
+/// according to function mode, and returns rows of declared schema, see
+/// [MapBatchesFunction] for accepted results. Function accepting two
+/// arguments is called with tuple of group keys as well.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ApplyPythonNode {
+    pub input: LogicalPlan,
+    pub keys: Vec<Expr>,
+    pub function: MapBatchesFunction,
+    schema: DFSchemaRef,
+}
+
+impl ApplyPythonNode {
+    pub fn try_new(input: LogicalPlan, keys: Vec<Expr>, function: MapBatchesFunction) -> Result<Self> {
+        if keys.is_empty() {
+            return plan_err!(
+                "function: {} has to be applied to groups of at least one key",
+                function.name()
+            );
+        }
+        let schema = Arc::new(DFSchema::try_from(function.schema().as_ref().clone())?);
+
+        Ok(Self {
+            input,
+            keys,
+            function,
+            schema,
+        })
+    }
+}
+
+impl PartialOrd for ApplyPythonNode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        (&self.input, &self.keys, self.function.name()).partial_cmp(&(&other.input, &other.keys, other.function.name()))
+    }
+}
+
+impl UserDefinedLogicalNodeCore for ApplyPythonNode {
+    fn name(&self) -> &str {
+        "ApplyPython"
+    }
+
+    fn inputs(&self) -> Vec<&LogicalPlan> {
+        vec![&self.input]
+    }
+
+    fn schema(&self) -> &DFSchemaRef {
+        &self.schema
+    }
+
+    fn expressions(&self) -> Vec<Expr> {
+        self.keys.clone()
+    }
+
+    fn fmt_for_explain(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let keys = self.keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        write!(
+            f,
+            "ApplyPython: function={}, keys=[{}]",
+            self.function.name(),
+            keys.join(", ")
+        )
+    }
+
+    fn with_exprs_and_inputs(&self, exprs: Vec<Expr>, mut inputs: Vec<LogicalPlan>) -> Result<Self> {
+        match inputs.pop() {
+            Some(input) if inputs.is_empty() => Self::try_new(input, exprs, self.function.clone()),
+            _ => plan_err!("apply python node expects single input"),
+        }
+    }
+}
+
+/// Execution plan applying python function to groups of rows.
+///
+/// Input is hash partitioned and sorted by group keys, so rows
+/// of a group are contiguous in a single partition. Rows of a group
+/// are collected and passed to the function once the group ends.
+#[derive(Debug, Clone)]
+pub struct ApplyPythonExec {
+    input: Arc<dyn ExecutionPlan>,
+    keys: Vec<Arc<dyn PhysicalExpr>>,
+    function: MapBatchesFunction,
+    properties: PlanProperties,
+}
+
+impl ApplyPythonExec {
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        keys: Vec<Arc<dyn PhysicalExpr>>,
+        function: MapBatchesFunction,
+    ) -> Result<Self> {
+        if keys.is_empty() {
+            return exec_err!(
+                "function: {} has to be applied to groups of at least one key",
+                function.name()
+            );
+        }
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(function.schema().clone()),
+            Partitioning::UnknownPartitioning(input.properties().output_partitioning().partition_count()),
+            EmissionType::Incremental,
+            input.properties().boundedness,
+        );
+
+        Ok(Self {
+            input,
+            keys,
+            function,
+            properties,
+        })
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn keys(&self) -> &[Arc<dyn PhysicalExpr>] {
+        &self.keys
+    }
+
+    pub fn function(&self) -> &MapBatchesFunction {
+        &self.function
+    }
+
+    fn ordering(&self) -> LexOrdering {
+        key_ordering(&self.keys).expect("at least one key")
+    }
+
+    fn run(
+        &self,
+        input_schema: SchemaRef,
+        mut input: Receiver<Result<RecordBatch>>,
+        output: Sender<Result<RecordBatch>>,
+    ) -> Result<()> {
+        let udf = self.function.python_udf();
+        log::debug!("grouped_map::run - function: {}", udf.name);
+        Python::with_gil(|py| {
+            let func = udf.callable(py)?;
+            let func = func.bind(py);
+            let with_key = accepts_key(py, func)?;
+            let mut output = Output::new(&output, self.function.schema());
+            let mut group = Group::default();
+            while let Some(batch) = py.allow_threads(|| input.blocking_recv()) {
+                let batch = batch.map_err(to_py_err)?;
+                let keys = self
+                    .keys
+                    .iter()
+                    .map(|k| k.evaluate(&batch)?.into_array(batch.num_rows()))
+                    .collect::<Result<Vec<ArrayRef>>>()
+                    .map_err(to_py_err)?;
+                // input is sorted, so rows with the same keys are contiguous
+                for range in partition(&keys).map_err(|e| to_py_err(e.into()))?.ranges() {
+                    let key = keys
+                        .iter()
+                        .map(|k| ScalarValue::try_from_array(k, range.start))
+                        .collect::<Result<Vec<_>>>()
+                        .map_err(to_py_err)?;
+                    if group.key.as_ref().is_some_and(|k| *k != key) {
+                        group.apply(py, func, with_key, &input_schema, udf.mode, &mut output)?;
+                    }
+                    group.key = Some(key);
+                    group.batches.push(batch.slice(range.start, range.end - range.start));
+                }
+                if output.closed {
+                    return Ok(());
+                }
+            }
+            group.apply(py, func, with_key, &input_schema, udf.mode, &mut output)
+        })
+        .map_err(|e| DataFusionError::Execution(format!("python function: {} failed: {e}", udf.name)))
+    }
+}
+
+impl DisplayAs for ApplyPythonExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let keys = self.keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        write!(
+            f,
+            "ApplyPythonExec: function={}, keys=[{}]",
+            self.function.name(),
+            keys.join(", ")
+        )
+    }
+}
+
+impl ExecutionPlan for ApplyPythonExec {
+    fn name(&self) -> &str {
+        "ApplyPythonExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn required_input_distribution(&self) -> Vec<Distribution> {
+        vec![Distribution::HashPartitioned(self.keys.clone())]
+    }
+
+    fn required_input_ordering(&self) -> Vec<Option<OrderingRequirements>> {
+        vec![Some(OrderingRequirements::from(self.ordering()))]
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(self: Arc<Self>, mut children: Vec<Arc<dyn ExecutionPlan>>) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.pop() {
+            Some(input) if children.is_empty() => Ok(Arc::new(Self::try_new(
+                input,
+                self.keys.clone(),
+                self.function.clone(),
+            )?)),
+            _ => exec_err!("apply python plan expects single input"),
+        }
+    }
+
+    fn execute(&self, partition: usize, context: Arc<TaskContext>) -> Result<SendableRecordBatchStream> {
+        let input = self.input.execute(partition, context)?;
+        let input_schema = self.input.schema();
+        let exec = self.clone();
+
+        Ok(spawn_python(
+            self.function.schema().clone(),
+            input,
+            move |input, output| exec.run(input_schema, input, output),
+        ))
+    }
+}
+
+/// rows of the current group
+#[derive(Default)]
+struct Group {
+    key: Option<Vec<ScalarValue>>,
+    batches: Vec<RecordBatch>,
+}
+
+impl Group {
+    /// calls the function with rows of the group, and clears it
+    fn apply(
+        &mut self,
+        py: Python<'_>,
+        func: &Bound<'_, PyAny>,
+        with_key: bool,
+        schema: &SchemaRef,
+        mode: CallMode,
+        output: &mut Output<'_>,
+    ) -> PyResult<()> {
+        let Some(key) = self.key.take() else {
+            return Ok(());
+        };
+        let batch = concat_batches(schema, &std::mem::take(&mut self.batches)).map_err(|e| to_py_err(e.into()))?;
+        let rows = to_py_group(py, batch, mode)?;
+        let result = match with_key {
+            true => func.call1((to_py_key(py, &key)?, rows))?,
+            false => func.call1((rows,))?,
+        };
+
+        output.emit(py, &result)
+    }
+}
+
+/// rows of a group passed to python function according to function mode
+fn to_py_group(py: Python<'_>, batch: RecordBatch, mode: CallMode) -> PyResult<PyObject> {
+    match mode {
+        CallMode::Arrow => Ok(py
+            .import("pyarrow")?
+            .getattr("Table")?
+            .call_method1("from_batches", (vec![batch.to_pyarrow(py)?],))?
+            .unbind()),
+        CallMode::Pandas => batch.to_pyarrow(py)?.call_method0(py, "to_pandas"),
+        CallMode::Capsule => {
+            let array = PyArrowArray::new(StructArray::from(batch).into());
+            Ok(Bound::new(py, array)?.into_any().unbind())
+        }
+    }
+}
+
+/// tuple of group key values
+fn to_py_key<'py>(py: Python<'py>, key: &[ScalarValue]) -> PyResult<Bound<'py, PyTuple>> {
+    let values = key
+        .iter()
+        .map(|value| {
+            let array = value.to_array().map_err(to_py_err)?;
+            Ok(to_py_values(py, &array)?.remove(0))
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+
+    PyTuple::new(py, values)
+}
+
+/// function accepting two arguments is called with group key as well
+fn accepts_key(py: Python<'_>, func: &Bound<'_, PyAny>) -> PyResult<bool> {
+    match py.import("inspect")?.call_method1("signature", (func,)) {
+        Ok(signature) => Ok(signature.getattr("parameters")?.len()? == 2),
+        // signature of some builtins can't be inspected
+        Err(_) => Ok(false),
+    }
+}
+
+fn to_py_err(e: DataFusionError) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+fn key_ordering(keys: &[Arc<dyn PhysicalExpr>]) -> Option<LexOrdering> {
+    LexOrdering::new(keys.iter().map(|k| PhysicalSortExpr::new_default(k.clone())))
+}
+
+/// Plans [ApplyPythonNode] as [ApplyPythonExec] of input hash
+/// partitioned by group keys, which makes a stage boundary in
+/// ballista, and sorted by the keys
+#[derive(Debug, Default)]
+pub struct ApplyPythonPlanner {}
+
+#[async_trait::async_trait]
+impl ExtensionPlanner for ApplyPythonPlanner {
+    async fn plan_extension(
+        &self,
+        planner: &dyn PhysicalPlanner,
+        node: &dyn UserDefinedLogicalNode,
+        logical_inputs: &[&LogicalPlan],
+        physical_inputs: &[Arc<dyn ExecutionPlan>],
+        session_state: &SessionState,
+    ) -> Result<Option<Arc<dyn ExecutionPlan>>> {
+        let Some(node) = node.as_any().downcast_ref::<ApplyPythonNode>() else {
+            return Ok(None);
+        };
+        let ([logical_input], [input]) = (logical_inputs, physical_inputs) else {
+            return plan_err!("apply python node expects single input");
+        };
+        let keys = node
+            .keys
+            .iter()
+            .map(|k| planner.create_physical_expr(k, logical_input.schema(), session_state))
+            .collect::<Result<Vec<_>>>()?;
+        let Some(ordering) = key_ordering(&keys) else {
+            return plan_err!("function: {} has no group keys", node.function.name());
+        };
+        let partitions = session_state.config().target_partitions();
+        let input = Arc::new(RepartitionExec::try_new(
+            input.clone(),
+            Partitioning::Hash(keys.clone(), partitions),
+        )?);
+        let input = Arc::new(SortExec::new(ordering, input).with_preserve_partitioning(true));
+
+        Ok(Some(Arc::new(ApplyPythonExec::try_new(
+            input,
+            keys,
+            node.function.clone(),
+        )?)))
+    }
+}
+
+/// Applies python functions to groups of data frame rows
+pub trait GroupedMapExt {
+    /// groups rows by keys, see [GroupedDataFrame::apply_python]
+    fn group_by(self, keys: Vec<Expr>) -> GroupedDataFrame;
+}
+
+impl GroupedMapExt for DataFrame {
+    fn group_by(self, keys: Vec<Expr>) -> GroupedDataFrame {
+        GroupedDataFrame { df: self, keys }
+    }
+}
+
+/// Data frame grouped by keys
+#[derive(Debug, Clone)]
+pub struct GroupedDataFrame {
+    df: DataFrame,
+    keys: Vec<Expr>,
+}
+
+impl GroupedDataFrame {
+    /// applies the function to rows of each group, see [ApplyPythonNode]
+    pub fn apply_python(self, function: MapBatchesFunction) -> Result<DataFrame> {
+        let (state, plan) = self.df.into_parts();
+        let node = ApplyPythonNode::try_new(plan, self.keys, function)?;
+
+        Ok(DataFrame::new(
+            state,
+            LogicalPlan::Extension(Extension { node: Arc::new(node) }),
+        ))
+    }
+}
@@ -1,40 +1,78 @@
+use env::PythonEnvConfig;
 use pyo3::{types::PyAnyMethods, Python};
 
+/// python environment archives shipped with a job.
+pub mod archive;
+/// content addressed store of artifacts shipped with jobs.
+pub mod artifacts;
+/// multi-file python module bundles shipped with functions.
+pub mod bundle;
+/// python capabilities advertised by executors.
+pub mod capabilities;
+/// arrow pycapsule interface of arrays passed to and returned by functions.
+pub mod capsule;
+/// persistent catalog of function definitions.
+pub mod catalog;
+/// cluster-wide function registry hosted by the scheduler.
+pub mod cluster;
 /// custom codecs which knows how to serialize
 /// python UDFs.
 pub mod codec;
+/// conversion of functions defined with datafusion-python.
+pub mod compat;
+/// `async def` python functions awaited on per-process event loop.
+pub mod coroutine;
+/// python environment (venv, python path, python home) configuration.
+pub mod env;
 /// function factory handler, handles `CREATE FUNCTION` statements.
 pub mod factory;
-/// wrapper around python `cloudpickle` library
-/// used to serialize python functions.
+/// arrow flight service advertising executor python capabilities.
+pub mod flight;
+/// python functions applied to groups of rows (grouped map).
+pub mod grouped_map;
+/// python functions transforming record batches of partitions.
+pub mod map_batches;
+/// python function serializers, wrapping `cloudpickle`,
+/// `dill` and standard library `pickle`.
 pub mod pickle;
+/// task placement on executors satisfying python function requirements.
+pub mod placement;
+/// python extension module exposing ballista client.
+pub mod python;
+/// registry of python functions pre-installed at executors.
+pub mod registry;
+/// python packages required by functions.
+pub mod requirements;
+/// signing and verification of serialized python functions.
+pub mod signing;
+/// sql extensions, like `CREATE FUNCTION` options.
+pub mod sql;
+/// class based python functions, instantiated once per process or task.
+pub mod stateful;
 /// datafusion (rust) UDF python function wrapper.
 pub mod udf;
+/// module pre-import and warm-up at process start.
+pub mod warmup;
+/// worker processes calling functions shipped with python environments.
+pub mod worker;
 
+/// setups python using [PythonEnvConfig] configured from environment variables
 pub fn setup_python() -> pyo3::PyResult<()> {
-    setup_python_path()?;
+    setup_python_with(&PythonEnvConfig::from_env())
+}
+
+/// setups python using given environment configuration,
+/// should be called at process start
+pub fn setup_python_with(config: &PythonEnvConfig) -> pyo3::PyResult<()> {
+    config.apply()?;
     assign_signal_check()?;
     Ok(())
 }
 
-/// setups python search path
-// haven't put much effort to find the best way
-// to setup python path
+/// setups python search path using [PythonEnvConfig]
+/// configured from environment variables
 pub fn setup_python_path() -> pyo3::PyResult<()> {
-    log::debug!("setting up python path ...");
-    Python::with_gil(|py| -> pyo3::PyResult<()> {
-        let version = py.version_info();
-        let sys = py.import("sys")?;
-        let path = sys.getattr("path")?;
-        path.call_method1(
-            "append",
-            (format!(
-                ".venv/lib/python{}.{}/site-packages",
-                version.major, version.minor
-            ),),
-        )?;
-        Ok(())
-    })
+    PythonEnvConfig::from_env().apply()
 }
 
 /// assign python signal check to shut down interpreter properly
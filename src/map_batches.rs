@@ -0,0 +1,649 @@
+use crate::capsule::PyArrowArray;
+use crate::grouped_map::ApplyPythonPlanner;
+use crate::stateful;
+use crate::udf::{CallMode, PythonUDF};
+use datafusion::arrow::array::{RecordBatch, StructArray};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{DataType, SchemaRef};
+use datafusion::arrow::ffi_stream::ArrowArrayStreamReader;
+use datafusion::arrow::pyarrow::{FromPyArrow, ToPyArrow};
+use datafusion::catalog::{Session, TableFunctionImpl, TableProvider};
+use datafusion::common::{exec_datafusion_err, exec_err, plan_err, DFSchema, DFSchemaRef, Result};
+use datafusion::datasource::TableType;
+use datafusion::execution::context::QueryPlanner;
+use datafusion::execution::{SendableRecordBatchStream, SessionState, TaskContext};
+use datafusion::logical_expr::{
+    Expr, Extension, LogicalPlan, LogicalPlanBuilder, ScalarUDF, UserDefinedLogicalNode, UserDefinedLogicalNodeCore,
+    Volatility,
+};
+use datafusion::physical_expr::EquivalenceProperties;
+use datafusion::physical_plan::execution_plan::EmissionType;
+use datafusion::physical_plan::stream::RecordBatchReceiverStreamBuilder;
+use datafusion::physical_plan::{DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, PlanProperties};
+use datafusion::physical_planner::{DefaultPhysicalPlanner, ExtensionPlanner, PhysicalPlanner};
+use datafusion::prelude::DataFrame;
+use futures::StreamExt;
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::any::Any;
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{Receiver, Sender};
+
+/// number of batches buffered between input,
+/// python function and output of a partition
+const BUFFERED_BATCHES: usize = 2;
+
+/// Python function transforming record batches of a partition
+/// to batches of declared schema, possibly with different number
+/// of rows.
+///
+/// Function is called with each batch of the partition, or once
+/// with an iterator over batches of the partition if
+/// [MapBatchesFunction::with_iterator] is set. Batches are passed
+/// according to function mode, `pyarrow` record batches by default.
+///
+/// Function can return a record batch, anything exposing arrow
+/// PyCapsule interface (`__arrow_c_array__` or `__arrow_c_stream__`),
+/// like `pyarrow` tables, `pandas` data frame, `None`, or iterable
+/// of these, like a list or a generator.
+#[derive(Debug, Clone)]
+pub struct MapBatchesFunction {
+    /// python function shipped as a [PythonUDF] without arguments,
+    /// so it is shipped the same way as scalar functions
+    function: Arc<ScalarUDF>,
+    schema: SchemaRef,
+    iterator: bool,
+}
+
+impl MapBatchesFunction {
+    /// creates function of python function object
+    pub fn new(name: impl Into<String>, func: PyObject, schema: SchemaRef) -> Self {
+        Self::from_udf(
+            PythonUDF::new(name, vec![], DataType::Null, Volatility::Volatile, func),
+            schema,
+        )
+    }
+
+    /// creates function of python function, its input and return
+    /// types are ignored, mode and shipping options are honored
+    pub fn from_udf(udf: PythonUDF, schema: SchemaRef) -> Self {
+        Self {
+            function: Arc::new(ScalarUDF::from(udf)),
+            schema,
+            iterator: false,
+        }
+    }
+
+    /// creates function of `name` function in the code,
+    /// shipped as source
+    pub fn from_code(name: &str, code: &str, schema: SchemaRef) -> Result<Self> {
+        let udf = PythonUDF::from_code_with_types(name, code, vec![], DataType::Null)?;
+
+        Ok(Self::from_udf(udf, schema))
+    }
+
+    /// function is called once per partition, with an
+    /// iterator over its batches, instead of once per batch
+    pub fn with_iterator(mut self, iterator: bool) -> Self {
+        self.iterator = iterator;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        self.function.name()
+    }
+
+    /// output schema of the function
+    pub fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    pub fn iterator(&self) -> bool {
+        self.iterator
+    }
+
+    /// python function, shipped by codecs as scalar function
+    pub fn udf(&self) -> &Arc<ScalarUDF> {
+        &self.function
+    }
+
+    pub fn python_udf(&self) -> &PythonUDF {
+        self.function
+            .inner()
+            .as_any()
+            .downcast_ref::<PythonUDF>()
+            .expect("python function")
+    }
+
+    /// recreates function of decoded python function
+    pub fn try_from_udf(function: Arc<ScalarUDF>, schema: SchemaRef, iterator: bool) -> Result<Self> {
+        match function.inner().as_any().downcast_ref::<PythonUDF>() {
+            None => return exec_err!("function: {} is not a python function", function.name()),
+            // batches are not passed to environment workers
+            Some(udf) if udf.worker.is_some() || udf.environment.is_some() => {
+                return exec_err!(
+                    "function: {} is shipped with python environment, which is supported for scalar functions only",
+                    function.name()
+                )
+            }
+            Some(_) => {}
+        }
+
+        Ok(Self {
+            function,
+            schema,
+            iterator,
+        })
+    }
+
+    /// applies the function to batches of the input stream,
+    /// python function is called by a blocking task, which
+    /// releases the GIL while waiting for input batches
+    pub fn map(&self, input: SendableRecordBatchStream) -> SendableRecordBatchStream {
+        let function = self.clone();
+        spawn_python(self.schema.clone(), input, move |input, output| {
+            function.run(input, output)
+        })
+    }
+
+    fn run(&self, mut input: Receiver<Result<RecordBatch>>, output: Sender<Result<RecordBatch>>) -> Result<()> {
+        let udf = self.python_udf();
+        log::debug!("map_batches::run - function: {}, iterator: {}", udf.name, self.iterator);
+        Python::with_gil(|py| {
+            let func = udf.callable(py)?;
+            let func = func.bind(py);
+            let mut output = Output::new(&output, &self.schema);
+            if self.iterator {
+                let batches = PyBatchIterator {
+                    input: Mutex::new(input),
+                    mode: udf.mode,
+                };
+                return output.emit(py, &func.call1((batches,))?);
+            }
+            while let Some(batch) = py.allow_threads(|| input.blocking_recv()) {
+                let batch = batch.map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+                output.emit(py, &func.call1((to_py_batch(py, batch, udf.mode)?,))?)?;
+                if output.closed {
+                    break;
+                }
+            }
+            Ok(())
+        })
+        .map_err(|e| exec_datafusion_err!("python function: {} failed: {e}", udf.name))
+    }
+}
+
+impl PartialEq for MapBatchesFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.function == other.function && self.schema == other.schema && self.iterator == other.iterator
+    }
+}
+
+impl Eq for MapBatchesFunction {}
+
+impl Hash for MapBatchesFunction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.function.hash(state);
+        self.schema.hash(state);
+        self.iterator.hash(state);
+    }
+}
+
+/// Iterator over batches of a partition, passed to python
+/// functions called with [MapBatchesFunction::with_iterator]
+#[pyclass(name = "BatchIterator", module = "ballista_python")]
+struct PyBatchIterator {
+    input: Mutex<Receiver<Result<RecordBatch>>>,
+    mode: CallMode,
+}
+
+#[pymethods]
+impl PyBatchIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let batch = py.allow_threads(|| self.input.lock().unwrap().blocking_recv());
+        match batch {
+            Some(Ok(batch)) => Ok(Some(to_py_batch(py, batch, self.mode)?)),
+            Some(Err(e)) => Err(PyRuntimeError::new_err(e.to_string())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// runs python task, consuming input batches and sending
+/// output batches, as a blocking task of the returned stream.
+/// task has to release the GIL while waiting for input batches
+pub(crate) fn spawn_python<F>(
+    schema: SchemaRef,
+    mut input: SendableRecordBatchStream,
+    task: F,
+) -> SendableRecordBatchStream
+where
+    F: FnOnce(Receiver<Result<RecordBatch>>, Sender<Result<RecordBatch>>) -> Result<()> + Send + 'static,
+{
+    let mut builder = RecordBatchReceiverStreamBuilder::new(schema, BUFFERED_BATCHES);
+    let output = builder.tx();
+    let (input_tx, input_rx) = tokio::sync::mpsc::channel(BUFFERED_BATCHES);
+    builder.spawn(async move {
+        while let Some(batch) = input.next().await {
+            if input_tx.send(batch).await.is_err() {
+                // function has completed without consuming all batches
+                break;
+            }
+        }
+        Ok(())
+    });
+    // blocking task calls functions in scope of the executed task
+    builder.spawn_blocking(stateful::in_current_task(move || task(input_rx, output)));
+
+    builder.build()
+}
+
+/// batches returned by python function
+pub(crate) struct Output<'a> {
+    sender: &'a Sender<Result<RecordBatch>>,
+    schema: &'a SchemaRef,
+    /// output stream has been dropped
+    pub(crate) closed: bool,
+}
+
+impl<'a> Output<'a> {
+    pub(crate) fn new(sender: &'a Sender<Result<RecordBatch>>, schema: &'a SchemaRef) -> Self {
+        Self {
+            sender,
+            schema,
+            closed: false,
+        }
+    }
+
+    /// sends batches of value returned by the function
+    pub(crate) fn emit(&mut self, py: Python<'_>, value: &Bound<'_, PyAny>) -> PyResult<()> {
+        if value.is_none() || self.closed {
+            return Ok(());
+        }
+        if value.hasattr("__arrow_c_array__")? || is_pyarrow_batch(value)? {
+            return self.send(py, RecordBatch::from_pyarrow_bound(value)?);
+        }
+        if value.hasattr("__arrow_c_stream__")? {
+            for batch in ArrowArrayStreamReader::from_pyarrow_bound(value)? {
+                self.send(py, batch.map_err(|e| PyRuntimeError::new_err(e.to_string()))?)?;
+            }
+            return Ok(());
+        }
+        if is_pandas_data_frame(value)? {
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("schema", self.schema.as_ref().to_pyarrow(py)?)?;
+            kwargs.set_item("preserve_index", false)?;
+            let batch =
+                py.import("pyarrow")?
+                    .getattr("RecordBatch")?
+                    .call_method("from_pandas", (value,), Some(&kwargs))?;
+            return self.send(py, RecordBatch::from_pyarrow_bound(&batch)?);
+        }
+        if value.hasattr("to_batches")? {
+            // older pyarrow tables
+            return self.emit(py, &value.call_method0("to_batches")?);
+        }
+        for item in value.try_iter()? {
+            self.emit(py, &item?)?;
+        }
+
+        Ok(())
+    }
+
+    fn send(&mut self, py: Python<'_>, batch: RecordBatch) -> PyResult<()> {
+        let batch = conform(batch, self.schema).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        if py.allow_threads(|| self.sender.blocking_send(Ok(batch))).is_err() {
+            log::debug!("map_batches::send - output stream closed");
+            self.closed = true;
+        }
+
+        Ok(())
+    }
+}
+
+/// batch passed to python function according to function mode
+pub(crate) fn to_py_batch(py: Python<'_>, batch: RecordBatch, mode: CallMode) -> PyResult<PyObject> {
+    match mode {
+        CallMode::Arrow => batch.to_pyarrow(py),
+        CallMode::Pandas => batch.to_pyarrow(py)?.call_method0(py, "to_pandas"),
+        CallMode::Capsule => {
+            let array = PyArrowArray::new(StructArray::from(batch).into());
+            Ok(Bound::new(py, array)?.into_any().unbind())
+        }
+    }
+}
+
+/// batch with declared schema, columns of other type are cast
+fn conform(batch: RecordBatch, schema: &SchemaRef) -> Result<RecordBatch> {
+    if batch.num_columns() != schema.fields().len() {
+        return exec_err!(
+            "function returned batch with {} columns, {} columns expected",
+            batch.num_columns(),
+            schema.fields().len()
+        );
+    }
+    let columns = batch
+        .columns()
+        .iter()
+        .zip(schema.fields())
+        .map(|(column, field)| match column.data_type() == field.data_type() {
+            true => Ok(column.clone()),
+            false => cast(column, field.data_type()).map_err(|e| {
+                exec_datafusion_err!(
+                    "function returned column: {} of type: {}, which can't be cast to: {}: {e}",
+                    field.name(),
+                    column.data_type(),
+                    field.data_type()
+                )
+            }),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+fn is_pyarrow_batch(value: &Bound<'_, PyAny>) -> PyResult<bool> {
+    Ok(value.hasattr("_export_to_c")? && value.hasattr("num_rows")? && !value.hasattr("to_batches")?)
+}
+
+fn is_pandas_data_frame(value: &Bound<'_, PyAny>) -> PyResult<bool> {
+    Ok(value.hasattr("iloc")? && value.hasattr("columns")?)
+}
+
+/// Logical plan node applying [MapBatchesFunction] to batches of its input
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MapBatchesNode {
+    pub input: LogicalPlan,
+    pub function: MapBatchesFunction,
+    schema: DFSchemaRef,
+}
+
+impl MapBatchesNode {
+    pub fn try_new(input: LogicalPlan, function: MapBatchesFunction) -> Result<Self> {
+        let schema = Arc::new(DFSchema::try_from(function.schema().as_ref().clone())?);
+
+        Ok(Self {
+            input,
+            function,
+            schema,
+        })
+    }
+}
+
+impl PartialOrd for MapBatchesNode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        (&self.input, self.function.name()).partial_cmp(&(&other.input, other.function.name()))
+    }
+}
+
+impl UserDefinedLogicalNodeCore for MapBatchesNode {
+    fn name(&self) -> &str {
+        "MapBatches"
+    }
+
+    fn inputs(&self) -> Vec<&LogicalPlan> {
+        vec![&self.input]
+    }
+
+    fn schema(&self) -> &DFSchemaRef {
+        &self.schema
+    }
+
+    fn expressions(&self) -> Vec<Expr> {
+        vec![]
+    }
+
+    fn fmt_for_explain(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MapBatches: function={}, iterator={}",
+            self.function.name(),
+            self.function.iterator()
+        )
+    }
+
+    fn with_exprs_and_inputs(&self, _exprs: Vec<Expr>, mut inputs: Vec<LogicalPlan>) -> Result<Self> {
+        match inputs.pop() {
+            Some(input) if inputs.is_empty() => Ok(Self { input, ..self.clone() }),
+            _ => plan_err!("map batches node expects single input"),
+        }
+    }
+}
+
+/// Execution plan applying [MapBatchesFunction] to batches of each input partition
+#[derive(Debug, Clone)]
+pub struct MapBatchesExec {
+    input: Arc<dyn ExecutionPlan>,
+    function: MapBatchesFunction,
+    properties: PlanProperties,
+}
+
+impl MapBatchesExec {
+    pub fn new(input: Arc<dyn ExecutionPlan>, function: MapBatchesFunction) -> Self {
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(function.schema().clone()),
+            Partitioning::UnknownPartitioning(input.properties().output_partitioning().partition_count()),
+            EmissionType::Incremental,
+            input.properties().boundedness,
+        );
+        Self {
+            input,
+            function,
+            properties,
+        }
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn function(&self) -> &MapBatchesFunction {
+        &self.function
+    }
+}
+
+impl DisplayAs for MapBatchesExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MapBatchesExec: function={}, iterator={}",
+            self.function.name(),
+            self.function.iterator()
+        )
+    }
+}
+
+impl ExecutionPlan for MapBatchesExec {
+    fn name(&self) -> &str {
+        "MapBatchesExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn with_new_children(self: Arc<Self>, mut children: Vec<Arc<dyn ExecutionPlan>>) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.pop() {
+            Some(input) if children.is_empty() => Ok(Arc::new(Self::new(input, self.function.clone()))),
+            _ => exec_err!("map batches plan expects single input"),
+        }
+    }
+
+    fn execute(&self, partition: usize, context: Arc<TaskContext>) -> Result<SendableRecordBatchStream> {
+        let input = self.input.execute(partition, context)?;
+
+        Ok(self.function.map(input))
+    }
+}
+
+/// Plans [MapBatchesNode] as [MapBatchesExec]
+#[derive(Debug, Default)]
+pub struct MapBatchesPlanner {}
+
+#[async_trait::async_trait]
+impl ExtensionPlanner for MapBatchesPlanner {
+    async fn plan_extension(
+        &self,
+        _planner: &dyn PhysicalPlanner,
+        node: &dyn UserDefinedLogicalNode,
+        _logical_inputs: &[&LogicalPlan],
+        physical_inputs: &[Arc<dyn ExecutionPlan>],
+        _session_state: &SessionState,
+    ) -> Result<Option<Arc<dyn ExecutionPlan>>> {
+        let Some(node) = node.as_any().downcast_ref::<MapBatchesNode>() else {
+            return Ok(None);
+        };
+        match physical_inputs {
+            [input] => Ok(Some(Arc::new(MapBatchesExec::new(
+                input.clone(),
+                node.function.clone(),
+            )))),
+            _ => plan_err!("map batches node expects single input"),
+        }
+    }
+}
+
+/// extension planners of [MapBatchesNode] and [crate::grouped_map::ApplyPythonNode],
+/// for sessions with their own query planner
+pub fn python_extension_planners() -> Vec<Arc<dyn ExtensionPlanner + Send + Sync>> {
+    vec![
+        Arc::new(MapBatchesPlanner::default()),
+        Arc::new(ApplyPythonPlanner::default()),
+    ]
+}
+
+/// Query planner of sessions executing [MapBatchesNode] and
+/// [crate::grouped_map::ApplyPythonNode], ballista schedulers
+/// have to use it as well. Sessions with their own query planner
+/// should add [python_extension_planners] to it instead.
+#[derive(Debug, Default)]
+pub struct PythonQueryPlanner {}
+
+#[async_trait::async_trait]
+impl QueryPlanner for PythonQueryPlanner {
+    async fn create_physical_plan(
+        &self,
+        logical_plan: &LogicalPlan,
+        session_state: &SessionState,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let planner = DefaultPhysicalPlanner::with_extension_planners(python_extension_planners());
+        planner.create_physical_plan(logical_plan, session_state).await
+    }
+}
+
+/// Applies python functions to batches of data frames
+pub trait MapBatchesExt {
+    /// applies the function to batches of each partition
+    fn map_batches(self, function: MapBatchesFunction) -> Result<DataFrame>;
+}
+
+impl MapBatchesExt for DataFrame {
+    fn map_batches(self, function: MapBatchesFunction) -> Result<DataFrame> {
+        let (state, plan) = self.into_parts();
+        let node = MapBatchesNode::try_new(plan, function)?;
+
+        Ok(DataFrame::new(
+            state,
+            LogicalPlan::Extension(Extension { node: Arc::new(node) }),
+        ))
+    }
+}
+
+/// Table function applying [MapBatchesFunction] to result of a subquery,
+/// registered with [datafusion::prelude::SessionContext::register_udtf]:
+///
+/// ```sql
+/// SELECT * FROM explode((SELECT id, tags FROM t))
+/// ```
+#[derive(Debug)]
+pub struct MapBatchesTableFunction {
+    function: MapBatchesFunction,
+}
+
+impl MapBatchesTableFunction {
+    pub fn new(function: MapBatchesFunction) -> Self {
+        Self { function }
+    }
+}
+
+impl TableFunctionImpl for MapBatchesTableFunction {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let name = self.function.name();
+        let input = match args {
+            [Expr::ScalarSubquery(subquery)] if subquery.outer_ref_columns.is_empty() => {
+                subquery.subquery.as_ref().clone()
+            }
+            _ => return plan_err!("function: {name} expects a subquery, like {name}((SELECT * FROM t))"),
+        };
+        let node = MapBatchesNode::try_new(input, self.function.clone())?;
+
+        Ok(Arc::new(MapBatchesTable {
+            plan: LogicalPlan::Extension(Extension { node: Arc::new(node) }),
+            schema: self.function.schema().clone(),
+        }))
+    }
+}
+
+/// result of [MapBatchesTableFunction], inlined
+/// into query plan like a view
+#[derive(Debug)]
+struct MapBatchesTable {
+    plan: LogicalPlan,
+    schema: SchemaRef,
+}
+
+#[async_trait::async_trait]
+impl TableProvider for MapBatchesTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::View
+    }
+
+    fn get_logical_plan(&self) -> Option<Cow<'_, LogicalPlan>> {
+        Some(Cow::Borrowed(&self.plan))
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let mut plan = LogicalPlanBuilder::from(self.plan.clone());
+        if let Some(projection) = projection {
+            plan = plan.select(projection.iter().cloned())?;
+        }
+        if let Some(limit) = limit {
+            plan = plan.limit(0, Some(limit))?;
+        }
+
+        state.create_physical_plan(&plan.build()?).await
+    }
+}
@@ -1,40 +1,102 @@
 use pyo3::types::{PyAnyMethods, PyBytes, PyBytesMethods};
 use pyo3::{PyObject, PyResult, Python};
 use std::fmt::Debug;
+use std::sync::Arc;
 
-static MODULE: &str = "cloudpickle";
 static FUN_LOADS: &str = "loads";
 static FUN_DUMPS: &str = "dumps";
 
+/// format id of [PickleSerializer::cloudpickle] serializer, also
+/// assumed for functions which have no format id specified.
+pub const FORMAT_CLOUDPICKLE: &str = "cloudpickle";
+/// format id of [PickleSerializer::dill] serializer
+pub const FORMAT_DILL: &str = "dill";
+/// format id of [PickleSerializer::std_pickle] serializer
+pub const FORMAT_PICKLE: &str = "pickle";
+
 /// Serde protocol for UD(a)F
+///
+/// Format id is stored alongside serialized function,
+/// so the function can be deserialized using the same format.
+pub trait PySerializer: Debug + Send + Sync {
+    /// format id of serialized functions
+    fn format(&self) -> &str;
+
+    fn pickle(&self, py: Python<'_>, py_any: &PyObject) -> PyResult<Vec<u8>>;
+
+    fn unpickle(&self, py: Python<'_>, blob: &[u8]) -> PyResult<PyObject>;
+}
+
+/// returns built-in serializer for given format id
+pub fn serializer_for_format(py: Python<'_>, format: &str) -> PyResult<Arc<dyn PySerializer>> {
+    match format {
+        "" | FORMAT_CLOUDPICKLE => Ok(Arc::new(PickleSerializer::cloudpickle(py)?)),
+        FORMAT_DILL => Ok(Arc::new(PickleSerializer::dill(py)?)),
+        FORMAT_PICKLE => Ok(Arc::new(PickleSerializer::std_pickle(py)?)),
+        f => Err(pyo3::exceptions::PyValueError::new_err(format!(
+            "unsupported python function serialization format: {f}"
+        ))),
+    }
+}
+
+/// Serializer using `dumps` and `loads` functions of a python module,
+/// like `cloudpickle`, `dill` or standard library `pickle`.
+///
+/// `dill` can handle some closures `cloudpickle` can't, standard library
+/// `pickle` pickles functions by reference, so they have to be importable
+/// at the executor.
 #[derive(Debug)]
-pub struct CloudPickle {
+pub struct PickleSerializer {
+    format: String,
     loads: PyObject,
     dumps: PyObject,
 }
 
-impl CloudPickle {
-    pub fn try_new(py: Python<'_>) -> PyResult<Self> {
-        //let m = PyModule::new_bound(py, "example_udf").expect("module created");
-
-        let module = py.import(MODULE)?;
-        //let register_module = module.getattr("register_pickle_by_value")?;
-        //register_module.call1((m,))?;
+impl PickleSerializer {
+    /// serializer using given python module, storing given format id
+    /// with serialized functions
+    pub fn try_new(py: Python<'_>, module: &str, format: impl Into<String>) -> PyResult<Self> {
+        let module = py.import(module)?;
 
         let loads = module.getattr(FUN_LOADS)?.unbind();
         let dumps = module.getattr(FUN_DUMPS)?.unbind();
 
-        Ok(Self { loads, dumps })
+        Ok(Self {
+            format: format.into(),
+            loads,
+            dumps,
+        })
+    }
+
+    /// serializer using `cloudpickle` library
+    pub fn cloudpickle(py: Python<'_>) -> PyResult<Self> {
+        Self::try_new(py, "cloudpickle", FORMAT_CLOUDPICKLE)
+    }
+
+    /// serializer using `dill` library
+    pub fn dill(py: Python<'_>) -> PyResult<Self> {
+        Self::try_new(py, "dill", FORMAT_DILL)
+    }
+
+    /// serializer using standard library `pickle` module
+    pub fn std_pickle(py: Python<'_>) -> PyResult<Self> {
+        Self::try_new(py, "pickle", FORMAT_PICKLE)
+    }
+}
+
+impl PySerializer for PickleSerializer {
+    fn format(&self) -> &str {
+        &self.format
     }
 
-    pub fn pickle(&self, py: Python<'_>, py_any: &PyObject) -> PyResult<Vec<u8>> {
+    fn pickle(&self, py: Python<'_>, py_any: &PyObject) -> PyResult<Vec<u8>> {
         let b: PyObject = self.dumps.call1(py, (py_any,))?.extract(py)?;
         let blob = b.downcast_bound::<PyBytes>(py)?.clone();
 
         Ok(blob.as_bytes().to_owned())
     }
 
-    pub fn unpickle(&self, py: Python<'_>, blob: &[u8]) -> PyResult<PyObject> {
+    fn unpickle(&self, py: Python<'_>, blob: &[u8]) -> PyResult<PyObject> {
         let t: PyObject = self.loads.call1(py, (blob,))?.extract(py)?;
 
         Ok(t)
@@ -0,0 +1,466 @@
+use crate::capabilities::PythonCapabilities;
+use crate::flight::fetch_capabilities;
+use crate::grouped_map::ApplyPythonExec;
+use crate::map_batches::MapBatchesExec;
+use crate::requirements::PythonRequirement;
+use crate::udf::PythonUDF;
+use ballista_core::serde::protobuf::{job_status, AvailableTaskSlots};
+use ballista_core::serde::scheduler::PartitionId;
+use ballista_core::utils::default_session_builder;
+use ballista_scheduler::cluster::{BoundTask, ClusterState, ClusterStateEvent, DistributionPolicy};
+use ballista_scheduler::config::SchedulerConfig;
+use ballista_scheduler::scheduler_server::SessionBuilder;
+use ballista_scheduler::state::execution_graph::{create_task_info, TaskDescription};
+use ballista_scheduler::state::task_manager::JobInfoCache;
+use datafusion::common::config::ConfigOptions;
+use datafusion::common::tree_node::{TreeNode, TreeNodeRecursion};
+use datafusion::common::{exec_err, Result};
+use datafusion::error::DataFusionError;
+use datafusion::execution::SessionStateBuilder;
+use datafusion::physical_expr::window::WindowExpr;
+use datafusion::physical_expr::{PhysicalExprRef, ScalarFunctionExpr};
+use datafusion::physical_optimizer::PhysicalOptimizerRule;
+use datafusion::physical_plan::aggregates::AggregateExec;
+use datafusion::physical_plan::coalesce_partitions::CoalescePartitionsExec;
+use datafusion::physical_plan::filter::FilterExec;
+use datafusion::physical_plan::joins::utils::JoinFilter;
+use datafusion::physical_plan::joins::{HashJoinExec, NestedLoopJoinExec, SortMergeJoinExec, SymmetricHashJoinExec};
+use datafusion::physical_plan::projection::ProjectionExec;
+use datafusion::physical_plan::repartition::RepartitionExec;
+use datafusion::physical_plan::sorts::sort::SortExec;
+use datafusion::physical_plan::sorts::sort_preserving_merge::SortPreservingMergeExec;
+use datafusion::physical_plan::windows::{BoundedWindowAggExec, WindowAggExec};
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, PhysicalExpr};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// how long the scheduler waits before asking registered
+/// executor for its capabilities again, until it answers
+const FETCH_RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Task distribution policy placing tasks running python functions
+/// only on executors whose advertised [PythonCapabilities] satisfy
+/// requirements of the functions. Executors which do not advertise
+/// capabilities get tasks without python functions only.
+///
+/// Capabilities are obtained from executors when they register with
+/// the scheduler (see [PythonDistributionPolicy::watch]), and jobs
+/// which no registered executor can run fail when they are planned
+/// (see [PythonDistributionPolicy::configure]).
+///
+/// Other tasks are placed like with `bias` policy, filling
+/// executors with the most available slots first.
+pub struct PythonDistributionPolicy {
+    capabilities: Mutex<HashMap<String, ExecutorCapabilities>>,
+}
+
+#[derive(Clone)]
+enum ExecutorCapabilities {
+    /// executor has registered, it has not answered yet
+    Pending,
+    Python(Arc<PythonCapabilities>),
+    Missing,
+}
+
+impl PythonDistributionPolicy {
+    pub fn new() -> Self {
+        Self {
+            capabilities: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// configures scheduler to fail jobs with python functions which
+    /// can't be run by any registered executor, wrapping
+    /// `override_session_builder` if it has been set.
+    pub fn configure(self: &Arc<Self>, config: SchedulerConfig) -> SchedulerConfig {
+        let builder = config.override_session_builder.clone();
+        let policy = self.clone();
+        let builder: SessionBuilder = Arc::new(move |config| {
+            let state = match &builder {
+                Some(builder) => builder(config)?,
+                None => default_session_builder(config)?,
+            };
+            Ok(SessionStateBuilder::new_from_existing(state)
+                .with_physical_optimizer_rule(Arc::new(PythonPlacementCheck { policy: policy.clone() }))
+                .build())
+        });
+
+        SchedulerConfig {
+            override_session_builder: Some(builder),
+            ..config
+        }
+    }
+
+    /// obtains capabilities of executors registered with the cluster,
+    /// and of executors registering later, until the cluster state
+    /// event stream ends. Capabilities of removed executors are dropped.
+    pub async fn watch(self: &Arc<Self>, cluster_state: Arc<dyn ClusterState>) -> Result<()> {
+        let mut events = cluster_state
+            .cluster_state_events()
+            .await
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        for metadata in cluster_state.registered_executor_metadata().await {
+            self.executor_registered(metadata.id, cluster_state.clone());
+        }
+
+        let policy = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                match event {
+                    ClusterStateEvent::RegisteredExecutor { executor_id } => {
+                        policy.executor_registered(executor_id, cluster_state.clone())
+                    }
+                    ClusterStateEvent::RemovedExecutor { executor_id } => {
+                        policy.capabilities.lock().unwrap().remove(&executor_id);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// capabilities of registered executor, `None` if executor
+    /// has not advertised them (yet)
+    pub fn executor_capabilities(&self, executor_id: &str) -> Option<Arc<PythonCapabilities>> {
+        match self.capabilities.lock().unwrap().get(executor_id) {
+            Some(ExecutorCapabilities::Python(capabilities)) => Some(capabilities.clone()),
+            _ => None,
+        }
+    }
+
+    /// checks if each stage of the plan using python functions can be run
+    /// by a registered executor, or by executor which has not advertised
+    /// its capabilities yet
+    pub fn check(&self, plan: &Arc<dyn ExecutionPlan>) -> Result<()> {
+        let capabilities = self.capabilities.lock().unwrap();
+        for requirements in stage_requirements(plan) {
+            let can_run = capabilities.values().any(|c| match c {
+                ExecutorCapabilities::Pending => true,
+                ExecutorCapabilities::Python(c) => c.satisfies(&requirements),
+                ExecutorCapabilities::Missing => false,
+            });
+            if !can_run {
+                let requirements = requirements.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+                return exec_err!(
+                    "no registered executor can run python functions with requirements: [{}]",
+                    requirements.join(", ")
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// asks registered executor for its capabilities until it answers,
+    /// as its flight service may start after it has registered
+    fn executor_registered(self: &Arc<Self>, executor_id: String, cluster_state: Arc<dyn ClusterState>) {
+        self.capabilities
+            .lock()
+            .unwrap()
+            .insert(executor_id.clone(), ExecutorCapabilities::Pending);
+
+        let policy = self.clone();
+        tokio::spawn(async move {
+            while policy.is_pending(&executor_id) {
+                let fetched = match cluster_state.get_executor_metadata(&executor_id).await {
+                    Ok(metadata) => fetch_capabilities(&metadata.host, metadata.port).await,
+                    Err(e) => Err(DataFusionError::External(Box::new(e))),
+                };
+                let fetched = match fetched {
+                    Ok(Some(capabilities)) => {
+                        log::debug!("placement::executor_registered - executor: {executor_id}, {capabilities:?}");
+                        ExecutorCapabilities::Python(Arc::new(capabilities))
+                    }
+                    Ok(None) => {
+                        log::info!("executor: {executor_id} does not advertise python capabilities");
+                        ExecutorCapabilities::Missing
+                    }
+                    Err(e) => {
+                        log::debug!("placement::executor_registered - executor: {executor_id} not ready: {e}");
+                        tokio::time::sleep(FETCH_RETRY_INTERVAL).await;
+                        continue;
+                    }
+                };
+                let mut capabilities = policy.capabilities.lock().unwrap();
+                // executor may have been removed in the meantime
+                if let Some(entry) = capabilities.get_mut(&executor_id) {
+                    *entry = fetched;
+                }
+                return;
+            }
+        });
+    }
+
+    /// checks if executor is registered and has not answered yet
+    fn is_pending(&self, executor_id: &str) -> bool {
+        matches!(
+            self.capabilities.lock().unwrap().get(executor_id),
+            Some(ExecutorCapabilities::Pending)
+        )
+    }
+}
+
+impl Default for PythonDistributionPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for PythonDistributionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PythonDistributionPolicy").finish()
+    }
+}
+
+#[async_trait::async_trait]
+impl DistributionPolicy for PythonDistributionPolicy {
+    async fn bind_tasks(
+        &self,
+        mut slots: Vec<&mut AvailableTaskSlots>,
+        running_jobs: Arc<HashMap<String, JobInfoCache>>,
+    ) -> datafusion::error::Result<Vec<BoundTask>> {
+        let mut schedulable_tasks: Vec<BoundTask> = vec![];
+        if slots.iter().all(|s| s.slots == 0) {
+            return Ok(schedulable_tasks);
+        }
+
+        let capabilities = slots
+            .iter()
+            .map(|s| (s.executor_id.clone(), self.executor_capabilities(&s.executor_id)))
+            .collect::<HashMap<_, _>>();
+        let can_run = |executor_id: &str, requirements: &Option<Vec<PythonRequirement>>| match requirements {
+            None => true,
+            Some(requirements) => capabilities
+                .get(executor_id)
+                .and_then(|c| c.as_ref())
+                .is_some_and(|c| c.satisfies(requirements)),
+        };
+
+        // executors with the most available slots first
+        slots.sort_by(|a, b| Ord::cmp(&b.slots, &a.slots));
+
+        for (job_id, job_info) in running_jobs.iter() {
+            if !matches!(job_info.status, Some(job_status::Status::Running(_))) {
+                continue;
+            }
+            let mut graph = job_info.execution_graph.write().await;
+            let session_id = graph.session_id().to_string();
+            let mut black_list = vec![];
+            while let Some((running_stage, task_id_gen)) = graph.fetch_running_stage(&black_list) {
+                let requirements = python_requirements(&running_stage.plan);
+                let runnable_tasks = running_stage
+                    .task_infos
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|(_partition, info)| info.is_none());
+                for (partition_id, task_info) in runnable_tasks {
+                    let Some(slot) = slots
+                        .iter_mut()
+                        .find(|s| s.slots > 0 && can_run(&s.executor_id, &requirements))
+                    else {
+                        log::debug!(
+                            "placement::bind_tasks - no executor can run stage {job_id}/{} now, requirements: {requirements:?}",
+                            running_stage.stage_id
+                        );
+                        break;
+                    };
+                    let executor_id = slot.executor_id.clone();
+                    let task_id = *task_id_gen;
+                    *task_id_gen += 1;
+                    *task_info = Some(create_task_info(executor_id.clone(), task_id));
+
+                    let partition = PartitionId {
+                        job_id: job_id.clone(),
+                        stage_id: running_stage.stage_id,
+                        partition_id,
+                    };
+                    let task_desc = TaskDescription {
+                        session_id: session_id.clone(),
+                        partition,
+                        stage_attempt_num: running_stage.stage_attempt_num,
+                        task_id,
+                        task_attempt: running_stage.task_failure_numbers[partition_id],
+                        plan: running_stage.plan.clone(),
+                        session_config: running_stage.session_config.clone(),
+                    };
+                    schedulable_tasks.push((executor_id, task_desc));
+
+                    slot.slots -= 1;
+                }
+                black_list.push(running_stage.stage_id);
+            }
+        }
+
+        Ok(schedulable_tasks)
+    }
+
+    fn name(&self) -> &str {
+        "PythonDistributionPolicy"
+    }
+}
+
+/// Physical optimizer rule failing plans which can't be run by any
+/// registered executor, see [PythonDistributionPolicy::check]
+#[derive(Debug)]
+struct PythonPlacementCheck {
+    policy: Arc<PythonDistributionPolicy>,
+}
+
+impl PhysicalOptimizerRule for PythonPlacementCheck {
+    fn optimize(&self, plan: Arc<dyn ExecutionPlan>, _config: &ConfigOptions) -> Result<Arc<dyn ExecutionPlan>> {
+        self.policy.check(&plan)?;
+        Ok(plan)
+    }
+
+    fn name(&self) -> &str {
+        "python_placement_check"
+    }
+
+    fn schema_check(&self) -> bool {
+        true
+    }
+}
+
+/// requirements of python functions used in the plan,
+/// `None` if the plan does not use python functions
+pub fn python_requirements(plan: &Arc<dyn ExecutionPlan>) -> Option<Vec<PythonRequirement>> {
+    let mut requirements = None;
+    let _ = plan.apply(|node| {
+        add_requirements(node.as_ref(), &mut requirements);
+        Ok(TreeNodeRecursion::Continue)
+    });
+
+    requirements
+}
+
+/// requirements of python functions of each stage using them, plan is
+/// split into stages at exchanges, as ballista distributed planner does
+pub fn stage_requirements(plan: &Arc<dyn ExecutionPlan>) -> Vec<Vec<PythonRequirement>> {
+    let mut stages = vec![];
+    collect_stage(plan, &mut stages);
+    stages
+}
+
+fn collect_stage(root: &Arc<dyn ExecutionPlan>, stages: &mut Vec<Vec<PythonRequirement>>) {
+    let mut requirements = None;
+    let mut nodes = vec![root];
+    while let Some(node) = nodes.pop() {
+        add_requirements(node.as_ref(), &mut requirements);
+        for child in node.children() {
+            // exchange starts new stage, writing its input
+            // partitioned as the exchange requires
+            match is_exchange(child.as_ref()) {
+                true => collect_stage(child, stages),
+                false => nodes.push(child),
+            }
+        }
+    }
+    stages.extend(requirements);
+}
+
+fn is_exchange(plan: &dyn ExecutionPlan) -> bool {
+    let any = plan.as_any();
+    any.is::<RepartitionExec>() || any.is::<CoalescePartitionsExec>() || any.is::<SortPreservingMergeExec>()
+}
+
+/// adds requirements of python functions called by plan node
+fn add_requirements(node: &dyn ExecutionPlan, requirements: &mut Option<Vec<PythonRequirement>>) {
+    let mut add = |udf: &PythonUDF| {
+        let found = requirements.get_or_insert_with(Vec::new);
+        for requirement in &udf.requirements {
+            if !found.contains(requirement) {
+                found.push(requirement.clone());
+            }
+        }
+    };
+    let any = node.as_any();
+    if let Some(map_batches) = any.downcast_ref::<MapBatchesExec>() {
+        add(map_batches.function().python_udf());
+    } else if let Some(apply_python) = any.downcast_ref::<ApplyPythonExec>() {
+        add(apply_python.function().python_udf());
+    }
+    for expr in expressions(node) {
+        let _ = expr.apply(|e| {
+            if let Some(udf) = e
+                .as_any()
+                .downcast_ref::<ScalarFunctionExpr>()
+                .and_then(|f| f.fun().inner().as_any().downcast_ref::<PythonUDF>())
+            {
+                add(udf);
+            }
+            Ok(TreeNodeRecursion::Continue)
+        });
+    }
+}
+
+/// expressions of plan nodes which can call scalar functions
+fn expressions(plan: &dyn ExecutionPlan) -> Vec<Arc<dyn PhysicalExpr>> {
+    let any = plan.as_any();
+    let join_on = |on: &[(PhysicalExprRef, PhysicalExprRef)]| {
+        on.iter()
+            .flat_map(|(l, r)| [l.clone(), r.clone()])
+            .collect::<Vec<Arc<dyn PhysicalExpr>>>()
+    };
+    let join_filter = |filter: Option<&JoinFilter>| filter.map(|f| f.expression().clone());
+    let window = |window_expr: &[Arc<dyn WindowExpr>]| {
+        window_expr
+            .iter()
+            .flat_map(|w| {
+                let order_by = w.order_by().iter().map(|s| s.expr.clone());
+                w.expressions()
+                    .into_iter()
+                    .chain(w.partition_by().to_vec())
+                    .chain(order_by)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    if let Some(projection) = any.downcast_ref::<ProjectionExec>() {
+        projection.expr().iter().map(|(e, _)| e.clone()).collect()
+    } else if let Some(filter) = any.downcast_ref::<FilterExec>() {
+        vec![filter.predicate().clone()]
+    } else if let Some(sort) = any.downcast_ref::<SortExec>() {
+        sort.expr().iter().map(|e| e.expr.clone()).collect()
+    } else if let Some(merge) = any.downcast_ref::<SortPreservingMergeExec>() {
+        merge.expr().iter().map(|e| e.expr.clone()).collect()
+    } else if let Some(repartition) = any.downcast_ref::<RepartitionExec>() {
+        match repartition.partitioning() {
+            Partitioning::Hash(exprs, _) => exprs.clone(),
+            _ => vec![],
+        }
+    } else if let Some(aggregate) = any.downcast_ref::<AggregateExec>() {
+        let group_by = aggregate.group_expr().expr().iter().map(|(e, _)| e.clone());
+        let arguments = aggregate.aggr_expr().iter().flat_map(|a| a.expressions());
+        let filters = aggregate.filter_expr().iter().flatten().cloned();
+        group_by.chain(arguments).chain(filters).collect()
+    } else if let Some(window_agg) = any.downcast_ref::<BoundedWindowAggExec>() {
+        window(window_agg.window_expr())
+    } else if let Some(window_agg) = any.downcast_ref::<WindowAggExec>() {
+        window(window_agg.window_expr())
+    } else if let Some(join) = any.downcast_ref::<HashJoinExec>() {
+        join_on(join.on())
+            .into_iter()
+            .chain(join_filter(join.filter()))
+            .collect()
+    } else if let Some(join) = any.downcast_ref::<SortMergeJoinExec>() {
+        join_on(join.on())
+            .into_iter()
+            .chain(join_filter(join.filter().as_ref()))
+            .collect()
+    } else if let Some(join) = any.downcast_ref::<SymmetricHashJoinExec>() {
+        join_on(join.on())
+            .into_iter()
+            .chain(join_filter(join.filter()))
+            .collect()
+    } else if let Some(join) = any.downcast_ref::<NestedLoopJoinExec>() {
+        join_filter(join.filter()).into_iter().collect()
+    } else if let Some(apply_python) = any.downcast_ref::<ApplyPythonExec>() {
+        apply_python.keys().to_vec()
+    } else {
+        vec![]
+    }
+}
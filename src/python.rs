@@ -0,0 +1,375 @@
+use crate::capsule::PyArrowArray;
+use crate::codec::PyLogicalCodec;
+use crate::compat;
+use crate::env::PythonEnvConfig;
+use crate::factory::PythonFunctionFactory;
+use crate::grouped_map::{GroupedDataFrame, GroupedMapExt};
+use crate::map_batches::{MapBatchesExt, MapBatchesFunction, MapBatchesTableFunction};
+use crate::sql::PythonSessionExt;
+use crate::stateful::{self, InstanceScope, PythonInstance};
+use crate::udf::{CallMode, PythonUDF};
+use ballista::prelude::{SessionConfigExt, SessionContextExt};
+use datafusion::arrow::datatypes::{DataType, Schema};
+use datafusion::arrow::pyarrow::{PyArrowType, ToPyArrow};
+use datafusion::error::DataFusionError;
+use datafusion::execution::SessionStateBuilder;
+use datafusion::logical_expr::{ScalarUDF, Volatility};
+use datafusion::prelude::{DataFrame, ParquetReadOptions, SessionConfig, SessionContext};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyTuple};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+use tokio::runtime::Runtime;
+
+/// runtime ballista client futures are executed on
+static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("ballista-python")
+        .build()
+        .expect("tokio runtime to be created")
+});
+
+/// waits for the future releasing the GIL, so python
+/// functions can be pickled by runtime threads meanwhile
+fn wait_for<F, T>(py: Python<'_>, future: F) -> PyResult<T>
+where
+    F: Future<Output = datafusion::common::Result<T>> + Send,
+    T: Send,
+{
+    py.allow_threads(|| RUNTIME.block_on(future)).map_err(to_py_err)
+}
+
+fn to_py_err(e: DataFusionError) -> PyErr {
+    PyRuntimeError::new_err(e.to_string())
+}
+
+fn map_batches_function(
+    py: Python<'_>,
+    func: PyObject,
+    schema: Schema,
+    mode: &str,
+    iterator: bool,
+    name: Option<String>,
+) -> PyResult<MapBatchesFunction> {
+    let name = match name {
+        Some(name) => name,
+        None => func.getattr(py, "__name__")?.extract(py)?,
+    };
+    let udf = PythonUDF::new(name, vec![], DataType::Null, Volatility::Volatile, func)
+        .with_mode(CallMode::from_str(mode).map_err(to_py_err)?);
+
+    Ok(MapBatchesFunction::from_udf(udf, Arc::new(schema)).with_iterator(iterator))
+}
+
+/// Ballista session, created with [connect], with python
+/// functions shipped to the cluster by [PyLogicalCodec]
+#[pyclass(name = "BallistaContext", module = "ballista_python")]
+pub struct PyBallistaContext {
+    ctx: SessionContext,
+}
+
+#[pymethods]
+impl PyBallistaContext {
+    /// executes sql statement, including `CREATE FUNCTION` with options
+    fn sql(&self, py: Python<'_>, query: &str) -> PyResult<PyDataFrame> {
+        let df = wait_for(py, self.ctx.python_sql(query))?;
+
+        Ok(PyDataFrame { df })
+    }
+
+    fn table(&self, py: Python<'_>, name: &str) -> PyResult<PyDataFrame> {
+        let df = wait_for(py, self.ctx.table(name))?;
+
+        Ok(PyDataFrame { df })
+    }
+
+    fn register_parquet(&self, py: Python<'_>, name: &str, path: &str) -> PyResult<()> {
+        wait_for(py, self.ctx.register_parquet(name, path, ParquetReadOptions::default()))
+    }
+
+    /// registers python function, `input_types` and `return_type`
+    /// are `pyarrow` data types, volatility is `immutable`,
+    /// `stable` or `volatile`; name defaults to function name.
+    ///
+    /// Function defined as a class is instantiated with `args` and
+    /// `kwargs` where it is called, once per `process` or `task`
+    /// (`instance`), see [PythonInstance].
+    ///
+    /// Without types, function defined with `ballista_python.udf`,
+    /// taking the same arguments as `datafusion.udf`, is expected
+    #[pyo3(signature = (
+        func,
+        input_types = None,
+        return_type = None,
+        volatility = "volatile",
+        name = None,
+        args = None,
+        kwargs = None,
+        instance = "process"
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn register_udf(
+        &self,
+        py: Python<'_>,
+        func: PyObject,
+        input_types: Option<Vec<PyArrowType<DataType>>>,
+        return_type: Option<PyArrowType<DataType>>,
+        volatility: &str,
+        name: Option<String>,
+        args: Option<Bound<'_, PyTuple>>,
+        kwargs: Option<Bound<'_, PyDict>>,
+        instance: &str,
+    ) -> PyResult<()> {
+        let udf = match (input_types, return_type) {
+            (None, None) => compat::from_datafusion_udf(func.bind(py)).map_err(to_py_err)?,
+            (Some(input_types), Some(return_type)) => {
+                let volatility = match volatility.to_lowercase().as_str() {
+                    "immutable" => Volatility::Immutable,
+                    "stable" => Volatility::Stable,
+                    "volatile" => Volatility::Volatile,
+                    v => return Err(PyValueError::new_err(format!("unsupported volatility: {v}"))),
+                };
+                let name = match name {
+                    Some(name) => name,
+                    None => func.getattr(py, "__name__")?.extract(py)?,
+                };
+                let input_types = input_types.into_iter().map(|t| t.0).collect();
+                let python_instance = match PythonInstance::is_class(py, &func)? {
+                    true => {
+                        let args = args.unwrap_or_else(|| PyTuple::empty(py));
+                        let scope = InstanceScope::from_str(instance).map_err(to_py_err)?;
+                        Some(PythonInstance::try_new(py, &func, &args, kwargs.as_ref())?.with_scope(scope))
+                    }
+                    false if args.is_some() || kwargs.is_some() => {
+                        return Err(PyValueError::new_err(format!(
+                            "function: {name} is not a class, it can't have constructor arguments"
+                        )))
+                    }
+                    false => None,
+                };
+                let udf = PythonUDF::new(name, input_types, return_type.0, volatility, func);
+                match python_instance {
+                    Some(python_instance) => udf.with_instance(python_instance),
+                    None => udf,
+                }
+            }
+            _ => return Err(PyValueError::new_err("both input types and return type expected")),
+        };
+        log::debug!("python::register_udf - function: {}", udf.name);
+        self.ctx.register_udf(ScalarUDF::from(udf));
+
+        Ok(())
+    }
+
+    fn deregister_udf(&self, name: &str) {
+        self.ctx.deregister_udf(name);
+    }
+
+    /// registers python function transforming record batches as sql
+    /// table function applied to a subquery, like `name((SELECT ...))`,
+    /// see [PyDataFrame::map_batches]
+    #[pyo3(signature = (name, func, schema, mode = "arrow", iterator = false))]
+    fn register_map_batches(
+        &self,
+        py: Python<'_>,
+        name: &str,
+        func: PyObject,
+        schema: PyArrowType<Schema>,
+        mode: &str,
+        iterator: bool,
+    ) -> PyResult<()> {
+        let function = map_batches_function(py, func, schema.0, mode, iterator, Some(name.to_string()))?;
+        log::debug!("python::register_map_batches - function: {name}");
+        self.ctx
+            .register_udtf(name, Arc::new(MapBatchesTableFunction::new(function)));
+
+        Ok(())
+    }
+}
+
+/// Lazily evaluated data frame, executed by the cluster
+#[pyclass(name = "DataFrame", module = "ballista_python")]
+pub struct PyDataFrame {
+    df: DataFrame,
+}
+
+#[pymethods]
+impl PyDataFrame {
+    /// selects sql expressions, like `df.select("a", "to_miles(b)")`
+    #[pyo3(signature = (*exprs))]
+    fn select(&self, exprs: Vec<String>) -> PyResult<Self> {
+        let exprs = exprs
+            .iter()
+            .map(|e| self.df.parse_sql_expr(e))
+            .collect::<datafusion::common::Result<Vec<_>>>()
+            .map_err(to_py_err)?;
+        let df = self.df.clone().select(exprs).map_err(to_py_err)?;
+
+        Ok(Self { df })
+    }
+
+    /// filters rows by sql predicate, like `df.filter("a > 1")`
+    fn filter(&self, predicate: &str) -> PyResult<Self> {
+        let predicate = self.df.parse_sql_expr(predicate).map_err(to_py_err)?;
+        let df = self.df.clone().filter(predicate).map_err(to_py_err)?;
+
+        Ok(Self { df })
+    }
+
+    /// applies python function to record batches of each partition,
+    /// returning batches of `schema` (`pyarrow` schema), possibly with
+    /// different number of rows. Function is called with each batch,
+    /// or once per partition with iterator over its batches
+    #[pyo3(signature = (func, schema, mode = "arrow", iterator = false, name = None))]
+    fn map_batches(
+        &self,
+        py: Python<'_>,
+        func: PyObject,
+        schema: PyArrowType<Schema>,
+        mode: &str,
+        iterator: bool,
+        name: Option<String>,
+    ) -> PyResult<Self> {
+        let function = map_batches_function(py, func, schema.0, mode, iterator, name)?;
+        let df = self.df.clone().map_batches(function).map_err(to_py_err)?;
+
+        Ok(Self { df })
+    }
+
+    /// groups rows by sql expressions, like `df.group_by("a", "b % 10")`,
+    /// see [PyGroupedData::apply_python]
+    #[pyo3(signature = (*keys))]
+    fn group_by(&self, keys: Vec<String>) -> PyResult<PyGroupedData> {
+        let keys = keys
+            .iter()
+            .map(|k| self.df.parse_sql_expr(k))
+            .collect::<datafusion::common::Result<Vec<_>>>()
+            .map_err(to_py_err)?;
+
+        Ok(PyGroupedData {
+            grouped: self.df.clone().group_by(keys),
+        })
+    }
+
+    #[pyo3(signature = (count, offset = 0))]
+    fn limit(&self, count: usize, offset: usize) -> PyResult<Self> {
+        let df = self.df.clone().limit(offset, Some(count)).map_err(to_py_err)?;
+
+        Ok(Self { df })
+    }
+
+    /// `pyarrow` schema of the data frame
+    fn schema(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.df.schema().inner().as_ref().to_pyarrow(py)
+    }
+
+    /// executes the data frame, returning list of `pyarrow` record batches
+    fn collect(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let batches = wait_for(py, self.df.clone().collect())?;
+        let batches = batches.iter().map(|b| b.to_pyarrow(py)).collect::<PyResult<Vec<_>>>()?;
+
+        Ok(PyList::new(py, batches)?.into_any().unbind())
+    }
+
+    /// executes the data frame, returning `pyarrow` table
+    fn to_arrow_table(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let batches = self.collect(py)?;
+        let schema = self.schema(py)?;
+        let table = py
+            .import("pyarrow")?
+            .getattr("Table")?
+            .call_method1("from_batches", (batches, schema))?;
+
+        Ok(table.unbind())
+    }
+
+    fn show(&self, py: Python<'_>) -> PyResult<()> {
+        wait_for(py, self.df.clone().show())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("DataFrame({})", self.df.schema())
+    }
+}
+
+/// Data frame grouped by keys, created with [PyDataFrame::group_by]
+#[pyclass(name = "GroupedData", module = "ballista_python")]
+pub struct PyGroupedData {
+    grouped: GroupedDataFrame,
+}
+
+#[pymethods]
+impl PyGroupedData {
+    /// applies python function to rows of each group, returning rows of
+    /// `schema` (`pyarrow` schema). Function is called with the group
+    /// (`pyarrow` table or `pandas` data frame, according to `mode`),
+    /// or with tuple of group keys and the group, if it accepts two arguments
+    #[pyo3(signature = (func, schema, mode = "arrow", name = None))]
+    fn apply_python(
+        &self,
+        py: Python<'_>,
+        func: PyObject,
+        schema: PyArrowType<Schema>,
+        mode: &str,
+        name: Option<String>,
+    ) -> PyResult<PyDataFrame> {
+        let function = map_batches_function(py, func, schema.0, mode, false, name)?;
+        let df = self.grouped.clone().apply_python(function).map_err(to_py_err)?;
+
+        Ok(PyDataFrame { df })
+    }
+}
+
+/// connects to ballista scheduler, like `connect("df://localhost:50050")`
+#[pyfunction]
+pub fn connect(py: Python<'_>, url: &str) -> PyResult<PyBallistaContext> {
+    let config =
+        SessionConfig::new_with_ballista().with_ballista_logical_extension_codec(Arc::new(PyLogicalCodec::default()));
+    let state = SessionStateBuilder::new()
+        .with_config(config)
+        .with_default_features()
+        .build();
+    let ctx = wait_for(py, async {
+        let ctx = SessionContext::remote_with_state(url, state).await?;
+        ctx.register_function_factory(Arc::new(PythonFunctionFactory::default()))
+            .await?;
+        Ok(ctx)
+    })?;
+    log::debug!("python::connect - connected to: {url}");
+
+    Ok(PyBallistaContext { ctx })
+}
+
+/// closes class based function instances shared by the process,
+/// registered to be called at interpreter exit
+#[pyfunction]
+pub fn close_instances() -> usize {
+    stateful::close_instances()
+}
+
+/// `ballista_python` python extension module
+#[pymodule]
+#[pyo3(name = "ballista_python")]
+pub fn extension_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    // module is loaded into running interpreter, environment
+    // configuration can only adjust python search path. isolated
+    // mode is not applied, search path of the interpreter is its user's
+    PythonEnvConfig::from_env().with_isolated(false).apply()?;
+    m.add_function(wrap_pyfunction!(connect, m)?)?;
+    m.add_function(wrap_pyfunction!(close_instances, m)?)?;
+    // `datafusion.udf` keeping python function, so it can be shipped
+    m.add("udf", compat::udf_function(m.py()).map_err(to_py_err)?)?;
+    m.py()
+        .import("atexit")?
+        .call_method1("register", (m.getattr("close_instances")?,))?;
+    m.add_class::<PyBallistaContext>()?;
+    m.add_class::<PyDataFrame>()?;
+    m.add_class::<PyGroupedData>()?;
+    m.add_class::<PyArrowArray>()?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use crate::requirements::{is_version, is_version_equal};
+use datafusion::common::{exec_datafusion_err, exec_err, Result};
+use datafusion::error::DataFusionError;
+use pyo3::types::{PyAnyMethods, PyStringMethods};
+use pyo3::{PyObject, PyResult, Python};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Reference to a python function pre-installed at executors,
+/// in `module:function@version` format, version being optional.
+///
+/// Versions are compared as release versions, so `1.2` references
+/// installed version `1.2.0` as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionReference {
+    pub module: String,
+    pub function: String,
+    pub version: Option<String>,
+}
+
+impl FunctionReference {
+    pub fn new(module: impl Into<String>, function: impl Into<String>, version: Option<String>) -> Self {
+        Self {
+            module: module.into(),
+            function: function.into(),
+            version,
+        }
+    }
+
+    /// imports referenced function, without checking if module is allowed
+    /// or version matches.
+    pub fn import(&self, py: Python<'_>) -> PyResult<PyObject> {
+        Ok(py
+            .import(self.module.as_str())?
+            .getattr(self.function.as_str())?
+            .unbind())
+    }
+
+    /// checks if installed module version matches referenced version,
+    /// if version is referenced
+    pub fn check_version(&self, py: Python<'_>) -> Result<()> {
+        let Some(version) = &self.version else {
+            return Ok(());
+        };
+        let installed = self
+            .installed_version(py)
+            .map_err(|e| exec_datafusion_err!("python function: {self} can't be resolved: {e}"))?;
+        match installed {
+            Some(installed) if is_version_equal(&installed, version) => Ok(()),
+            Some(installed) => exec_err!("python function: {self} not found, installed version is: {installed}"),
+            None => exec_err!("python function: {self} not found, module version is not known"),
+        }
+    }
+
+    /// version of the installed module, `__version__` module attribute
+    /// or installed distribution version of the top level package.
+    fn installed_version(&self, py: Python<'_>) -> PyResult<Option<String>> {
+        let module = py.import(self.module.as_str())?;
+        if let Ok(version) = module.getattr("__version__") {
+            return Ok(Some(version.str()?.to_str()?.to_string()));
+        }
+
+        let package = self.module.split('.').next().unwrap_or_default();
+        let metadata = py.import("importlib.metadata")?;
+        match metadata.getattr("version")?.call1((package,)) {
+            Ok(version) => Ok(Some(version.str()?.to_str()?.to_string())),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl FromStr for FunctionReference {
+    type Err = DataFusionError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (path, version) = match s.split_once('@') {
+            Some((path, version)) => (path, Some(version.trim().to_string())),
+            None => (s, None),
+        };
+        if let Some(version) = version.as_ref().filter(|v| !is_version(v)) {
+            return exec_err!("invalid python function reference: {s}, version: {version} is not supported");
+        }
+        match path.split_once(':') {
+            Some((module, function)) if !module.trim().is_empty() && !function.trim().is_empty() => {
+                Ok(Self::new(module.trim(), function.trim(), version))
+            }
+            _ => exec_err!("invalid python function reference: {s}, expected `module:function@version`"),
+        }
+    }
+}
+
+impl Display for FunctionReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.module, self.function)?;
+        if let Some(version) = &self.version {
+            write!(f, "@{version}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Registry of vetted python functions, pre-installed at executors.
+///
+/// Functions shipped by reference are resolved against registry
+/// instead of being unpickled, only functions from listed modules
+/// (or their sub-modules) can be resolved.
+#[derive(Debug, Clone, Default)]
+pub struct PyFunctionRegistry {
+    modules: Vec<String>,
+}
+
+impl PyFunctionRegistry {
+    pub fn new(modules: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            modules: modules.into_iter().map(|m| m.into()).collect(),
+        }
+    }
+
+    /// resolves referenced function, checking if function module
+    /// is allowed and if installed version matches referenced version
+    pub fn resolve(&self, py: Python<'_>, reference: &FunctionReference) -> Result<PyObject> {
+        if !self.is_allowed(&reference.module) {
+            return exec_err!("python function: {reference} is not from a registered module");
+        }
+
+        reference.check_version(py)?;
+        reference
+            .import(py)
+            .map_err(|e| exec_datafusion_err!("python function: {reference} can't be resolved: {e}"))
+    }
+
+    fn is_allowed(&self, module: &str) -> bool {
+        self.modules
+            .iter()
+            .any(|m| m == module || module.strip_prefix(m.as_str()).is_some_and(|s| s.starts_with('.')))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FunctionReference, PyFunctionRegistry};
+    use std::str::FromStr;
+
+    #[test]
+    fn should_parse_reference() {
+        let reference = FunctionReference::from_str("udfs.geo:to_miles@1.2.0").unwrap();
+        assert_eq!(
+            FunctionReference::new("udfs.geo", "to_miles", Some("1.2.0".to_string())),
+            reference
+        );
+        assert_eq!("udfs.geo:to_miles@1.2.0", reference.to_string());
+
+        let reference = FunctionReference::from_str("udfs:to_miles").unwrap();
+        assert_eq!(None, reference.version);
+
+        assert!(FunctionReference::from_str("to_miles").is_err());
+        assert!(FunctionReference::from_str("udfs:@1.0").is_err());
+        assert!(FunctionReference::from_str("udfs:to_miles@2.0rc1").is_ok());
+        assert!(FunctionReference::from_str("udfs:to_miles@latest").is_err());
+        assert!(FunctionReference::from_str("udfs:to_miles@").is_err());
+    }
+
+    #[test]
+    fn should_allow_registered_modules_only() {
+        let registry = PyFunctionRegistry::new(["udfs"]);
+
+        assert!(registry.is_allowed("udfs"));
+        assert!(registry.is_allowed("udfs.geo"));
+        assert!(!registry.is_allowed("udfs_evil"));
+        assert!(!registry.is_allowed("os"));
+    }
+}
@@ -0,0 +1,528 @@
+use datafusion::common::{exec_err, Result};
+use datafusion::error::DataFusionError;
+use pyo3::types::{PyAnyMethods, PyStringMethods};
+use pyo3::Python;
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Python package required by a function, with optional version
+/// specifier, like `scikit-learn>=1.3,<2`.
+///
+/// Versions are compared as defined in PEP 440, including epochs,
+/// pre, post and development releases and local labels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PythonRequirement {
+    pub package: String,
+    /// comma separated version specifiers, empty if any version is accepted
+    pub specifier: String,
+}
+
+impl PythonRequirement {
+    /// checks if installed version satisfies requirement's version specifier
+    pub fn is_satisfied_by(&self, version: &str) -> bool {
+        self.specifier
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .all(|s| {
+                // specifier has been validated when requirement was created
+                let (op, expected) = split_specifier(s).unwrap_or(("==", s));
+                matches(op, version, expected)
+            })
+    }
+
+    /// parses list of requirements separated by `;`
+    pub fn parse_list(requirements: &str) -> Result<Vec<Self>> {
+        requirements
+            .split(';')
+            .map(|r| r.trim())
+            .filter(|r| !r.is_empty())
+            .map(Self::from_str)
+            .collect()
+    }
+}
+
+impl FromStr for PythonRequirement {
+    type Err = DataFusionError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let end = s
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'))
+            .unwrap_or(s.len());
+        let (package, specifier) = s.split_at(end);
+        if package.is_empty() {
+            return exec_err!("invalid python requirement: {s}");
+        }
+        let specifier = specifier.split_whitespace().collect::<String>();
+        for s in specifier.split(',').filter(|s| !s.is_empty()) {
+            if split_specifier(s).is_none() {
+                return exec_err!("invalid python requirement version specifier: {s}");
+            }
+        }
+
+        Ok(Self {
+            package: package.to_string(),
+            specifier,
+        })
+    }
+}
+
+impl Display for PythonRequirement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.package, self.specifier)
+    }
+}
+
+/// checks if requirements are installed, using `importlib.metadata`,
+/// returns error listing all missing or mismatched packages.
+pub fn check_requirements(py: Python<'_>, name: &str, requirements: &[PythonRequirement]) -> Result<()> {
+    if requirements.is_empty() {
+        return Ok(());
+    }
+    let version = py
+        .import("importlib.metadata")
+        .and_then(|m| m.getattr("version"))
+        .map_err(|e| DataFusionError::Execution(e.to_string()))?;
+
+    let mut missing = vec![];
+    let mut mismatched = vec![];
+    for requirement in requirements {
+        let installed = version
+            .call1((requirement.package.as_str(),))
+            .and_then(|v| Ok(v.str()?.to_str()?.to_string()));
+        match installed {
+            Err(_) => missing.push(requirement.to_string()),
+            Ok(installed) if !requirement.is_satisfied_by(&installed) => {
+                mismatched.push(format!("{requirement} (installed {installed})"))
+            }
+            Ok(_) => (),
+        }
+    }
+
+    match (missing.is_empty(), mismatched.is_empty()) {
+        (true, true) => Ok(()),
+        _ => exec_err!(
+            "python function: {name} requirements not satisfied, missing: [{}], mismatched: [{}]",
+            missing.join(", "),
+            mismatched.join(", ")
+        ),
+    }
+}
+
+/// checks if version is a valid PEP 440 version
+pub(crate) fn is_version(version: &str) -> bool {
+    Version::from_str(version).is_ok()
+}
+
+/// checks if versions are equal, ignoring trailing zeros
+/// of release segment, like `==` specifier
+pub(crate) fn is_version_equal(version: &str, other: &str) -> bool {
+    matches("==", version, other)
+}
+
+fn split_specifier(specifier: &str) -> Option<(&str, &str)> {
+    ["===", "==", "!=", "~=", ">=", "<=", ">", "<"]
+        .into_iter()
+        .find_map(|op| specifier.strip_prefix(op).map(|v| (op, v)))
+        .filter(|(op, v)| is_valid_specifier(op, v))
+}
+
+/// checks if specifier version can be compared using the operator
+fn is_valid_specifier(op: &str, version: &str) -> bool {
+    match (op, version.strip_suffix(".*")) {
+        ("===", _) => !version.is_empty(),
+        // prefix match, only release segment can be matched
+        ("==" | "!=", Some(prefix)) => Version::from_str(prefix).is_ok_and(|v| v.is_release_only()),
+        (_, Some(_)) => false,
+        // compatible release needs at least two release segments
+        ("~=", None) => Version::from_str(version).is_ok_and(|v| v.release.len() > 1 && v.local.is_empty()),
+        ("==" | "!=", None) => Version::from_str(version).is_ok(),
+        // local versions can be matched only for equality
+        (_, None) => Version::from_str(version).is_ok_and(|v| v.local.is_empty()),
+    }
+}
+
+/// checks if installed version matches specifier, unparsable installed
+/// version matches only `===`. Pre-releases of installed packages are
+/// accepted, as they have already been installed.
+fn matches(op: &str, version: &str, expected: &str) -> bool {
+    if op == "===" {
+        return version.eq_ignore_ascii_case(expected);
+    }
+    let Ok(version) = Version::from_str(version) else {
+        return false;
+    };
+    if let Some(prefix) = expected.strip_suffix(".*") {
+        let Ok(prefix) = Version::from_str(prefix) else {
+            return false;
+        };
+        return match op {
+            "==" => version.has_prefix(&prefix),
+            "!=" => !version.has_prefix(&prefix),
+            _ => false,
+        };
+    }
+    let Ok(expected) = Version::from_str(expected) else {
+        return false;
+    };
+    // local label of installed version is ignored,
+    // unless specifier has local label as well
+    let version = match expected.local.is_empty() {
+        true => version.public(),
+        false => version,
+    };
+    match op {
+        "==" => version == expected,
+        "!=" => version != expected,
+        ">=" => version >= expected,
+        "<=" => version <= expected,
+        // `>1.7` does not match `1.7.post1`, unless expected is post-release
+        ">" => version > expected && (expected.post.is_some() || !version.is_post_of(&expected)),
+        // `<1.7` does not match `1.7rc1`, unless expected is pre-release
+        "<" => version < expected && (expected.is_pre_release() || !version.is_pre_of(&expected)),
+        // compatible release, `~=1.4.2` is `>=1.4.2,==1.4.*`
+        "~=" => {
+            let prefix = Version {
+                release: expected.release[..expected.release.len() - 1].to_vec(),
+                ..Version::default()
+            };
+            version >= expected && version.has_prefix(&prefix)
+        }
+        _ => false,
+    }
+}
+
+/// pre-release kind, ordered as in PEP 440
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum PreRelease {
+    Alpha,
+    Beta,
+    Candidate,
+}
+
+/// epoch, release, pre, post and dev segments, ordered as in PEP 440
+type VersionKey = (u64, Vec<u64>, (u8, Option<(PreRelease, u64)>), Option<u64>, (u8, u64));
+
+/// PEP 440 version, in normalized form
+#[derive(Debug, Clone, Default)]
+struct Version {
+    epoch: u64,
+    release: Vec<u64>,
+    pre: Option<(PreRelease, u64)>,
+    post: Option<u64>,
+    dev: Option<u64>,
+    local: Vec<String>,
+}
+
+impl Version {
+    fn is_release_only(&self) -> bool {
+        self.pre.is_none() && self.post.is_none() && self.dev.is_none() && self.local.is_empty()
+    }
+
+    fn is_pre_release(&self) -> bool {
+        self.pre.is_some() || self.dev.is_some()
+    }
+
+    /// version without local label
+    fn public(self) -> Self {
+        Self { local: vec![], ..self }
+    }
+
+    /// release segment padded with zeros to given length,
+    /// as trailing zeros are not significant
+    fn padded_release(&self, len: usize) -> Vec<u64> {
+        let mut release = self.release.clone();
+        release.resize(len.max(release.len()), 0);
+        release
+    }
+
+    fn same_release(&self, other: &Self) -> bool {
+        let len = self.release.len().max(other.release.len());
+        self.epoch == other.epoch && self.padded_release(len) == other.padded_release(len)
+    }
+
+    fn is_pre_of(&self, other: &Self) -> bool {
+        self.is_pre_release() && self.same_release(other)
+    }
+
+    fn is_post_of(&self, other: &Self) -> bool {
+        self.post.is_some() && self.same_release(other)
+    }
+
+    /// checks if release segment starts with release segment of prefix
+    fn has_prefix(&self, prefix: &Self) -> bool {
+        self.epoch == prefix.epoch
+            && self.padded_release(prefix.release.len())[..prefix.release.len()] == prefix.release
+    }
+
+    /// key ordering versions as in PEP 440, dev releases come before
+    /// pre-releases, pre-releases before final and post-releases
+    fn key(&self) -> VersionKey {
+        let mut release = self.release.clone();
+        while release.last() == Some(&0) {
+            release.pop();
+        }
+        let pre = match (self.pre, self.post, self.dev) {
+            (None, None, Some(_)) => (0, None),
+            (Some(pre), _, _) => (1, Some(pre)),
+            (None, _, _) => (2, None),
+        };
+        let dev = match self.dev {
+            Some(dev) => (0, dev),
+            None => (1, 0),
+        };
+        (self.epoch, release, pre, self.post, dev)
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key()
+            .cmp(&other.key())
+            .then_with(|| compare_local(&self.local, &other.local))
+    }
+}
+
+/// numeric local segments are greater than alphanumeric
+/// ones, and compared numerically
+fn compare_local(local: &[String], other: &[String]) -> Ordering {
+    let key = |s: &String| match s.parse::<u64>() {
+        Ok(n) => (1, n, String::new()),
+        Err(_) => (0, 0, s.clone()),
+    };
+    local.iter().map(key).cmp(other.iter().map(key))
+}
+
+impl FromStr for Version {
+    type Err = DataFusionError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || exec_err!("invalid python version: {s}");
+        let normalized = s.trim().to_lowercase();
+        let (public, local) = match normalized.split_once('+') {
+            Some((public, local)) => (public, Some(local)),
+            None => (normalized.as_str(), None),
+        };
+        let mut version = Version::default();
+        if let Some(local) = local {
+            version.local = local.split(['.', '-', '_']).map(str::to_string).collect();
+            if version
+                .local
+                .iter()
+                .any(|s| s.is_empty() || !s.chars().all(|c| c.is_ascii_alphanumeric()))
+            {
+                return invalid();
+            }
+        }
+
+        let mut rest = public.strip_prefix('v').unwrap_or(public);
+        if let Some((epoch, tail)) = rest.split_once('!') {
+            let Ok(epoch) = epoch.parse() else {
+                return invalid();
+            };
+            version.epoch = epoch;
+            rest = tail;
+        }
+
+        // release segment, like `1.2.3`
+        loop {
+            let (number, tail) = take_number(rest);
+            let Some(number) = number else {
+                return invalid();
+            };
+            version.release.push(number);
+            match tail
+                .strip_prefix('.')
+                .filter(|t| t.starts_with(|c: char| c.is_ascii_digit()))
+            {
+                Some(tail) => rest = tail,
+                None => {
+                    rest = tail;
+                    break;
+                }
+            }
+        }
+
+        // pre-release, like `rc1`, `.beta.2` or `a`
+        let tail = strip_separator(rest);
+        let pre = [
+            ("alpha", PreRelease::Alpha),
+            ("beta", PreRelease::Beta),
+            ("preview", PreRelease::Candidate),
+            ("pre", PreRelease::Candidate),
+            ("rc", PreRelease::Candidate),
+            ("a", PreRelease::Alpha),
+            ("b", PreRelease::Beta),
+            ("c", PreRelease::Candidate),
+        ]
+        .into_iter()
+        .find_map(|(label, kind)| tail.strip_prefix(label).map(|t| (kind, t)));
+        if let Some((kind, tail)) = pre {
+            let (number, tail) = take_separated_number(tail);
+            version.pre = Some((kind, number.unwrap_or_default()));
+            rest = tail;
+        }
+
+        // post-release, like `.post1`, `-r2` or implicit `-1`
+        let tail = strip_separator(rest);
+        let post = ["post", "rev", "r"]
+            .into_iter()
+            .find_map(|label| tail.strip_prefix(label));
+        if let Some(tail) = post {
+            let (number, tail) = take_separated_number(tail);
+            version.post = Some(number.unwrap_or_default());
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix('-') {
+            let (number, tail) = take_number(tail);
+            if let Some(number) = number {
+                version.post = Some(number);
+                rest = tail;
+            }
+        }
+
+        // development release, like `.dev3`
+        if let Some(tail) = strip_separator(rest).strip_prefix("dev") {
+            let (number, tail) = take_separated_number(tail);
+            version.dev = Some(number.unwrap_or_default());
+            rest = tail;
+        }
+
+        match rest.is_empty() {
+            true => Ok(version),
+            false => invalid(),
+        }
+    }
+}
+
+fn strip_separator(s: &str) -> &str {
+    s.strip_prefix(['.', '-', '_']).unwrap_or(s)
+}
+
+fn take_number(s: &str) -> (Option<u64>, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    (s[..end].parse().ok(), &s[end..])
+}
+
+/// number, optionally preceded by separator
+fn take_separated_number(s: &str) -> (Option<u64>, &str) {
+    match take_number(strip_separator(s)) {
+        (Some(number), tail) => (Some(number), tail),
+        (None, _) => (None, s),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PythonRequirement;
+    use std::str::FromStr;
+
+    #[test]
+    fn should_parse_requirements() {
+        let requirement = PythonRequirement::from_str("scikit-learn >= 1.3, < 2").unwrap();
+        assert_eq!("scikit-learn", requirement.package);
+        assert_eq!(">=1.3,<2", requirement.specifier);
+        assert_eq!("scikit-learn>=1.3,<2", requirement.to_string());
+
+        let requirements = PythonRequirement::parse_list("pandas; numpy==1.26.*").unwrap();
+        assert_eq!(2, requirements.len());
+        assert_eq!("", requirements[0].specifier);
+
+        assert!(PythonRequirement::from_str(">=1.0").is_err());
+        assert!(PythonRequirement::from_str("pandas=>1.0").is_err());
+    }
+
+    #[test]
+    fn should_match_versions() {
+        let requirement = PythonRequirement::from_str("scikit-learn>=1.3,<2").unwrap();
+        assert!(requirement.is_satisfied_by("1.3.0"));
+        assert!(requirement.is_satisfied_by("1.5.2"));
+        assert!(!requirement.is_satisfied_by("1.2.9"));
+        assert!(!requirement.is_satisfied_by("2.0"));
+
+        let requirement = PythonRequirement::from_str("numpy==1.26.*").unwrap();
+        assert!(requirement.is_satisfied_by("1.26.4"));
+        assert!(!requirement.is_satisfied_by("1.27.0"));
+
+        let requirement = PythonRequirement::from_str("pandas~=2.1.0").unwrap();
+        assert!(requirement.is_satisfied_by("2.1.4"));
+        assert!(!requirement.is_satisfied_by("2.2.0"));
+
+        let requirement = PythonRequirement::from_str("pyarrow!=15.0.0").unwrap();
+        assert!(requirement.is_satisfied_by("16.0"));
+        assert!(!requirement.is_satisfied_by("15"));
+
+        assert!(PythonRequirement::from_str("pandas").unwrap().is_satisfied_by("0.1"));
+    }
+
+    #[test]
+    fn should_match_pre_post_and_dev_releases() {
+        let requirement = PythonRequirement::from_str("pandas>=2.0").unwrap();
+        assert!(!requirement.is_satisfied_by("2.0.0rc1"));
+        assert!(!requirement.is_satisfied_by("2.0.dev3"));
+        assert!(requirement.is_satisfied_by("2.0.post1"));
+        assert!(requirement.is_satisfied_by("2.1.0b1"));
+        assert!(requirement.is_satisfied_by("1!0.1"));
+
+        let requirement = PythonRequirement::from_str("pandas<2.0").unwrap();
+        assert!(requirement.is_satisfied_by("1.9"));
+        assert!(!requirement.is_satisfied_by("2.0rc1"));
+        assert!(PythonRequirement::from_str("pandas<2.0rc2")
+            .unwrap()
+            .is_satisfied_by("2.0rc1"));
+
+        let requirement = PythonRequirement::from_str("pandas>2.0").unwrap();
+        assert!(!requirement.is_satisfied_by("2.0.post1"));
+        assert!(requirement.is_satisfied_by("2.0.1"));
+
+        // ordering of pre-release kinds, dev and post releases
+        let requirement = PythonRequirement::from_str("torch>1.0a2,<1.0rc2").unwrap();
+        assert!(requirement.is_satisfied_by("1.0b1"));
+        assert!(requirement.is_satisfied_by("1.0.RC1"));
+        assert!(!requirement.is_satisfied_by("1.0a1"));
+        assert!(!requirement.is_satisfied_by("1.0.dev1"));
+
+        // local labels are ignored, unless specified
+        assert!(PythonRequirement::from_str("torch==2.1.0")
+            .unwrap()
+            .is_satisfied_by("2.1.0+cu121"));
+        assert!(!PythonRequirement::from_str("torch==2.1.0+cpu")
+            .unwrap()
+            .is_satisfied_by("2.1.0+cu121"));
+        assert!(PythonRequirement::from_str("numpy==1.26.*")
+            .unwrap()
+            .is_satisfied_by("1.26.0rc1"));
+    }
+
+    #[test]
+    fn should_reject_unparsable_versions() {
+        assert!(PythonRequirement::from_str("pandas>=latest").is_err());
+        assert!(PythonRequirement::from_str("pandas~=2").is_err());
+        assert!(PythonRequirement::from_str("pandas>=2.*").is_err());
+        assert!(PythonRequirement::from_str("pandas==2.0rc1.*").is_err());
+        assert!(PythonRequirement::from_str("pandas===build-7").is_ok());
+
+        // unparsable installed version matches only `===`
+        assert!(!PythonRequirement::from_str("pandas>=1.0")
+            .unwrap()
+            .is_satisfied_by("unknown"));
+        assert!(PythonRequirement::from_str("pandas===build-7")
+            .unwrap()
+            .is_satisfied_by("build-7"));
+    }
+}
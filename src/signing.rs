@@ -0,0 +1,101 @@
+use datafusion::common::{exec_err, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt::Debug;
+
+/// environment variable used to configure shared UDF signing key
+pub static ENV_SIGNING_KEY: &str = "BALLISTA_PYTHON_UDF_KEY";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs and verifies serialized UDF payloads using HMAC-SHA256
+/// and a key shared between clients, schedulers and executors.
+///
+/// Verification happens before payload is unpickled, so a process
+/// configured with a signer will never execute code it did not
+/// receive from a party knowing the key.
+#[derive(Clone)]
+pub struct UdfSigner {
+    key: Vec<u8>,
+}
+
+impl UdfSigner {
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self { key: key.into() }
+    }
+
+    /// creates signer from [ENV_SIGNING_KEY] environment variable,
+    /// returns `None` if variable is not set or is empty.
+    pub fn from_env() -> Option<Self> {
+        std::env::var(ENV_SIGNING_KEY)
+            .ok()
+            .filter(|key| !key.is_empty())
+            .map(Self::new)
+    }
+
+    /// signs function `name` and its encoded payload
+    pub fn sign(&self, name: &str, payload: &[u8]) -> Vec<u8> {
+        self.mac(name, payload).finalize().into_bytes().to_vec()
+    }
+
+    /// verifies signature of function `name` and its encoded payload
+    pub fn verify(&self, name: &str, payload: &[u8], signature: &[u8]) -> Result<()> {
+        if signature.is_empty() {
+            return exec_err!("python function: {name} is not signed, unsigned functions are rejected");
+        }
+        // constant time comparison provided by `verify_slice`
+        match self.mac(name, payload).verify_slice(signature) {
+            Ok(_) => Ok(()),
+            Err(_) => exec_err!("python function: {name} signature verification failed"),
+        }
+    }
+
+    fn mac(&self, name: &str, payload: &[u8]) -> HmacSha256 {
+        // hmac accepts keys of any size
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac to accept key of any size");
+        // name is bound to signature, so signed payload
+        // can't be reused for a function with different name
+        mac.update(name.as_bytes());
+        mac.update(&[0]);
+        mac.update(payload);
+        mac
+    }
+}
+
+impl Debug for UdfSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UdfSigner").field("key", &"<KEY>").finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UdfSigner;
+
+    #[test]
+    fn should_verify_signed_payload() {
+        let signer = UdfSigner::new("secret");
+        let signature = signer.sign("to_miles", b"payload");
+
+        assert!(signer.verify("to_miles", b"payload", &signature).is_ok());
+    }
+
+    #[test]
+    fn should_reject_tampered_payload() {
+        let signer = UdfSigner::new("secret");
+        let signature = signer.sign("to_miles", b"payload");
+
+        assert!(signer.verify("to_miles", b"payl0ad", &signature).is_err());
+        assert!(signer.verify("to_km", b"payload", &signature).is_err());
+        assert!(UdfSigner::new("other")
+            .verify("to_miles", b"payload", &signature)
+            .is_err());
+    }
+
+    #[test]
+    fn should_reject_unsigned_payload() {
+        let signer = UdfSigner::new("secret");
+
+        assert!(signer.verify("to_miles", b"payload", &[]).is_err());
+    }
+}
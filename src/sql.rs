@@ -0,0 +1,351 @@
+use crate::catalog::FunctionDefinition;
+use crate::factory::{create_with_statement_options, FunctionOptions, PythonFunctionFactory};
+use crate::udf::PythonUDF;
+use datafusion::arrow::array::{RecordBatch, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::common::config::SqlParserOptions;
+use datafusion::common::{exec_datafusion_err, plan_err, Result};
+use datafusion::execution::context::RegisterFunction;
+use datafusion::execution::{FunctionRegistry, SessionState};
+use datafusion::logical_expr::{DdlStatement, DropFunction, LogicalPlan, LogicalPlanBuilder};
+use datafusion::prelude::{DataFrame, SessionContext};
+use datafusion::sql::parser::Statement as DFStatement;
+use datafusion::sql::planner::IdentNormalizer;
+use datafusion::sql::sqlparser::ast::{ObjectNamePart, ShowCreateObject, Statement};
+use datafusion::sql::sqlparser::dialect::{dialect_from_str, Dialect};
+use datafusion::sql::sqlparser::parser::Parser;
+use datafusion::sql::sqlparser::tokenizer::{Token, TokenWithSpan, Tokenizer};
+use std::sync::Arc;
+
+/// Python specific sql extensions of [SessionContext].
+#[async_trait::async_trait]
+pub trait PythonSessionExt {
+    /// executes sql statement, like [SessionContext::sql], supporting
+    /// `OPTIONS (key 'value', ...)` clause of `CREATE FUNCTION` statement,
+    /// `STRICT` (`RETURNS NULL ON NULL INPUT`) clause and
+    /// `SHOW CREATE FUNCTION name` statement of python functions,
+    /// which are not supported by datafusion sql planner.
+    ///
+    /// functions are created by function factory installed in the session,
+    /// or default [PythonFunctionFactory] if none is installed. Options are
+    /// passed to [PythonFunctionFactory], statement with options fails
+    /// if other function factory is installed. `DROP FUNCTION` removes
+    /// the function from catalog and cluster registry of the factory
+    /// installed by [PythonSessionExt::register_function_factory] as well.
+    ///
+    /// ```sql
+    /// CREATE FUNCTION to_miles(DOUBLE)
+    /// RETURNS DOUBLE
+    /// LANGUAGE PYTHON
+    /// OPTIONS (requirements 'numpy>=1.26')
+    /// AS '...'
+    /// ```
+    async fn python_sql(&self, sql: &str) -> Result<DataFrame>;
+
+    /// sets function factory of the session, registering it as session
+    /// config extension as well, and registers functions stored
+    /// in factory's catalog.
+    async fn register_function_factory(&self, factory: Arc<PythonFunctionFactory>) -> Result<()>;
+}
+
+#[async_trait::async_trait]
+impl PythonSessionExt for SessionContext {
+    async fn python_sql(&self, sql: &str) -> Result<DataFrame> {
+        let state = self.state();
+        if let Some(name) = parse_show_create_function(sql, &state.config().options().sql_parser)? {
+            return show_create_function(self, &name);
+        }
+        let Some((statement, options)) = parse_create_function(sql, &state.config().options().sql_parser.dialect)?
+        else {
+            let plan = state.create_logical_plan(sql).await?;
+            return match plan {
+                LogicalPlan::Ddl(DdlStatement::DropFunction(statement)) => drop_function(self, statement).await,
+                plan => self.execute_logical_plan(plan).await,
+            };
+        };
+
+        let plan = state
+            .statement_to_plan(DFStatement::Statement(Box::new(statement)))
+            .await?;
+        let statement = match plan {
+            LogicalPlan::Ddl(DdlStatement::CreateFunction(statement)) => statement,
+            _ => return plan_err!("create function statement expected"),
+        };
+        log::debug!("python_sql::create function: {}, options: {options:?}", statement.name);
+
+        // function factory installed in the session, like by datafusion
+        let factory = match state.function_factory() {
+            Some(factory) => factory.clone(),
+            None => Arc::new(PythonFunctionFactory::default()),
+        };
+        let function = create_with_statement_options(options, factory.create(&state, statement)).await?;
+        register_function(self, function);
+
+        Ok(DataFrame::new(self.state(), LogicalPlanBuilder::empty(false).build()?))
+    }
+
+    async fn register_function_factory(&self, factory: Arc<PythonFunctionFactory>) -> Result<()> {
+        {
+            let state = self.state_ref();
+            let mut state = state.write();
+            state.set_function_factory(factory.clone());
+            state.config_mut().set_extension(factory.clone());
+        }
+        let functions = factory.catalog_functions(&self.state()).await?;
+        log::debug!(
+            "python_sql::register_function_factory - {} catalog functions",
+            functions.len()
+        );
+        for function in functions {
+            register_function(self, function);
+        }
+
+        Ok(())
+    }
+}
+
+/// returns statement creating python function,
+/// in `function_name` and `definition` columns
+fn show_create_function(ctx: &SessionContext, name: &str) -> Result<DataFrame> {
+    let udf = ctx.udf(name)?;
+    let definition = udf
+        .inner()
+        .as_any()
+        .downcast_ref::<PythonUDF>()
+        .and_then(FunctionDefinition::from_udf)
+        .ok_or_else(|| exec_datafusion_err!("function: {name} is not python function created from source"))?;
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("function_name", DataType::Utf8, false),
+        Field::new("definition", DataType::Utf8, false),
+    ]));
+    let batch = RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(StringArray::from(vec![definition.name.clone()])),
+            Arc::new(StringArray::from(vec![definition.to_sql()?])),
+        ],
+    )?;
+
+    ctx.read_batch(batch)
+}
+
+/// drops function from the session, factory's catalog and cluster registry
+async fn drop_function(ctx: &SessionContext, statement: DropFunction) -> Result<DataFrame> {
+    // function stored in the catalog exists,
+    // even if it is not registered in this session
+    let removed = match installed_python_factory(&ctx.state()) {
+        Some(factory) => factory.drop_function(&ctx.state(), &statement.name).await?,
+        None => false,
+    };
+    log::debug!("python_sql::drop function: {}", statement.name);
+    let statement = DropFunction {
+        if_exists: statement.if_exists || removed,
+        ..statement
+    };
+
+    ctx.execute_logical_plan(LogicalPlan::Ddl(DdlStatement::DropFunction(statement)))
+        .await
+}
+
+/// [PythonFunctionFactory] installed in the session by
+/// [PythonSessionExt::register_function_factory], `None` if it has been
+/// replaced by other function factory since
+fn installed_python_factory(state: &SessionState) -> Option<Arc<PythonFunctionFactory>> {
+    let installed = state.function_factory()?;
+    let factory = state.config().get_extension::<PythonFunctionFactory>()?;
+    // function factory can't be downcast, it is matched by address
+    std::ptr::addr_eq(Arc::as_ptr(installed), Arc::as_ptr(&factory)).then_some(factory)
+}
+
+fn register_function(ctx: &SessionContext, function: RegisterFunction) {
+    match function {
+        RegisterFunction::Scalar(f) => ctx.register_udf(f.as_ref().clone()),
+        RegisterFunction::Aggregate(f) => ctx.register_udaf(f.as_ref().clone()),
+        RegisterFunction::Window(f) => ctx.register_udwf(f.as_ref().clone()),
+        RegisterFunction::Table(name, f) => ctx.register_udtf(&name, f),
+    }
+}
+
+/// parses `CREATE FUNCTION` statement and its options,
+/// returns `None` for other statements
+fn parse_create_function(sql: &str, dialect_name: &str) -> Result<Option<(Statement, FunctionOptions)>> {
+    let dialect = dialect_from_str(dialect_name)
+        .ok_or_else(|| exec_datafusion_err!("unsupported sql dialect: {dialect_name}"))?;
+    let tokens = Tokenizer::new(dialect.as_ref(), sql)
+        .tokenize_with_location()
+        .map_err(|e| exec_datafusion_err!("statement can't be tokenized: {e}"))?;
+
+    if !is_create_function(&tokens) {
+        return Ok(None);
+    }
+
+    let (tokens, options) = extract_options(tokens)?;
+    let statement = parse_statement(dialect.as_ref(), tokens)?;
+    let options = match &statement {
+        Statement::CreateFunction(function) => options.with_called_on_null(function.called_on_null.as_ref()),
+        _ => options,
+    };
+
+    Ok(Some((statement, options)))
+}
+
+/// parses `SHOW CREATE FUNCTION name` statement, returns
+/// normalized function name, `None` for other statements
+fn parse_show_create_function(sql: &str, options: &SqlParserOptions) -> Result<Option<String>> {
+    let dialect = dialect_from_str(&options.dialect)
+        .ok_or_else(|| exec_datafusion_err!("unsupported sql dialect: {}", options.dialect))?;
+    let tokens = Tokenizer::new(dialect.as_ref(), sql)
+        .tokenize_with_location()
+        .map_err(|e| exec_datafusion_err!("statement can't be tokenized: {e}"))?;
+    let words = significant(&tokens).map(|(_, t)| t).take(3).collect::<Vec<_>>();
+    if !matches!(words.as_slice(), [show, create, function]
+        if is_keyword(show, "SHOW") && is_keyword(create, "CREATE") && is_keyword(function, "FUNCTION"))
+    {
+        return Ok(None);
+    }
+
+    match parse_statement(dialect.as_ref(), tokens)? {
+        Statement::ShowCreate {
+            obj_type: ShowCreateObject::Function,
+            obj_name,
+        } => match obj_name.0.into_iter().last() {
+            Some(ObjectNamePart::Identifier(ident)) => Ok(Some(
+                IdentNormalizer::new(options.enable_ident_normalization).normalize(ident),
+            )),
+            None => plan_err!("function name expected"),
+        },
+        _ => plan_err!("SHOW CREATE FUNCTION statement expected"),
+    }
+}
+
+fn significant(tokens: &[TokenWithSpan]) -> impl Iterator<Item = (usize, &Token)> {
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| !matches!(t.token, Token::Whitespace(_)))
+        .map(|(i, t)| (i, &t.token))
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    matches!(token, Token::Word(w) if w.quote_style.is_none() && w.value.eq_ignore_ascii_case(keyword))
+}
+
+/// checks if statement is `CREATE [OR REPLACE] [TEMPORARY] FUNCTION`
+fn is_create_function(tokens: &[TokenWithSpan]) -> bool {
+    let words = significant(tokens).map(|(_, t)| t).take(5).collect::<Vec<_>>();
+    let mut words = words.as_slice();
+    match words.first() {
+        Some(t) if is_keyword(t, "CREATE") => words = &words[1..],
+        _ => return false,
+    }
+    if words.len() >= 2 && is_keyword(words[0], "OR") && is_keyword(words[1], "REPLACE") {
+        words = &words[2..];
+    }
+    if words
+        .first()
+        .is_some_and(|t| is_keyword(t, "TEMPORARY") || is_keyword(t, "TEMP"))
+    {
+        words = &words[1..];
+    }
+    words.first().is_some_and(|t| is_keyword(t, "FUNCTION"))
+}
+
+/// removes `OPTIONS (key 'value', ...)` clause from statement tokens,
+/// returning remaining tokens and parsed options
+fn extract_options(tokens: Vec<TokenWithSpan>) -> Result<(Vec<TokenWithSpan>, FunctionOptions)> {
+    let significant = significant(&tokens).collect::<Vec<_>>();
+    let mut depth = 0;
+    let mut start = None;
+    for (position, (_, token)) in significant.iter().enumerate() {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            t if depth == 0 && is_keyword(t, "OPTIONS") => {
+                if matches!(significant.get(position + 1), Some((_, Token::LParen))) {
+                    start = Some(position);
+                    break;
+                }
+            }
+            _ => (),
+        }
+    }
+    let Some(start) = start else {
+        return Ok((tokens, FunctionOptions::default()));
+    };
+
+    let mut options = vec![];
+    let mut position = start + 2;
+    loop {
+        let key = match significant.get(position) {
+            Some((_, Token::RParen)) if options.is_empty() => break,
+            Some((_, Token::Word(w))) => w.value.clone(),
+            t => return plan_err!("option name expected, found: {:?}", t.map(|(_, t)| t)),
+        };
+        position += 1;
+        if matches!(significant.get(position), Some((_, Token::Eq))) {
+            position += 1;
+        }
+        let value = match significant.get(position) {
+            Some((_, Token::SingleQuotedString(v))) | Some((_, Token::DoubleQuotedString(v))) => v.clone(),
+            Some((_, Token::DollarQuotedString(v))) => v.value.clone(),
+            Some((_, Token::Number(v, _))) => v.clone(),
+            _ => return plan_err!("value of option: {key} expected"),
+        };
+        options.push((key, value));
+        position += 1;
+        match significant.get(position) {
+            Some((_, Token::Comma)) => position += 1,
+            Some((_, Token::RParen)) => break,
+            _ => return plan_err!("options clause not closed"),
+        }
+    }
+
+    let (first, last) = (significant[start].0, significant[position].0);
+    let tokens = tokens
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| *i < first || *i > last)
+        .map(|(_, t)| t)
+        .collect();
+
+    Ok((tokens, FunctionOptions::new(options)))
+}
+
+fn parse_statement(dialect: &dyn Dialect, tokens: Vec<TokenWithSpan>) -> Result<Statement> {
+    Parser::new(dialect)
+        .with_tokens_with_locations(tokens)
+        .parse_statement()
+        .map_err(|e| exec_datafusion_err!("statement can't be parsed: {e}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{extract_options, is_create_function};
+    use datafusion::sql::sqlparser::dialect::GenericDialect;
+    use datafusion::sql::sqlparser::tokenizer::Tokenizer;
+
+    #[test]
+    fn should_extract_options() {
+        let sql = "CREATE OR REPLACE FUNCTION f(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON \
+                   OPTIONS (requirements 'numpy>=1.26; pandas', Other = 'x') AS 'def f(a): return a'";
+        let tokens = Tokenizer::new(&GenericDialect {}, sql)
+            .tokenize_with_location()
+            .unwrap();
+        assert!(is_create_function(&tokens));
+
+        let (tokens, options) = extract_options(tokens).unwrap();
+        assert_eq!(Some("numpy>=1.26; pandas"), options.get("requirements"));
+        assert_eq!(Some("x"), options.get("other"));
+        let statement = tokens.iter().map(|t| t.token.to_string()).collect::<String>();
+        assert!(!statement.contains("OPTIONS"));
+        assert!(statement.contains("LANGUAGE PYTHON"));
+    }
+
+    #[test]
+    fn should_detect_create_function() {
+        let tokens = Tokenizer::new(&GenericDialect {}, "SELECT options FROM t")
+            .tokenize_with_location()
+            .unwrap();
+        assert!(!is_create_function(&tokens));
+    }
+}
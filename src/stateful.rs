@@ -0,0 +1,548 @@
+use crate::archive::sha256_hex;
+use crate::pickle::{PickleSerializer, PySerializer};
+use async_trait::async_trait;
+use ballista_core::serde::protobuf::ShuffleWritePartition;
+use ballista_executor::execution_engine::{DefaultExecutionEngine, ExecutionEngine, QueryStageExecutor};
+use datafusion::common::{exec_err, Result};
+use datafusion::error::DataFusionError;
+use datafusion::execution::TaskContext;
+use datafusion::physical_plan::metrics::MetricsSet;
+use datafusion::physical_plan::ExecutionPlan;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyTuple};
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// default time after which unused [InstanceScope::Process] instances are evicted
+pub const DEFAULT_INSTANCE_TTL: Duration = Duration::from_secs(3600);
+
+/// instances of [InstanceScope::Process] functions
+static INSTANCES: LazyLock<Mutex<ProcessInstances>> = LazyLock::new(|| Mutex::new(ProcessInstances::new()));
+
+tokio::task_local! {
+    /// instances of [InstanceScope::Task] functions, of the executed task
+    static TASK_INSTANCES: Arc<TaskInstances>;
+}
+
+/// How long instance of class based function lives
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InstanceScope {
+    /// instance is shared by all tasks of the process. it is closed
+    /// once it is not used for [set_instance_ttl], or once function
+    /// with the same name but other class or arguments creates its
+    /// instance, and by [close_instances] at shutdown
+    #[default]
+    Process,
+    /// instance is created for each task executed with [task_scope],
+    /// like by [PythonExecutionEngine] at executors, and closed when
+    /// the task completes. Called outside of tasks, instance lives
+    /// with the function, and is closed when the function is dropped
+    Task,
+}
+
+impl FromStr for InstanceScope {
+    type Err = DataFusionError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "process" => Ok(Self::Process),
+            "task" => Ok(Self::Task),
+            _ => exec_err!("unsupported python function instance scope: {s}, supported scopes: process, task"),
+        }
+    }
+}
+
+impl Display for InstanceScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Process => write!(f, "process"),
+            Self::Task => write!(f, "task"),
+        }
+    }
+}
+
+/// Instance of class based python function, created lazily
+/// where the function is called.
+///
+/// Class is called with constructor arguments, then `setup()` of
+/// the instance is called, if defined, so expensive state, like
+/// a model, is loaded once instead of in every call. Instance is
+/// called (`__call__`) like plain functions, and its `close()`
+/// is called, if defined, once it is not needed any more.
+///
+/// Constructor arguments are shipped pickled with `cloudpickle`,
+/// class is shipped like plain functions.
+pub struct PythonInstance {
+    scope: InstanceScope,
+    /// `(args, kwargs)` pickled with `cloudpickle`,
+    /// empty if class is called without arguments
+    arguments: Vec<u8>,
+    /// digest of pickled class and its arguments,
+    /// identifies instance shared by the process or task
+    digest: String,
+    /// instance of [InstanceScope::Task] function called outside of tasks
+    instance: OnceLock<PyObject>,
+}
+
+impl PythonInstance {
+    /// instance of the class created with given constructor arguments
+    pub fn try_new(
+        py: Python<'_>,
+        class: &PyObject,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Self> {
+        let pickle = PickleSerializer::cloudpickle(py)?;
+        let kwargs = kwargs.filter(|k| !k.is_empty());
+        let arguments = match args.is_empty() && kwargs.is_none() {
+            true => vec![],
+            false => pickle.pickle(py, &(args, kwargs).into_pyobject(py)?.into_any().unbind())?,
+        };
+        let digest = sha256_hex(&pickle.pickle(py, &(class, args, kwargs).into_pyobject(py)?.into_any().unbind())?);
+
+        Ok(Self::from_parts(InstanceScope::default(), arguments, digest))
+    }
+
+    /// instance of the class created without arguments
+    pub fn without_arguments(py: Python<'_>, class: &PyObject) -> PyResult<Self> {
+        Self::try_new(py, class, &PyTuple::empty(py), None)
+    }
+
+    /// instance of decoded function
+    pub fn from_parts(scope: InstanceScope, arguments: Vec<u8>, digest: String) -> Self {
+        Self {
+            scope,
+            arguments,
+            digest,
+            instance: OnceLock::new(),
+        }
+    }
+
+    pub fn with_scope(mut self, scope: InstanceScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    pub fn scope(&self) -> InstanceScope {
+        self.scope
+    }
+
+    pub fn arguments(&self) -> &[u8] {
+        &self.arguments
+    }
+
+    pub fn digest(&self) -> &str {
+        &self.digest
+    }
+
+    /// `true` if python function is a class
+    pub fn is_class(py: Python<'_>, func: &PyObject) -> PyResult<bool> {
+        py.import("inspect")?.call_method1("isclass", (func,))?.extract()
+    }
+
+    /// instance of the class, created if it does not exist yet
+    pub(crate) fn get(&self, py: Python<'_>, name: &str, class: &PyObject) -> PyResult<PyObject> {
+        match self.scope {
+            InstanceScope::Task => match TASK_INSTANCES.try_with(Arc::clone) {
+                Ok(task) => task.get(py, self, name, class),
+                Err(_) => self.get_own(py, name, class),
+            },
+            InstanceScope::Process => {
+                let (instance, closable) = INSTANCES.lock().unwrap().get(py, &self.digest);
+                close_all(py, closable);
+                if let Some(instance) = instance {
+                    return Ok(instance);
+                }
+                // lock is not held while instance is created, as setup
+                // may release the GIL, letting other threads lock it
+                let instance = self.create(py, name, class)?;
+                let (instance, closable) = INSTANCES.lock().unwrap().insert(py, name, &self.digest, instance);
+                close_all(py, closable);
+                Ok(instance)
+            }
+        }
+    }
+
+    /// instance owned by the function, of [InstanceScope::Task]
+    /// function called outside of tasks
+    fn get_own(&self, py: Python<'_>, name: &str, class: &PyObject) -> PyResult<PyObject> {
+        if let Some(instance) = self.instance.get() {
+            return Ok(instance.clone_ref(py));
+        }
+        let instance = self.create(py, name, class)?;
+        // setup may release the GIL, so another
+        // thread could have created an instance meanwhile
+        match self.instance.set(instance.clone_ref(py)) {
+            Ok(()) => Ok(instance),
+            Err(_) => {
+                close(py, name, &instance);
+                Ok(self.instance.get().expect("instance to be set").clone_ref(py))
+            }
+        }
+    }
+
+    fn create(&self, py: Python<'_>, name: &str, class: &PyObject) -> PyResult<PyObject> {
+        let started = Instant::now();
+        let instance = match self.arguments.is_empty() {
+            true => class.call0(py)?,
+            false => {
+                let arguments = PickleSerializer::cloudpickle(py)?.unpickle(py, &self.arguments)?;
+                let (args, kwargs): (Bound<'_, PyTuple>, Option<Bound<'_, PyDict>>) = arguments.extract(py)?;
+                class.call(py, args, kwargs.as_ref())?
+            }
+        };
+        if instance.bind(py).hasattr("setup")? {
+            instance.call_method0(py, "setup")?;
+        }
+        log::info!(
+            "python function: {name} instance ({} scope) created in {:?}",
+            self.scope,
+            started.elapsed()
+        );
+
+        Ok(instance)
+    }
+}
+
+impl Drop for PythonInstance {
+    fn drop(&mut self) {
+        if let Some(instance) = self.instance.take() {
+            if is_interpreter_running() {
+                Python::with_gil(|py| close(py, &self.digest, &instance));
+            }
+        }
+    }
+}
+
+impl Debug for PythonInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PythonInstance")
+            .field("scope", &self.scope)
+            .field("arguments", &self.arguments.len())
+            .field("digest", &self.digest)
+            .finish()
+    }
+}
+
+impl PartialEq for PythonInstance {
+    fn eq(&self, other: &Self) -> bool {
+        self.scope == other.scope && self.arguments == other.arguments && self.digest == other.digest
+    }
+}
+
+/// Instances of [InstanceScope::Process] functions, by instance digest.
+///
+/// Evicted instances are closed once they are not referenced
+/// any more, so instances are not closed while they are called.
+struct ProcessInstances {
+    ttl: Duration,
+    instances: HashMap<String, SharedInstance>,
+    /// evicted instances, with function name, to be closed
+    evicted: Vec<(String, PyObject)>,
+}
+
+struct SharedInstance {
+    name: String,
+    instance: PyObject,
+    used: Instant,
+}
+
+impl ProcessInstances {
+    fn new() -> Self {
+        Self {
+            ttl: DEFAULT_INSTANCE_TTL,
+            instances: HashMap::new(),
+            evicted: vec![],
+        }
+    }
+
+    /// instance with given digest, and evicted instances which can be closed
+    fn get(&mut self, py: Python<'_>, digest: &str) -> (Option<PyObject>, Vec<(String, PyObject)>) {
+        self.evict_expired();
+        let instance = self.instances.get_mut(digest).map(|shared| {
+            shared.used = Instant::now();
+            shared.instance.clone_ref(py)
+        });
+        (instance, self.closable(py))
+    }
+
+    /// inserts created instance, unless another thread has inserted
+    /// one meanwhile, which is returned and created one is closed.
+    /// instances of the function with other digest are evicted,
+    /// as they have been superseded by the inserted one
+    fn insert(
+        &mut self,
+        py: Python<'_>,
+        name: &str,
+        digest: &str,
+        instance: PyObject,
+    ) -> (PyObject, Vec<(String, PyObject)>) {
+        if let Some(existing) = self.instances.get(digest) {
+            let existing = existing.instance.clone_ref(py);
+            let mut closable = self.closable(py);
+            closable.push((name.to_string(), instance));
+            return (existing, closable);
+        }
+        let superseded = self
+            .instances
+            .iter()
+            .filter(|(_, shared)| shared.name == name)
+            .map(|(digest, _)| digest.clone())
+            .collect::<Vec<_>>();
+        for digest in superseded {
+            log::debug!("stateful::get - instance of function: {name} has been superseded, evicted");
+            self.evict(&digest);
+        }
+        let shared = SharedInstance {
+            name: name.to_string(),
+            instance: instance.clone_ref(py),
+            used: Instant::now(),
+        };
+        self.instances.insert(digest.to_string(), shared);
+        (instance, self.closable(py))
+    }
+
+    fn evict(&mut self, digest: &str) {
+        if let Some(shared) = self.instances.remove(digest) {
+            self.evicted.push((shared.name, shared.instance));
+        }
+    }
+
+    fn evict_expired(&mut self) -> usize {
+        let expired = self
+            .instances
+            .iter()
+            .filter(|(_, shared)| shared.used.elapsed() > self.ttl)
+            .map(|(digest, _)| digest.clone())
+            .collect::<Vec<_>>();
+        for digest in &expired {
+            log::debug!(
+                "stateful::evict_expired - instance: {digest} not used for {:?}, evicted",
+                self.ttl
+            );
+            self.evict(digest);
+        }
+        expired.len()
+    }
+
+    /// evicted instances which are not referenced any more
+    fn closable(&mut self, py: Python<'_>) -> Vec<(String, PyObject)> {
+        let (closable, referenced) = std::mem::take(&mut self.evicted)
+            .into_iter()
+            .partition(|(_, instance)| instance.get_refcnt(py) <= 1);
+        self.evicted = referenced;
+        closable
+    }
+}
+
+/// Instances of [InstanceScope::Task] functions created by a task,
+/// by instance digest, closed once the task completes
+struct TaskInstances {
+    task_id: String,
+    instances: Mutex<HashMap<String, (String, PyObject)>>,
+}
+
+impl TaskInstances {
+    fn get(&self, py: Python<'_>, instance: &PythonInstance, name: &str, class: &PyObject) -> PyResult<PyObject> {
+        if let Some((_, existing)) = self.instances.lock().unwrap().get(instance.digest()) {
+            return Ok(existing.clone_ref(py));
+        }
+        let created = instance.create(py, name, class)?;
+        // setup may release the GIL, so another thread
+        // of the task could have created an instance meanwhile
+        let existing = self
+            .instances
+            .lock()
+            .unwrap()
+            .get(instance.digest())
+            .map(|(_, existing)| existing.clone_ref(py));
+        match existing {
+            Some(existing) => {
+                close(py, name, &created);
+                Ok(existing)
+            }
+            None => {
+                log::debug!("stateful::get - task: {} created instance of: {name}", self.task_id);
+                self.instances
+                    .lock()
+                    .unwrap()
+                    .insert(instance.digest().to_string(), (name.to_string(), created.clone_ref(py)));
+                Ok(created)
+            }
+        }
+    }
+}
+
+impl Drop for TaskInstances {
+    fn drop(&mut self) {
+        let instances = std::mem::take(&mut *self.instances.lock().unwrap());
+        if !instances.is_empty() && is_interpreter_running() {
+            Python::with_gil(|py| close_all(py, instances.into_values().collect()));
+        }
+    }
+}
+
+/// Executes task, with [TaskContext] of the task, so [InstanceScope::Task]
+/// functions called by the task share instances, which are closed once
+/// the task completes, or is cancelled. Used by [PythonExecutionEngine]
+/// for each task of the executor.
+pub async fn task_scope<F: Future>(context: &TaskContext, task: F) -> F::Output {
+    let instances = Arc::new(TaskInstances {
+        task_id: context.task_id().unwrap_or_default(),
+        instances: Mutex::new(HashMap::new()),
+    });
+    let result = TASK_INSTANCES.scope(instances, task).await;
+    evict_instances();
+    result
+}
+
+/// wraps function, run by a blocking task of the executed task,
+/// so it is called in [task_scope] of the executed task as well
+pub(crate) fn in_current_task<F, R>(function: F) -> impl FnOnce() -> R
+where
+    F: FnOnce() -> R,
+{
+    let task = TASK_INSTANCES.try_with(Arc::clone).ok();
+    move || match task {
+        Some(task) => TASK_INSTANCES.sync_scope(task, function),
+        None => function(),
+    }
+}
+
+/// Ballista execution engine, executing tasks of the wrapped engine in
+/// [task_scope], to be used as executor's `override_execution_engine`,
+/// see [crate::env::PythonEnvConfig::configure_executor]
+pub struct PythonExecutionEngine {
+    inner: Arc<dyn ExecutionEngine>,
+}
+
+impl PythonExecutionEngine {
+    pub fn new(inner: Arc<dyn ExecutionEngine>) -> Self {
+        Self { inner }
+    }
+}
+
+impl Default for PythonExecutionEngine {
+    fn default() -> Self {
+        Self::new(Arc::new(DefaultExecutionEngine {}))
+    }
+}
+
+impl ExecutionEngine for PythonExecutionEngine {
+    fn create_query_stage_exec(
+        &self,
+        job_id: String,
+        stage_id: usize,
+        plan: Arc<dyn ExecutionPlan>,
+        work_dir: &str,
+    ) -> Result<Arc<dyn QueryStageExecutor>> {
+        let inner = self.inner.create_query_stage_exec(job_id, stage_id, plan, work_dir)?;
+        Ok(Arc::new(PythonQueryStageExec { inner }))
+    }
+}
+
+#[derive(Debug)]
+struct PythonQueryStageExec {
+    inner: Arc<dyn QueryStageExecutor>,
+}
+
+impl Display for PythonQueryStageExec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.inner, f)
+    }
+}
+
+#[async_trait]
+impl QueryStageExecutor for PythonQueryStageExec {
+    async fn execute_query_stage(
+        &self,
+        input_partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<Vec<ShuffleWritePartition>> {
+        let task = self.inner.execute_query_stage(input_partition, context.clone());
+        task_scope(&context, task).await
+    }
+
+    fn collect_plan_metrics(&self) -> Vec<MetricsSet> {
+        self.inner.collect_plan_metrics()
+    }
+}
+
+/// `false` once interpreter is finalized, instances
+/// can't be closed, nor GIL acquired, any more
+fn is_interpreter_running() -> bool {
+    unsafe { pyo3::ffi::Py_IsInitialized() != 0 }
+}
+
+/// calls `close()` of the instance, if defined,
+/// failures are logged as there is nobody to report them to
+fn close(py: Python<'_>, name: &str, instance: &PyObject) {
+    let result = match instance.bind(py).hasattr("close") {
+        Ok(true) => instance.call_method0(py, "close").map(|_| ()),
+        Ok(false) => Ok(()),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => log::debug!("stateful::close - instance: {name} closed"),
+        Err(e) => log::warn!("stateful::close - instance: {name} failed to close: {e}"),
+    }
+}
+
+fn close_all(py: Python<'_>, instances: Vec<(String, PyObject)>) {
+    for (name, instance) in instances {
+        close(py, &name, &instance);
+    }
+}
+
+/// sets time after which unused [InstanceScope::Process] instances
+/// are evicted, defaults to [DEFAULT_INSTANCE_TTL]
+pub fn set_instance_ttl(ttl: Duration) {
+    INSTANCES.lock().unwrap().ttl = ttl;
+}
+
+/// evicts [InstanceScope::Process] instances not used for instance ttl,
+/// and closes evicted instances which are not referenced any more.
+/// called once a task of [task_scope] completes, and when instances
+/// are accessed. returns number of evicted instances
+pub fn evict_instances() -> usize {
+    let mut instances = INSTANCES.lock().unwrap();
+    let count = instances.evict_expired();
+    if instances.evicted.is_empty() || !is_interpreter_running() {
+        return count;
+    }
+    drop(instances);
+    Python::with_gil(|py| {
+        let closable = INSTANCES.lock().unwrap().closable(py);
+        close_all(py, closable);
+    });
+
+    count
+}
+
+/// closes instances shared by the process, evicted ones included,
+/// should be called at process shutdown. returns number of closed instances
+pub fn close_instances() -> usize {
+    let (instances, evicted) = {
+        let mut instances = INSTANCES.lock().unwrap();
+        (
+            std::mem::take(&mut instances.instances),
+            std::mem::take(&mut instances.evicted),
+        )
+    };
+    let instances = instances
+        .into_values()
+        .map(|shared| (shared.name, shared.instance))
+        .chain(evicted)
+        .collect::<Vec<_>>();
+    let count = instances.len();
+    if count > 0 && is_interpreter_running() {
+        Python::with_gil(|py| close_all(py, instances));
+    }
+    log::debug!("stateful::close_instances - {count} instances closed");
+
+    count
+}
@@ -1,16 +1,134 @@
-use datafusion::arrow::array::{make_array, Array, ArrayData};
+use crate::archive::{default_cache_dir, sha256_hex, PythonEnvArchive};
+use crate::artifacts::PythonArtifactStore;
+use crate::bundle::PythonBundle;
+use crate::capsule::{to_array, PyArrowArray};
+use crate::coroutine::{call_all, from_py_values, is_coroutine_function, to_py_values, AsyncOptions};
+use crate::pickle::{PickleSerializer, PySerializer};
+use crate::registry::FunctionReference;
+use crate::requirements::PythonRequirement;
+use crate::stateful::PythonInstance;
+use crate::worker::WorkerFunction;
+use datafusion::arrow::array::{new_empty_array, new_null_array, Array, ArrayRef, BooleanArray, UInt32Array};
+use datafusion::arrow::compute::kernels::boolean::and;
+use datafusion::arrow::compute::{concat, filter, is_not_null, take};
 use datafusion::arrow::datatypes::DataType;
-use datafusion::arrow::pyarrow::{FromPyArrow, ToPyArrow};
-use datafusion::common::Result;
+use datafusion::arrow::pyarrow::ToPyArrow;
+use datafusion::common::{exec_err, Result};
 use datafusion::error::DataFusionError;
 use datafusion::logical_expr::Signature;
-use datafusion::logical_expr::{ColumnarValue, ScalarUDFImpl, Volatility};
-use pyo3::ffi::c_str;
+use datafusion::logical_expr::{ColumnarValue, DocSection, Documentation, ScalarUDFImpl, Volatility};
+use pyo3::exceptions::PyValueError;
 use pyo3::types::{PyAnyMethods, PyModule, PyTuple};
 use pyo3::{Py, PyAny, PyObject, PyResult, Python};
 use std::any::Any;
 use std::ffi::CString;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// number of source characters described in `information_schema.routines`
+const DESCRIBED_SOURCE_LENGTH: usize = 500;
+/// prefix of modules functions are compiled in from source
+pub(crate) const SOURCE_MODULE_PREFIX: &str = "ballista_udf_";
+
+/// Python source code function has been compiled from.
+///
+/// Source is shipped instead of pickled function when available,
+/// and compiled again at the executor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PythonSource {
+    /// module source code
+    pub code: String,
+    /// name of the function in the module
+    pub entry_point: String,
+}
+
+impl PythonSource {
+    pub fn new(code: impl Into<String>, entry_point: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            entry_point: entry_point.into(),
+        }
+    }
+
+    /// name of the module source code of `function` is compiled as,
+    /// unique per function name, entry point and code, so globals of
+    /// functions are isolated, even if they share the same source
+    pub fn module_name(&self, function: &str) -> String {
+        let name = function
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        let content = [function, self.entry_point.as_str(), self.code.as_str()].join("\0");
+        let digest = sha256_hex(content.as_bytes());
+
+        format!("{SOURCE_MODULE_PREFIX}{name}_{}", &digest[..16])
+    }
+
+    /// compiles module source code of `function` and returns entry point function
+    ///
+    /// Module is registered to be pickled by value by `cloudpickle`,
+    /// as it can't be imported where pickled function is loaded.
+    /// Other serializers pickle functions by reference, so codecs
+    /// refuse to pickle compiled functions with them.
+    ///
+    /// Please do read warnings at [PyModule::from_code] to understand
+    /// why this function is dangerous.
+    pub fn compile(&self, py: Python<'_>, function: &str) -> PyResult<Py<PyAny>> {
+        let module_name = self.module_name(function);
+        let code = CString::new(self.code.as_str())?;
+        let file_name = CString::new(format!("{module_name}.py"))?;
+        let udf_module = PyModule::from_code(py, &code, &file_name, &CString::new(module_name.as_str())?)?;
+        match py.import("cloudpickle") {
+            Ok(cloudpickle) => {
+                cloudpickle.call_method1("register_pickle_by_value", (&udf_module,))?;
+            }
+            Err(e) => {
+                log::debug!("python_source::compile - module: {module_name} not registered with cloudpickle: {e}")
+            }
+        }
+
+        Ok(udf_module.getattr(self.entry_point.as_str())?.unbind())
+    }
+}
+
+/// How arguments are passed to python function.
+///
+/// Function can return any arrow compatible value, see [crate::capsule::to_array].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CallMode {
+    /// arguments are `pyarrow` arrays
+    #[default]
+    Arrow,
+    /// arguments are `pandas` series
+    Pandas,
+    /// arguments implement arrow PyCapsule interface
+    /// ([crate::capsule::PyArrowArray]), `pyarrow` is not required
+    Capsule,
+}
+
+impl FromStr for CallMode {
+    type Err = DataFusionError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "arrow" => Ok(Self::Arrow),
+            "pandas" => Ok(Self::Pandas),
+            "capsule" => Ok(Self::Capsule),
+            _ => exec_err!("unsupported python function mode: {s}, supported modes: arrow, pandas, capsule"),
+        }
+    }
+}
+
+impl Display for CallMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Arrow => write!(f, "arrow"),
+            Self::Pandas => write!(f, "pandas"),
+            Self::Capsule => write!(f, "capsule"),
+        }
+    }
+}
 
 /// Implements [`ScalarUDFImpl`] for functions that have a single signature and
 /// return type.
@@ -20,6 +138,38 @@ pub struct PythonUDF {
     pub input_types: Vec<DataType>,
     pub return_type: DataType,
     pub func: PyObject,
+    /// source code of the function, if known
+    pub source: Option<PythonSource>,
+    /// reference to the function pre-installed at executors,
+    /// function is shipped by reference if set
+    pub reference: Option<FunctionReference>,
+    /// python environment shipped with the function
+    pub environment: Option<PythonEnvArchive>,
+    /// bundle of python modules function has been loaded from
+    pub bundle: Option<PythonBundle>,
+    /// worker process calling the function, for function
+    /// shipped with python environment
+    pub worker: Option<WorkerFunction>,
+    /// python packages required by the function,
+    /// checked at executors before function is loaded
+    pub requirements: Vec<PythonRequirement>,
+    /// function is not called for rows with null argument,
+    /// result of these rows is null
+    pub strict: bool,
+    pub mode: CallMode,
+    /// names of function arguments, empty for arguments without name
+    pub argument_names: Vec<String>,
+    /// how `async def` function is called
+    pub async_options: AsyncOptions,
+    /// class based function, `func` is the class
+    /// called with arguments of the instance
+    pub instance: Option<PythonInstance>,
+    /// sha256 digest of encoded function, set by codec which decoded it
+    pub digest: Option<String>,
+    /// checked when function is called first
+    coroutine: OnceLock<bool>,
+    /// built when first requested, after the function is configured
+    documentation: OnceLock<Documentation>,
 }
 
 impl Debug for PythonUDF {
@@ -30,6 +180,18 @@ impl Debug for PythonUDF {
             .field("input_types", &self.input_types)
             .field("return_type", &self.return_type)
             .field("func", &"<FUNC>")
+            .field("source", &self.source.as_ref().map(|s| &s.entry_point))
+            .field("reference", &self.reference)
+            .field("environment", &self.environment.as_ref().map(|e| &e.digest))
+            .field("bundle", &self.bundle.as_ref().map(|b| (&b.digest, &b.entry_point)))
+            .field("worker", &self.worker.as_ref().map(|w| w.environment().digest.clone()))
+            .field("requirements", &self.requirements)
+            .field("strict", &self.strict)
+            .field("mode", &self.mode)
+            .field("argument_names", &self.argument_names)
+            .field("async_options", &self.async_options)
+            .field("instance", &self.instance)
+            .field("digest", &self.digest)
             .finish()
     }
 }
@@ -70,8 +232,110 @@ impl PythonUDF {
             input_types,
             return_type,
             func,
+            source: None,
+            reference: None,
+            environment: None,
+            bundle: None,
+            worker: None,
+            requirements: vec![],
+            strict: false,
+            mode: CallMode::default(),
+            argument_names: vec![],
+            async_options: AsyncOptions::default(),
+            instance: None,
+            digest: None,
+            coroutine: OnceLock::new(),
+            documentation: OnceLock::new(),
         }
     }
+
+    /// Sets function volatility, immutable functions
+    /// with constant arguments are evaluated at planning
+    pub fn with_volatility(mut self, volatility: Volatility) -> Self {
+        self.signature = Signature::exact(self.input_types.clone(), volatility);
+        self
+    }
+
+    /// Function is not called for rows with null argument (`STRICT`)
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// How function is called if it is `async def` function
+    pub fn with_async_options(mut self, async_options: AsyncOptions) -> Self {
+        self.async_options = async_options;
+        self
+    }
+
+    /// Function is a class, its instance is called, see [PythonInstance]
+    pub fn with_instance(mut self, instance: PythonInstance) -> Self {
+        self.instance = Some(instance);
+        self
+    }
+
+    pub fn with_mode(mut self, mode: CallMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Names of function arguments, shown in `information_schema.parameters`
+    pub fn with_argument_names(mut self, argument_names: Vec<String>) -> Self {
+        self.argument_names = argument_names;
+        self
+    }
+
+    /// Declares python packages required by the function
+    pub fn with_requirements(mut self, requirements: Vec<PythonRequirement>) -> Self {
+        self.requirements = requirements;
+        self
+    }
+
+    /// Create a new `PythonUDF` referencing function pre-installed at executors.
+    ///
+    /// Function is not pickled, it is shipped by reference and resolved
+    /// against [crate::registry::PyFunctionRegistry] at the executor.
+    /// Referenced module has to be importable at the client as well.
+    pub fn from_reference(
+        name: impl Into<String>,
+        reference: FunctionReference,
+        input_types: Vec<DataType>,
+        return_type: DataType,
+        volatility: Volatility,
+    ) -> Result<Self> {
+        let func = Python::with_gil(|py| reference.import(py))
+            .map_err(|e| DataFusionError::Execution(format!("python function: {reference} can't be imported: {e}")))?;
+
+        let mut function = Self::new(name, input_types, return_type, volatility, func);
+        function.reference = Some(reference);
+
+        Ok(function)
+    }
+
+    /// Create a new `PythonUDF` loaded from entry point of the bundle,
+    /// bundle is unpacked into [crate::archive::default_cache_dir].
+    /// Bundle is published to [PythonArtifactStore] when the function
+    /// is encoded, and shipped with the function by digest.
+    pub fn from_bundle(
+        name: impl Into<String>,
+        bundle: PythonBundle,
+        input_types: Vec<DataType>,
+        return_type: DataType,
+        volatility: Volatility,
+    ) -> Result<Self> {
+        let func = Python::with_gil(|py| bundle.load(py, &PythonArtifactStore::from_env(), &default_cache_dir()))?;
+        let mut function = Self::new(name, input_types, return_type, volatility, func);
+        function.bundle = Some(bundle);
+
+        Ok(function)
+    }
+
+    /// Attaches source code function has been compiled from
+    pub fn with_source(mut self, source: PythonSource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
     /// Function used for testing ONLY
     ///
     /// Please do read warnings at [PyModule::from_code_bound] to understand
@@ -87,29 +351,302 @@ impl PythonUDF {
         input_types: Vec<DataType>,
         result_type: DataType,
     ) -> Result<Self> {
-        // TODO: we can add proper signature
-        let code = CString::new(code).map_err(|e| DataFusionError::Execution(e.to_string()))?;
-        let py_function: PyResult<Py<PyAny>> = Python::with_gil(|py| {
-            // we need to
-            let udf_module =
-                // TODO: we need better mutly file handling and module name
-                PyModule::from_code(py, &code, c_str!("main.py"), c_str!("__main__"))?;
-
-            // At the moment we assume that function will be named udf, that could be changed
-            Ok(udf_module.getattr(name)?.unbind())
-        });
-        let function = PythonUDF::new(
-            name,
-            input_types,
-            result_type,
-            Volatility::Volatile,
-            py_function.expect("function to compile "),
+        Self::from_code_with_entry_point(name, code, name, input_types, result_type)
+    }
+
+    /// Creates function named `name` from `entry_point` function of the code
+    pub fn from_code_with_entry_point(
+        name: &str,
+        code: &str,
+        entry_point: &str,
+        input_types: Vec<DataType>,
+        result_type: DataType,
+    ) -> Result<Self> {
+        let source = PythonSource::new(code, entry_point);
+        let py_function = Python::with_gil(|py| source.compile(py, name))
+            .map_err(|e| DataFusionError::Execution(format!("function {name} failed to compile: {e}")))?;
+
+        let function = PythonUDF::new(name, input_types, result_type, Volatility::Volatile, py_function);
+
+        Ok(function.with_source(source))
+    }
+
+    /// kind and content function is built from: `source` code for functions
+    /// with known source, `reference` for referenced functions, `bundle`
+    /// for functions loaded from bundle, `encoded` function for functions
+    /// called in worker process, or `pickle` of the function (using
+    /// `cloudpickle`) otherwise. Codec ships it within encoded function,
+    /// along with function's types and options
+    pub fn content(&self) -> Result<(&'static str, Vec<u8>)> {
+        if let Some(reference) = &self.reference {
+            return Ok(("reference", reference.to_string().into_bytes()));
+        }
+        if let Some(worker) = &self.worker {
+            return Ok(("encoded", worker.payload().to_vec()));
+        }
+        if let Some(bundle) = &self.bundle {
+            return match &bundle.content {
+                Some(content) => Ok(("bundle", content.to_vec())),
+                None => exec_err!("bundle content is kept in artifact store, digest: {}", bundle.digest),
+            };
+        }
+        if let Some(source) = &self.source {
+            return Ok(("source", source.code.clone().into_bytes()));
+        }
+        Python::with_gil(|py| PickleSerializer::cloudpickle(py)?.pickle(py, &self.func))
+            .map(|pickle| ("pickle", pickle))
+            .map_err(|e| DataFusionError::Execution(format!("function {} can't be pickled: {e}", self.name)))
+    }
+
+    /// kind, digest and size of the content function is built from, if
+    /// they are known without encoding the function. Functions are not
+    /// pickled just to be described, see [PythonUDF::content]
+    fn described_content(&self) -> String {
+        let described =
+            |kind: &str, content: &[u8]| format!("{kind} sha256 {} ({} bytes)", sha256_hex(content), content.len());
+        if let Some(reference) = &self.reference {
+            return described("reference", reference.to_string().as_bytes());
+        }
+        if let Some(worker) = &self.worker {
+            return described("encoded", worker.payload());
+        }
+        if let Some(bundle) = &self.bundle {
+            return match &bundle.content {
+                Some(content) => format!("bundle sha256 {} ({} bytes)", bundle.digest, content.len()),
+                None => format!("bundle sha256 {}", bundle.digest),
+            };
+        }
+        if let Some(source) = &self.source {
+            return described("source", source.code.as_bytes());
+        }
+        "pickle, digest is known once the function is encoded".to_string()
+    }
+
+    /// describes function in `SHOW FUNCTIONS` and `information_schema.routines`
+    fn build_documentation(&self) -> Documentation {
+        let volatility = format!("{:?}", self.signature.volatility).to_lowercase();
+        let null_input = match self.strict {
+            true => "strict",
+            false => "called on null input",
+        };
+        let content = self.described_content();
+        let mut description = format!(
+            "python function, language: python, mode: {}, volatility: {volatility}, null input: {null_input}, content: {content}",
+            self.mode
         );
+        if let Some(reference) = &self.reference {
+            description.push_str(&format!(", reference: {reference}"));
+        }
+        if let Some(bundle) = &self.bundle {
+            description.push_str(&format!(
+                ", bundle: sha256 {}, bundle entry point: {}",
+                bundle.digest, bundle.entry_point
+            ));
+        }
+        if let Some(source) = &self.source {
+            // full source is returned by `SHOW CREATE FUNCTION`
+            let code = match source.code.char_indices().nth(DESCRIBED_SOURCE_LENGTH) {
+                Some((end, _)) => format!("{}\n... (truncated, see SHOW CREATE FUNCTION)", &source.code[..end]),
+                None => source.code.clone(),
+            };
+            description.push_str(&format!(", entry point: {}\n{code}", source.entry_point));
+        }
 
-        Ok(function)
+        let arguments = self
+            .input_types
+            .iter()
+            .enumerate()
+            .map(|(position, data_type)| {
+                let name = match self.argument_names.get(position) {
+                    Some(name) if !name.is_empty() => name.clone(),
+                    _ => format!("arg{}", position + 1),
+                };
+                (name, data_type.to_string())
+            })
+            .collect::<Vec<_>>();
+        let syntax_example = format!(
+            "{}({})",
+            self.name,
+            arguments.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>().join(", ")
+        );
+        let section = DocSection {
+            include: true,
+            label: "Python Functions",
+            description: None,
+        };
+
+        Documentation {
+            doc_section: section,
+            description,
+            syntax_example,
+            sql_example: None,
+            arguments: Some(arguments),
+            alternative_syntax: None,
+            related_udfs: None,
+        }
+    }
+
+    /// python callable invoked by the function, the function itself,
+    /// or `__call__` of class instance, created if it does not exist yet
+    pub fn callable(&self, py: Python<'_>) -> PyResult<PyObject> {
+        match &self.instance {
+            Some(instance) => instance.get(py, &self.name, &self.func)?.getattr(py, "__call__"),
+            None => Ok(self.func.clone_ref(py)),
+        }
+    }
+
+    /// calls python function with given arguments, in worker
+    /// process of its environment, if function has one
+    pub(crate) fn invoke(&self, arrays: &[ArrayRef], number_rows: usize) -> Result<ArrayRef> {
+        let result = match &self.worker {
+            Some(worker) => worker.call(&self.name, arrays, number_rows)?,
+            None => Python::with_gil(|py| self.call(py, arrays, number_rows))?,
+        };
+        if result.len() != number_rows {
+            return exec_err!(
+                "python function: {} returned {} values, expected one value per row: {number_rows}",
+                self.name,
+                result.len()
+            );
+        }
+
+        Ok(result)
+    }
+
+    /// calls python function with given arguments
+    fn call(&self, py: Python<'_>, arrays: &[ArrayRef], number_rows: usize) -> Result<ArrayRef> {
+        let func = self
+            .callable(py)
+            .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+        let is_coroutine = match self.coroutine.get() {
+            Some(is_coroutine) => *is_coroutine,
+            None => {
+                let is_coroutine =
+                    is_coroutine_function(py, &func).map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+                *self.coroutine.get_or_init(|| is_coroutine)
+            }
+        };
+        if is_coroutine {
+            return self
+                .call_async(py, &func, arrays, number_rows)
+                .map_err(|e| DataFusionError::Execution(format!("{e:?}")));
+        }
+
+        // 1. cast args to PyArrow arrays
+        let py_args = self
+            .arguments(py, arrays)
+            .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+        let py_args = PyTuple::new(py, py_args).map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+
+        // 2. call function
+        let value = func
+            .call(py, py_args, None)
+            .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
+
+        // 3. cast to arrow::array::Array
+        to_array(py, value.bind(py), &self.return_type).map_err(|e| DataFusionError::Execution(format!("{e:?}")))
+    }
+
+    /// arrays converted according to the function mode
+    fn arguments(&self, py: Python<'_>, arrays: &[ArrayRef]) -> PyResult<Vec<PyObject>> {
+        arrays
+            .iter()
+            .map(|arg| match self.mode {
+                CallMode::Arrow => arg.into_data().to_pyarrow(py),
+                CallMode::Pandas => arg.into_data().to_pyarrow(py)?.call_method0(py, "to_pandas"),
+                CallMode::Capsule => Ok(Py::new(py, PyArrowArray::new(arg.into_data()))?.into_any()),
+            })
+            .collect()
+    }
+
+    /// calls `async def` function once per row, or once per chunk
+    /// of rows, awaiting calls concurrently
+    /// key of semaphore limiting concurrent calls: digest of decoded
+    /// function, function name and concurrency of other functions
+    fn semaphore_key(&self) -> String {
+        match &self.digest {
+            Some(digest) => digest.clone(),
+            None => format!("{}/{}", self.name, self.async_options.concurrency),
+        }
+    }
+
+    fn call_async(
+        &self,
+        py: Python<'_>,
+        func: &PyObject,
+        arrays: &[ArrayRef],
+        number_rows: usize,
+    ) -> PyResult<ArrayRef> {
+        match self.async_options.chunk_size {
+            None => {
+                let columns = arrays
+                    .iter()
+                    .map(|a| to_py_values(py, a))
+                    .collect::<PyResult<Vec<_>>>()?;
+                let calls = (0..number_rows)
+                    .map(|row| PyTuple::new(py, columns.iter().map(|c| c[row].clone_ref(py))))
+                    .collect::<PyResult<Vec<_>>>()?;
+                let results = call_all(py, &self.name, &self.semaphore_key(), func, calls, &self.async_options)?;
+
+                from_py_values(py, results, &self.return_type)
+            }
+            Some(chunk_size) => {
+                let calls = (0..number_rows)
+                    .step_by(chunk_size)
+                    .map(|offset| {
+                        let length = chunk_size.min(number_rows - offset);
+                        let chunk = arrays.iter().map(|a| a.slice(offset, length)).collect::<Vec<_>>();
+                        PyTuple::new(py, self.arguments(py, &chunk)?)
+                    })
+                    .collect::<PyResult<Vec<_>>>()?;
+                let results = call_all(py, &self.name, &self.semaphore_key(), func, calls, &self.async_options)?;
+                let results = results
+                    .iter()
+                    .map(|r| to_array(py, r.bind(py), &self.return_type))
+                    .collect::<PyResult<Vec<_>>>()?;
+
+                match results.is_empty() {
+                    true => Ok(new_empty_array(&self.return_type)),
+                    false => concat(&results.iter().map(|r| r.as_ref()).collect::<Vec<_>>())
+                        .map_err(|e| PyValueError::new_err(e.to_string())),
+                }
+            }
+        }
     }
 }
 
+/// rows where none of arguments is null,
+/// `None` if no argument has nulls
+fn valid_rows(arrays: &[ArrayRef]) -> Result<Option<BooleanArray>> {
+    let mut valid: Option<BooleanArray> = None;
+    for array in arrays.iter().filter(|a| a.null_count() > 0) {
+        let not_null = is_not_null(array)?;
+        valid = Some(match valid {
+            Some(valid) => and(&valid, &not_null)?,
+            None => not_null,
+        });
+    }
+
+    Ok(valid)
+}
+
+/// spreads results of valid rows back to all rows,
+/// result of other rows is null
+fn scatter(valid: &BooleanArray, result: &ArrayRef) -> Result<ArrayRef> {
+    let mut position = 0;
+    let indices = valid
+        .iter()
+        .map(|v| {
+            v.unwrap_or_default().then(|| {
+                position += 1;
+                position - 1
+            })
+        })
+        .collect::<UInt32Array>();
+
+    Ok(take(result, &indices, None)?)
+}
+
 impl ScalarUDFImpl for PythonUDF {
     fn as_any(&self) -> &dyn Any {
         self
@@ -127,30 +664,62 @@ impl ScalarUDFImpl for PythonUDF {
         Ok(self.return_type.clone())
     }
 
+    fn documentation(&self) -> Option<&Documentation> {
+        Some(self.documentation.get_or_init(|| self.build_documentation()))
+    }
+
     fn invoke_with_args(&self, args: datafusion::logical_expr::ScalarFunctionArgs) -> Result<ColumnarValue> {
-        let array_refs = ColumnarValue::values_to_arrays(&args.args)?;
-        let array_data: Result<_> = Python::with_gil(|py| {
-            // 1. cast args to PyArrow arrays
-            let py_args = array_refs
-                .iter()
-                .map(|arg| {
-                    arg.into_data()
-                        .to_pyarrow(py)
-                        .map_err(|e| DataFusionError::Execution(format!("{e:?}")))
-                })
-                .collect::<Result<Vec<_>, _>>()?;
-            let py_args = PyTuple::new(py, py_args).map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
-
-            // 2. call function
-            let value = self
-                .func
-                .call(py, py_args, None)
-                .map_err(|e| DataFusionError::Execution(format!("{e:?}")))?;
-
-            // 3. cast to arrow::array::Array
-            ArrayData::from_pyarrow_bound(value.bind(py)).map_err(|e| DataFusionError::Execution(format!("{e:?}")))
-        });
+        // scalar arguments are expanded to all rows, function returns value per row
+        let arrays = args
+            .args
+            .iter()
+            .map(|a| a.to_array(args.number_rows))
+            .collect::<Result<Vec<_>>>()?;
+        // strict function is called only for rows without null arguments
+        let valid = match self.strict {
+            true => valid_rows(&arrays)?,
+            false => None,
+        };
+
+        let result = match &valid {
+            None => self.invoke(&arrays, args.number_rows)?,
+            Some(valid) if valid.true_count() == 0 => new_null_array(&self.return_type, args.number_rows),
+            Some(valid) => {
+                let arrays = arrays
+                    .iter()
+                    .map(|a| filter(a, valid))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                let result = self.invoke(&arrays, valid.true_count())?;
+                scatter(valid, &result)?
+            }
+        };
+
+        Ok(result.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{scatter, valid_rows};
+    use datafusion::arrow::array::{Array, ArrayRef, Float64Array, Int64Array};
+    use datafusion::arrow::compute::filter;
+    use std::sync::Arc;
+
+    #[test]
+    fn should_skip_rows_with_null_arguments() {
+        let a: ArrayRef = Arc::new(Int64Array::from(vec![Some(1), None, Some(3), Some(4)]));
+        let b: ArrayRef = Arc::new(Int64Array::from(vec![Some(10), Some(20), None, Some(40)]));
+        let c: ArrayRef = Arc::new(Int64Array::from(vec![1, 2, 3, 4]));
+
+        assert!(valid_rows(std::slice::from_ref(&c)).unwrap().is_none());
+
+        let valid = valid_rows(&[a.clone(), b, c]).unwrap().unwrap();
+        assert_eq!(2, valid.true_count());
+        assert_eq!(2, filter(&a, &valid).unwrap().len());
 
-        Ok(make_array(array_data?).into())
+        let result: ArrayRef = Arc::new(Float64Array::from(vec![0.5, 2.0]));
+        let result = scatter(&valid, &result).unwrap();
+        let expected: ArrayRef = Arc::new(Float64Array::from(vec![Some(0.5), None, None, Some(2.0)]));
+        assert_eq!(&expected, &result);
     }
 }
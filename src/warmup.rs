@@ -0,0 +1,99 @@
+use crate::registry::FunctionReference;
+use datafusion::common::{exec_err, Result};
+use pyo3::Python;
+use std::str::FromStr;
+use std::time::Instant;
+
+/// environment variable listing (comma separated) modules to pre-import
+pub static ENV_PRELOAD_MODULES: &str = "BALLISTA_PYTHON_PRELOAD_MODULES";
+/// environment variable listing (comma separated) warm-up callables,
+/// in `module:function@version` format, version being optional
+pub static ENV_WARM_UP: &str = "BALLISTA_PYTHON_WARM_UP";
+
+/// Modules imported and warm-up callables run at process start,
+/// so the first task using heavy libraries does not pay their
+/// import time inside a function call.
+///
+/// Should be run before executor registers with the scheduler,
+/// so misconfigured executors fail early.
+#[derive(Debug, Clone, Default)]
+pub struct PythonWarmUp {
+    modules: Vec<String>,
+    callables: Vec<FunctionReference>,
+}
+
+impl PythonWarmUp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// creates warm-up from [ENV_PRELOAD_MODULES] and [ENV_WARM_UP]
+    /// environment variables
+    pub fn from_env() -> Result<Self> {
+        let mut warm_up = Self::new();
+        for module in env_list(ENV_PRELOAD_MODULES) {
+            warm_up = warm_up.with_module(module);
+        }
+        for callable in env_list(ENV_WARM_UP) {
+            warm_up = warm_up.with_callable(FunctionReference::from_str(&callable)?);
+        }
+
+        Ok(warm_up)
+    }
+
+    /// module to be imported
+    pub fn with_module(mut self, module: impl Into<String>) -> Self {
+        self.modules.push(module.into());
+        self
+    }
+
+    /// callable, with no arguments, to be called after modules are imported,
+    /// fails if referenced version is not installed
+    pub fn with_callable(mut self, callable: FunctionReference) -> Self {
+        self.callables.push(callable);
+        self
+    }
+
+    /// imports modules and runs warm-up callables,
+    /// returns error listing all failures
+    pub fn run(&self) -> Result<()> {
+        log::debug!("warming up python ...");
+        let failures = Python::with_gil(|py| {
+            let mut failures = vec![];
+            for module in &self.modules {
+                let start = Instant::now();
+                match py.import(module.as_str()) {
+                    Ok(_) => log::info!("python module: {module} imported in {:?}", start.elapsed()),
+                    Err(e) => failures.push(format!("module {module} failed to import: {e}")),
+                }
+            }
+            for callable in &self.callables {
+                let start = Instant::now();
+                let result = callable
+                    .check_version(py)
+                    .map_err(|e| e.to_string())
+                    .and_then(|_| callable.import(py).and_then(|f| f.call0(py)).map_err(|e| e.to_string()));
+                match result {
+                    Ok(_) => log::info!("python warm-up: {callable} finished in {:?}", start.elapsed()),
+                    Err(e) => failures.push(format!("warm-up {callable} failed: {e}")),
+                }
+            }
+            failures
+        });
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            exec_err!("python warm-up failed: {}", failures.join(", "))
+        }
+    }
+}
+
+fn env_list(key: &str) -> Vec<String> {
+    std::env::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
@@ -0,0 +1,448 @@
+use crate::archive::{sha256_hex, PythonEnvArchive, ENV_ARCHIVE_CACHE};
+use crate::artifacts::{PythonArtifactStore, ENV_ARTIFACT_STORE};
+use crate::codec::serde::SignedUdfProto;
+use crate::codec::PyPhysicalCodec;
+use crate::env::{PythonEnvConfig, ENV_EXTRA_PATH, ENV_VENV};
+use crate::udf::PythonUDF;
+use datafusion::arrow::array::{ArrayRef, RecordBatch, RecordBatchOptions};
+use datafusion::arrow::datatypes::{Field, Schema};
+use datafusion::arrow::ipc::reader::StreamReader;
+use datafusion::arrow::ipc::writer::StreamWriter;
+use datafusion::common::{exec_datafusion_err, exec_err, Result};
+use datafusion::error::DataFusionError;
+use datafusion::logical_expr::ScalarUDF;
+use datafusion_proto::physical_plan::PhysicalExtensionCodec;
+use prost::Message;
+use pyo3::types::PyAnyMethods;
+use pyo3::{PyResult, Python};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// environment variable with command started as python worker
+/// process, like `ballista-python-worker`, see [run_if_worker]
+pub static ENV_WORKER_COMMAND: &str = "BALLISTA_PYTHON_WORKER_COMMAND";
+/// environment variable with module path of the environment,
+/// set for worker processes only
+static ENV_WORKER_MODULE_PATH: &str = "BALLISTA_PYTHON_WORKER_MODULE_PATH";
+
+/// environment variables of executor's virtual environment and
+/// extra paths, not passed to worker processes. `PYTHONPATH` of
+/// the interpreter is kept, unless isolated mode is configured
+static WORKER_CLEARED_VARIABLES: [&str; 3] = [ENV_VENV, ENV_EXTRA_PATH, "VIRTUAL_ENV"];
+
+/// workers of activated environments, by environment digest
+static WORKERS: LazyLock<Mutex<HashMap<String, Arc<PythonWorker>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Where environments are fetched from and unpacked to,
+/// and how worker processes are started.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkerOptions {
+    /// store environment archives are fetched from
+    pub store: PythonArtifactStore,
+    /// directory environments are unpacked to
+    pub cache_dir: PathBuf,
+    /// worker command, [ENV_WORKER_COMMAND] if not set. Command has
+    /// to call [run_if_worker], there is no default, as executors
+    /// started as workers would start another executor
+    pub command: Option<PathBuf>,
+}
+
+impl WorkerOptions {
+    fn command(&self) -> Result<PathBuf> {
+        match self
+            .command
+            .clone()
+            .or_else(|| std::env::var_os(ENV_WORKER_COMMAND).map(PathBuf::from))
+        {
+            Some(command) => Ok(command),
+            None => exec_err!(
+                "python worker command is not configured, set {ENV_WORKER_COMMAND} or codec's worker \
+                 command to a command calling `run_if_worker`, like `ballista-python-worker`"
+            ),
+        }
+    }
+}
+
+/// Function shipped with python environment, called in worker
+/// process of the environment.
+///
+/// Each environment gets its own worker process, with environment
+/// modules on its module path, so modules of different environments
+/// do not clash with each other or with modules of executor process.
+/// Worker loads the function from the payload it has been shipped
+/// with, arguments and results are exchanged as arrow IPC streams.
+#[derive(Debug, Clone)]
+pub struct WorkerFunction {
+    /// encoded function (`UdfProto`), as it has been shipped
+    payload: Arc<Vec<u8>>,
+    digest: String,
+    environment: PythonEnvArchive,
+    options: WorkerOptions,
+}
+
+impl WorkerFunction {
+    pub(crate) fn new(payload: Vec<u8>, environment: PythonEnvArchive, options: WorkerOptions) -> Self {
+        Self {
+            digest: sha256_hex(&payload),
+            payload: Arc::new(payload),
+            environment,
+            options,
+        }
+    }
+
+    /// encoded function, shipped further as it is
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    pub fn environment(&self) -> &PythonEnvArchive {
+        &self.environment
+    }
+
+    /// calls function in worker process of its environment,
+    /// starting the worker if it is not running
+    pub(crate) fn call(&self, name: &str, arrays: &[ArrayRef], number_rows: usize) -> Result<ArrayRef> {
+        let worker = WORKERS
+            .lock()
+            .unwrap()
+            .entry(self.environment.digest.clone())
+            .or_insert_with(|| Arc::new(PythonWorker::new(self.environment.clone(), self.options.clone())))
+            .clone();
+
+        let request = WorkerRequestProto {
+            name: name.to_string(),
+            digest: self.digest.clone(),
+            function: vec![],
+            arguments: write_batch(arrays, number_rows)?,
+            number_rows: number_rows as u64,
+        };
+        let response = worker.call(request, &self.payload)?;
+        if !response.error.is_empty() {
+            return Err(DataFusionError::Execution(response.error));
+        }
+        let batch = read_batch(&response.result)?;
+        match batch.columns().first() {
+            Some(result) => Ok(result.clone()),
+            None => exec_err!("python function: {name} returned no result from worker"),
+        }
+    }
+}
+
+impl PartialEq for WorkerFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.digest == other.digest && self.environment == other.environment
+    }
+}
+
+/// worker process of an environment, started once needed,
+/// and started again if it exits
+#[derive(Debug)]
+struct PythonWorker {
+    environment: PythonEnvArchive,
+    options: WorkerOptions,
+    process: Mutex<Option<WorkerProcess>>,
+}
+
+impl PythonWorker {
+    fn new(environment: PythonEnvArchive, options: WorkerOptions) -> Self {
+        Self {
+            environment,
+            options,
+            process: Mutex::new(None),
+        }
+    }
+
+    /// sends request to the worker, with function payload
+    /// if the function has not been loaded by the worker yet
+    fn call(&self, mut request: WorkerRequestProto, payload: &[u8]) -> Result<WorkerResponseProto> {
+        let mut process = self.process.lock().unwrap();
+        if process.is_none() {
+            *process = Some(self.spawn()?);
+        }
+        let running = process.as_mut().expect("worker process to be started");
+        let loaded = running.loaded.contains(&request.digest);
+        if !loaded {
+            request.function = SignedUdfProto {
+                payload: payload.to_vec(),
+                signature: vec![],
+            }
+            .encode_to_vec();
+        }
+        match running.request(&request) {
+            Ok(response) => {
+                if !loaded && response.error.is_empty() {
+                    running.loaded.insert(request.digest);
+                }
+                Ok(response)
+            }
+            Err(e) => {
+                // worker is started again by the next call
+                *process = None;
+                exec_err!("python worker of environment: {} failed: {e}", self.environment.digest)
+            }
+        }
+    }
+
+    /// activates environment and starts worker process with it
+    fn spawn(&self) -> Result<WorkerProcess> {
+        let module_path = self
+            .environment
+            .activate(&self.options.store, &self.options.cache_dir)?;
+        let command = self.options.command()?;
+        log::info!(
+            "starting python worker: {} of environment: {} ...",
+            command.display(),
+            self.environment.digest
+        );
+        // bundles of functions are loaded by the worker,
+        // from the same store and cache as environments
+        let mut worker = Command::new(&command);
+        worker
+            .env(ENV_WORKER_MODULE_PATH, &module_path)
+            .env(ENV_ARCHIVE_CACHE, &self.options.cache_dir);
+        // modules of executor's environment are not visible to the worker
+        for variable in WORKER_CLEARED_VARIABLES {
+            worker.env_remove(variable);
+        }
+        if self.options.store.is_shared() {
+            worker.env(ENV_ARTIFACT_STORE, self.options.store.root());
+        }
+        let mut child = worker
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| exec_datafusion_err!("python worker: {} can't be started: {e}", command.display()))?;
+        let stdin = child.stdin.take().expect("worker stdin to be piped");
+        let stdout = child.stdout.take().expect("worker stdout to be piped");
+
+        Ok(WorkerProcess {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            loaded: HashSet::new(),
+        })
+    }
+}
+
+#[derive(Debug)]
+struct WorkerProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// digests of functions loaded by the worker
+    loaded: HashSet<String>,
+}
+
+impl WorkerProcess {
+    fn request(&mut self, request: &WorkerRequestProto) -> std::io::Result<WorkerResponseProto> {
+        write_frame(&mut self.stdin, request)?;
+        read_frame(&mut self.stdout)?.ok_or_else(|| std::io::ErrorKind::UnexpectedEof.into())
+    }
+}
+
+impl Drop for WorkerProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// stops worker processes, returns number of stopped workers.
+/// workers are started again if their functions are called
+pub fn close_workers() -> usize {
+    let workers = std::mem::take(&mut *WORKERS.lock().unwrap());
+    workers
+        .values()
+        .filter(|worker| worker.process.lock().unwrap().take().is_some())
+        .count()
+}
+
+/// runs worker loop, if the process has been started as python worker,
+/// and exits the process once the worker is not needed any more.
+///
+/// Worker command, like `ballista-python-worker`, or an executor
+/// configured as its own worker command, should call it at the
+/// beginning of `main`, before anything else is done.
+pub fn run_if_worker() {
+    let Some(module_path) = std::env::var_os(ENV_WORKER_MODULE_PATH) else {
+        return;
+    };
+    let code = match run_worker(Path::new(&module_path)) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("python worker failed: {e}");
+            1
+        }
+    };
+    std::process::exit(code);
+}
+
+fn run_worker(module_path: &Path) -> Result<()> {
+    // environment is the only module path added to the interpreter,
+    // virtual environment is not looked up, not even in current directory
+    let config = PythonEnvConfig {
+        venv: None,
+        extra_paths: vec![],
+        ..PythonEnvConfig::from_env()
+    };
+    crate::setup_python_with(&config).map_err(|e| DataFusionError::Execution(e.to_string()))?;
+    let output = Python::with_gil(|py| prepare_worker(py, module_path))
+        .map_err(|e| exec_datafusion_err!("python worker can't be prepared: {e}"))?;
+    #[cfg(unix)]
+    let mut output = {
+        use std::os::fd::FromRawFd;
+        // SAFETY: descriptor has been duplicated for the worker only
+        unsafe { std::fs::File::from_raw_fd(output) }
+    };
+    #[cfg(not(unix))]
+    let mut output = {
+        let _ = output;
+        std::io::stdout()
+    };
+    let mut input = std::io::stdin().lock();
+
+    let codec = Python::with_gil(PyPhysicalCodec::for_worker)
+        .map_err(|e| exec_datafusion_err!("python worker codec can't be created: {e}"))?;
+    let mut functions = HashMap::new();
+    while let Some(request) = read_frame::<WorkerRequestProto>(&mut input)? {
+        let response = match handle_request(&codec, &mut functions, request) {
+            Ok(result) => WorkerResponseProto {
+                result,
+                error: String::new(),
+            },
+            Err(e) => WorkerResponseProto {
+                result: vec![],
+                error: e.to_string(),
+            },
+        };
+        write_frame(&mut output, &response)?;
+    }
+
+    Ok(())
+}
+
+/// puts environment in front of the module path, so its modules
+/// take precedence, and redirects stdout to stderr, so output of
+/// functions does not interfere with responses, returning
+/// descriptor responses are written to
+fn prepare_worker(py: Python<'_>, module_path: &Path) -> PyResult<i32> {
+    let sys = py.import("sys")?;
+    // entries have to be strings, path objects are ignored by importers
+    let module_path = module_path.to_string_lossy();
+    sys.getattr("path")?.call_method1("insert", (0, module_path.as_ref()))?;
+    // site dir, so `.pth` files are processed
+    py.import("site")?.call_method1("addsitedir", (module_path.as_ref(),))?;
+
+    let os = py.import("os")?;
+    sys.getattr("stdout")?.call_method0("flush")?;
+    let output = os.call_method1("dup", (1,))?.extract()?;
+    os.call_method1("dup2", (2, 1))?;
+
+    Ok(output)
+}
+
+fn handle_request(
+    codec: &PyPhysicalCodec,
+    functions: &mut HashMap<String, Arc<ScalarUDF>>,
+    request: WorkerRequestProto,
+) -> Result<Vec<u8>> {
+    if !request.function.is_empty() && !functions.contains_key(&request.digest) {
+        let function = codec.try_decode_udf(&request.name, &request.function)?;
+        functions.insert(request.digest.clone(), function);
+    }
+    let function = functions
+        .get(&request.digest)
+        .ok_or_else(|| exec_datafusion_err!("python function: {} is not loaded by worker", request.name))?;
+    let function = function
+        .inner()
+        .as_any()
+        .downcast_ref::<PythonUDF>()
+        .ok_or_else(|| exec_datafusion_err!("function: {} is not a python function", request.name))?;
+
+    let arguments = read_batch(&request.arguments)?;
+    let result = function.invoke(arguments.columns(), request.number_rows as usize)?;
+
+    write_batch(&[result], request.number_rows as usize)
+}
+
+fn write_batch(arrays: &[ArrayRef], number_rows: usize) -> Result<Vec<u8>> {
+    let fields = arrays
+        .iter()
+        .enumerate()
+        .map(|(i, a)| Field::new(format!("c{i}"), a.data_type().clone(), true))
+        .collect::<Vec<_>>();
+    let schema = Arc::new(Schema::new(fields));
+    // functions without arguments are called with number of rows only
+    let options = RecordBatchOptions::new().with_row_count(Some(number_rows));
+    let batch = RecordBatch::try_new_with_options(schema.clone(), arrays.to_vec(), &options)?;
+
+    let mut buf = vec![];
+    let mut writer = StreamWriter::try_new(&mut buf, &schema)?;
+    writer.write(&batch)?;
+    writer.finish()?;
+    drop(writer);
+
+    Ok(buf)
+}
+
+fn read_batch(buf: &[u8]) -> Result<RecordBatch> {
+    let mut reader = StreamReader::try_new(buf, None)?;
+    match reader.next() {
+        Some(batch) => Ok(batch?),
+        None => exec_err!("python worker message has no record batch"),
+    }
+}
+
+fn write_frame(output: &mut impl Write, message: &impl Message) -> std::io::Result<()> {
+    let buf = message.encode_to_vec();
+    output.write_all(&(buf.len() as u64).to_le_bytes())?;
+    output.write_all(&buf)?;
+    output.flush()
+}
+
+/// reads length prefixed message, `None` if input is closed
+fn read_frame<M: Message + Default>(input: &mut impl Read) -> std::io::Result<Option<M>> {
+    let mut length = [0u8; 8];
+    match input.read_exact(&mut length) {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let mut buf = vec![0u8; u64::from_le_bytes(length) as usize];
+    input.read_exact(&mut buf)?;
+
+    M::decode(buf.as_slice())
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct WorkerRequestProto {
+    #[prost(string, tag = 1)]
+    name: String,
+    /// digest of function payload
+    #[prost(string, tag = 2)]
+    digest: String,
+    /// encoded function, empty if the function has been loaded by the worker
+    #[prost(bytes, tag = 3)]
+    function: Vec<u8>,
+    /// function arguments, arrow IPC stream
+    #[prost(bytes, tag = 4)]
+    arguments: Vec<u8>,
+    #[prost(uint64, tag = 5)]
+    number_rows: u64,
+}
+
+#[derive(Clone, PartialEq, ::prost::Message)]
+struct WorkerResponseProto {
+    /// function result, arrow IPC stream
+    #[prost(bytes, tag = 1)]
+    result: Vec<u8>,
+    /// empty if function succeeded
+    #[prost(string, tag = 2)]
+    error: String,
+}
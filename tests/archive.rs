@@ -0,0 +1,215 @@
+mod common;
+
+use ballista_python::{
+    archive::PythonEnvArchive,
+    artifacts::PythonArtifactStore,
+    codec::PyLogicalCodec,
+    codec::PyPhysicalCodec,
+    setup_python,
+    udf::{CallMode, PythonUDF},
+};
+use common::temp_dir;
+use datafusion::arrow::array::{AsArray, RecordBatch};
+use datafusion::arrow::datatypes::Float64Type;
+use datafusion::common::Result;
+use datafusion::logical_expr::ScalarUDF;
+use datafusion::prelude::SessionContext;
+use datafusion_proto::logical_plan::LogicalExtensionCodec;
+use datafusion_proto::physical_plan::PhysicalExtensionCodec;
+use pyo3::types::PyAnyMethods;
+use pyo3::Python;
+use std::path::Path;
+use std::sync::Arc;
+
+static WORKER_COMMAND: &str = env!("CARGO_BIN_EXE_ballista-python-worker");
+
+#[tokio::test]
+async fn should_ship_environment_with_function() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let root = temp_dir("should_ship_environment_with_function");
+
+    // environment with a module, not available at the executor
+    let archive_path = root.join("env.zip");
+    create_zip(&archive_path, "shipped_helper.py", "MULTIPLIER = 2.0\n");
+    let archive = PythonEnvArchive::from_path(&archive_path)?;
+
+    // function is called in worker process, not in this one
+    let code = format!(
+        r#"
+def to_miles(values):
+    import os
+    import shipped_helper
+    assert shipped_helper.MULTIPLIER == 2.0
+    assert os.getpid() != {}
+    return values
+"#,
+        std::process::id()
+    );
+    let udf = PythonUDF::from_code("to_miles", &code)?.with_mode(CallMode::Capsule);
+    let udf = ScalarUDF::from(udf);
+
+    let store = PythonArtifactStore::new(root.join("store"));
+    let mut buf = vec![];
+    PyLogicalCodec::default()
+        .with_environment(archive.clone())
+        .with_artifact_store(store.clone())
+        .try_encode_udf(&udf, &mut buf)?;
+
+    // environment is shipped by digest, its content is in the store
+    let content = archive.content.as_ref().unwrap();
+    assert!(!buf.windows(content.len()).any(|w| w == content.as_slice()));
+    assert_eq!(archive.content.as_deref(), Some(&store.get(&archive.digest)?));
+
+    let cache_dir = root.join("cache");
+    let codec = PyPhysicalCodec::default()
+        .with_artifact_store(store)
+        .with_env_cache_dir(&cache_dir)
+        .with_worker_command(WORKER_COMMAND);
+    let decoded = codec.try_decode_udf("to_miles", &buf)?;
+    let python_udf = decoded.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+    assert_eq!(Some(&archive), python_udf.environment.as_ref());
+
+    let result = call(decoded.clone()).await?;
+    let result = result[0].column(0).as_primitive::<Float64Type>();
+    assert_eq!(vec![Some(1.5), None, Some(3.0)], result.iter().collect::<Vec<_>>());
+
+    // environment is not added to this process
+    Python::with_gil(|py| {
+        let sys = py.import("sys").unwrap();
+        let path: Vec<String> = sys.getattr("path").unwrap().extract().unwrap();
+        assert!(!path.iter().any(|p| p.contains(&archive.digest)));
+        assert!(!sys.getattr("modules").unwrap().contains("shipped_helper").unwrap());
+    });
+
+    // environment unpacked once
+    call(decoded).await?;
+    assert_eq!(1, std::fs::read_dir(&cache_dir)?.count());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_ship_environment_content_without_shared_store() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let root = temp_dir("should_ship_environment_content_without_shared_store");
+
+    let archive_path = root.join("env.zip");
+    create_zip(&archive_path, "inline_helper.py", "MULTIPLIER = 2.0\n");
+    let archive = PythonEnvArchive::from_path(&archive_path)?;
+    let code = "def to_miles(values):\n    import inline_helper\n    return values\n";
+    let udf = ScalarUDF::from(PythonUDF::from_code("to_miles", code)?.with_mode(CallMode::Capsule));
+
+    // store of the client host, executors can't reach it
+    let store = PythonArtifactStore::local(root.join("client_store"));
+    let mut buf = vec![];
+    PyLogicalCodec::default()
+        .with_environment(archive.clone())
+        .with_artifact_store(store.clone())
+        .try_encode_udf(&udf, &mut buf)?;
+    assert!(store.get(&archive.digest).is_err());
+
+    // executor with other store gets content with the function
+    let decoded = PyPhysicalCodec::default()
+        .with_artifact_store(PythonArtifactStore::local(root.join("executor_store")))
+        .with_env_cache_dir(root.join("cache"))
+        .with_worker_command(WORKER_COMMAND)
+        .try_decode_udf("to_miles", &buf)?;
+    let result = call(decoded).await?;
+    assert_eq!(3, result[0].num_rows());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_require_worker_command() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let root = temp_dir("should_require_worker_command");
+
+    let archive_path = root.join("env.zip");
+    create_zip(&archive_path, "helper.py", "MULTIPLIER = 2.0\n");
+    let udf = PythonUDF::from_code("f", "def f(values):\n    return values\n")?.with_mode(CallMode::Capsule);
+    let mut buf = vec![];
+    PyLogicalCodec::default()
+        .with_environment(PythonEnvArchive::from_path(&archive_path)?)
+        .try_encode_udf(&ScalarUDF::from(udf), &mut buf)?;
+
+    // test executable does not run as worker, it is not started
+    let decoded = PyPhysicalCodec::default()
+        .with_env_cache_dir(root.join("cache"))
+        .try_decode_udf("f", &buf)?;
+    let message = call(decoded).await.unwrap_err().to_string();
+    assert!(message.contains("worker command is not configured"), "{message}");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_reject_archive_with_member_outside_of_target() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let root = temp_dir("should_reject_archive_with_member_outside_of_target");
+
+    let archive_path = root.join("env.zip");
+    create_zip(&archive_path, "../evil.py", "raise RuntimeError()\n");
+    let archive = PythonEnvArchive::from_path(&archive_path)?;
+
+    let udf = PythonUDF::from_code("f", "def f(values):\n    return values\n")?.with_mode(CallMode::Capsule);
+    let store = PythonArtifactStore::new(root.join("store"));
+    let mut buf = vec![];
+    PyLogicalCodec::default()
+        .with_environment(archive)
+        .with_artifact_store(store.clone())
+        .try_encode_udf(&ScalarUDF::from(udf), &mut buf)?;
+
+    let cache_dir = root.join("cache");
+    let decoded = PyPhysicalCodec::default()
+        .with_artifact_store(store)
+        .with_env_cache_dir(&cache_dir)
+        .with_worker_command(WORKER_COMMAND)
+        .try_decode_udf("f", &buf)?;
+
+    let message = call(decoded).await.unwrap_err().to_string();
+    assert!(message.contains("outside of target directory"), "{message}");
+    assert!(!cache_dir.join("evil.py").exists());
+
+    Ok(())
+}
+
+#[test]
+fn should_reject_archive_digest_which_is_not_hex_digest() {
+    setup_python().expect("python environment to be set");
+    let root = temp_dir("should_reject_archive_digest_which_is_not_hex_digest");
+    // directory outside of the cache, which would be trusted as unpacked
+    std::fs::create_dir_all(root.join("outside")).unwrap();
+
+    let error = PythonEnvArchive::from_digest("zip", "../outside").unwrap_err();
+    assert!(error.to_string().contains("invalid python environment digest"));
+
+    let archive = PythonEnvArchive {
+        format: "zip".into(),
+        digest: "../outside".into(),
+        content: None,
+    };
+    let store = PythonArtifactStore::new(root.join("store"));
+    let error = archive.activate(&store, &root.join("cache")).unwrap_err();
+    assert!(error.to_string().contains("invalid python environment digest"));
+}
+
+async fn call(udf: Arc<ScalarUDF>) -> Result<Vec<RecordBatch>> {
+    let ctx = SessionContext::new();
+    let name = udf.name().to_string();
+    ctx.register_udf(udf.as_ref().clone());
+    ctx.sql(&format!("select {name}(column1) from (values (1.5), (null), (3.0))"))
+        .await?
+        .collect()
+        .await
+}
+
+fn create_zip(path: &Path, name: &str, content: &str) {
+    Python::with_gil(|py| -> pyo3::PyResult<()> {
+        let zip = py.import("zipfile")?.getattr("ZipFile")?.call1((path, "w"))?;
+        zip.call_method1("writestr", (name, content))?;
+        zip.call_method0("close")?;
+        Ok(())
+    })
+    .expect("archive created");
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use ballista_python::{
+    artifacts::PythonArtifactStore, bundle::PythonBundle, codec::PyPhysicalCodec, setup_python, sql::PythonSessionExt,
+    udf::PythonUDF,
+};
+use common::temp_dir;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::execution::FunctionRegistry;
+use datafusion::logical_expr::{ScalarUDF, Volatility};
+use datafusion::prelude::SessionContext;
+use datafusion_proto::physical_plan::PhysicalExtensionCodec;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn should_ship_bundle_with_function() -> datafusion::common::Result<()> {
+    setup_python().expect("python environment to be set");
+    let root = temp_dir("should_ship_bundle_with_function");
+    let modules = write_modules(&root.join("modules"), "0.62137119");
+
+    let bundle = PythonBundle::from_path(&modules, "main:to_miles")?;
+    // bundle content does not depend on when it is packed
+    assert_eq!(bundle, PythonBundle::from_path(&modules, "main:to_miles")?);
+
+    let udf = PythonUDF::from_bundle(
+        "to_miles",
+        bundle.clone(),
+        vec![DataType::Float64],
+        DataType::Float64,
+        Volatility::Immutable,
+    )?;
+    let udf = ScalarUDF::from(udf);
+
+    let mut buf = vec![];
+    let store = PythonArtifactStore::new(root.join("store"));
+    let codec = PyPhysicalCodec::default()
+        .with_artifact_store(store.clone())
+        .with_env_cache_dir(root.join("cache"));
+    codec.try_encode_udf(&udf, &mut buf)?;
+    // bundle content is published once, not shipped with the function
+    assert_eq!(bundle.content.as_deref(), Some(&store.get(&bundle.digest)?));
+    assert!(buf.len() < bundle.content.as_ref().unwrap().len());
+    let decoded = codec.try_decode_udf("to_miles", &buf)?;
+    let decoded = decoded.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+
+    assert_eq!(
+        Some(&PythonBundle::from_digest(&bundle.digest, "main:to_miles")),
+        decoded.bundle.as_ref()
+    );
+    assert!(decoded.source.is_none());
+    assert!(root.join("cache").join(format!("bundle-{}", bundle.digest)).is_dir());
+
+    pyo3::Python::with_gil(|py| {
+        let result: f64 = decoded.func.call1(py, (1.0,)).unwrap().extract(py).unwrap();
+        assert_eq!(0.62137119, result);
+    });
+
+    Ok(())
+}
+
+#[test]
+fn should_not_clash_bundles_with_same_modules() -> datafusion::common::Result<()> {
+    setup_python().expect("python environment to be set");
+    let root = temp_dir("should_not_clash_bundles_with_same_modules");
+    let first = PythonBundle::from_path(write_modules(&root.join("first"), "1.0"), "main:to_miles")?;
+    let second = PythonBundle::from_path(write_modules(&root.join("second"), "2.0"), "main:to_miles")?;
+    assert_ne!(first.package(), second.package());
+
+    pyo3::Python::with_gil(|py| -> datafusion::common::Result<()> {
+        let store = PythonArtifactStore::new(root.join("store"));
+        let cache_dir = root.join("cache");
+        let first = first.load(py, &store, &cache_dir)?;
+        let second = second.load(py, &store, &cache_dir)?;
+        assert_eq!(1.0, first.call1(py, (1.0,)).unwrap().extract::<f64>(py).unwrap());
+        assert_eq!(2.0, second.call1(py, (1.0,)).unwrap().extract::<f64>(py).unwrap());
+        Ok(())
+    })
+}
+
+#[tokio::test]
+async fn should_create_function_from_bundle() -> datafusion::common::Result<()> {
+    setup_python().expect("python environment to be set");
+    let root = temp_dir("should_create_function_from_bundle");
+    let modules = write_modules(&root.join("modules"), "0.62137119");
+    let ctx = SessionContext::new();
+
+    ctx.python_sql(&format!(
+        "CREATE FUNCTION to_miles(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON \
+         OPTIONS (bundle '{}', handler 'main:to_miles')",
+        modules.display()
+    ))
+    .await?;
+    let udf = ctx.udf("to_miles")?;
+    let udf = udf.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+    assert_eq!(
+        Some("main:to_miles"),
+        udf.bundle.as_ref().map(|b| b.entry_point.as_str())
+    );
+
+    // entry point has to be declared
+    assert!(ctx
+        .python_sql(&format!(
+            "CREATE FUNCTION other(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON OPTIONS (bundle '{}')",
+            modules.display()
+        ))
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+/// writes modules importing each other, `main.to_miles`
+/// returns the multiplier of `helpers` module
+fn write_modules(dir: &Path, multiplier: &str) -> PathBuf {
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("helpers.py"),
+        format!("def multiplier():\n    return {multiplier}\n"),
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("main.py"),
+        "from . import helpers\n\ndef to_miles(km):\n    return km * helpers.multiplier()\n",
+    )
+    .unwrap();
+    dir.to_path_buf()
+}
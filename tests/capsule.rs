@@ -0,0 +1,135 @@
+use ballista_python::setup_python;
+use ballista_python::udf::{CallMode, PythonUDF};
+use datafusion::arrow::array::{Array, AsArray, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Float64Type, Int64Type};
+use datafusion::common::Result;
+use datafusion::logical_expr::ScalarUDF;
+use datafusion::prelude::SessionContext;
+
+async fn call(udf: PythonUDF) -> Result<Vec<RecordBatch>> {
+    let ctx = SessionContext::new();
+    ctx.register_udf(ScalarUDF::from(udf));
+    ctx.sql("select f(column1) from (values (1.5), (null), (3.0))")
+        .await?
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn should_pass_arguments_without_pyarrow() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def f(values):
+    assert len(values) == 3
+    return values
+"#;
+    let udf = PythonUDF::from_code("f", code)?.with_mode(CallMode::Capsule);
+
+    let result = call(udf).await?;
+    let result = result[0].column(0).as_primitive::<Float64Type>();
+    assert_eq!(vec![Some(1.5), None, Some(3.0)], result.iter().collect::<Vec<_>>());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_cast_arrow_compatible_result_to_return_type() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    // any object exporting arrow array, like nanoarrow or polars ones
+    let code = r#"
+class Exported:
+    def __init__(self, array):
+        self.array = array
+
+    def __arrow_c_array__(self, requested_schema=None):
+        return self.array.__arrow_c_array__(requested_schema)
+
+def f(values):
+    return Exported(values)
+"#;
+    let udf = PythonUDF::from_code_with_types("f", code, vec![DataType::Float64], DataType::Int64)?
+        .with_mode(CallMode::Capsule);
+
+    let result = call(udf).await?;
+    let result = result[0].column(0);
+    assert_eq!(&DataType::Int64, result.data_type());
+    assert_eq!(
+        vec![Some(1), None, Some(3)],
+        result.as_primitive::<Int64Type>().iter().collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_convert_sequence_result_with_return_type() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def f(values):
+    return [None if v is None else v * 2 for v in values.to_pylist()]
+"#;
+    let udf = PythonUDF::from_code("f", code)?;
+
+    let result = call(udf).await?;
+    let result = result[0].column(0).as_primitive::<Float64Type>();
+    assert_eq!(vec![Some(3.0), None, Some(6.0)], result.iter().collect::<Vec<_>>());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_reject_result_with_other_number_of_rows() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    // returns values of the first call
+    let code = r#"
+def f(values):
+    global first
+    if "first" not in globals():
+        first = values
+    return first
+"#;
+    let udf = PythonUDF::from_code("f", code)?.with_mode(CallMode::Capsule);
+    let ctx = SessionContext::new();
+    ctx.register_udf(ScalarUDF::from(udf));
+
+    ctx.sql("select f(column1) from (values (1.5), (null), (3.0))")
+        .await?
+        .collect()
+        .await?;
+    let message = ctx
+        .sql("select f(column1) from (values (1.5), (2.5))")
+        .await?
+        .collect()
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(
+        message.contains("returned 3 values, expected one value per row: 2"),
+        "{message}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_expand_scalar_arguments_to_all_rows() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def f(values):
+    assert len(values) == 3
+    return values
+"#;
+    let udf = PythonUDF::from_code("f", code)?.with_mode(CallMode::Capsule);
+    let ctx = SessionContext::new();
+    ctx.register_udf(ScalarUDF::from(udf));
+
+    let result = ctx
+        .sql("select f(1.5) from (values (1), (2), (3))")
+        .await?
+        .collect()
+        .await?;
+    let result = result[0].column(0).as_primitive::<Float64Type>();
+    assert_eq!(vec![Some(1.5); 3], result.iter().collect::<Vec<_>>());
+
+    Ok(())
+}
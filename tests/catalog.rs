@@ -0,0 +1,149 @@
+mod common;
+
+use ballista_python::catalog::{DirectoryCatalog, FunctionCatalog, FunctionDefinition};
+use ballista_python::factory::PythonFunctionFactory;
+use ballista_python::setup_python;
+use ballista_python::sql::PythonSessionExt;
+use common::temp_dir;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::Result;
+use datafusion::execution::FunctionRegistry;
+use datafusion::logical_expr::Volatility;
+use datafusion::prelude::SessionContext;
+use std::sync::Arc;
+
+#[test]
+fn should_store_function_definitions() -> Result<()> {
+    let dir = temp_dir("should_store_function_definitions");
+    let catalog = DirectoryCatalog::try_new(dir.path())?;
+    let definition = FunctionDefinition {
+        name: "to miles".to_string(),
+        language: "python".to_string(),
+        args: vec![("km".to_string(), DataType::Float64), (String::new(), DataType::Int32)],
+        return_type: DataType::Float64,
+        volatility: Some(Volatility::Immutable),
+        body: "def to_miles(km, n):\n    return km\n".to_string(),
+        options: vec![
+            ("handler".to_string(), "to_miles".to_string()),
+            ("mode".to_string(), "pandas".to_string()),
+        ],
+        strict: true,
+    };
+
+    catalog.save(&definition)?;
+    assert_eq!(vec![definition.clone()], catalog.load()?);
+
+    assert!(catalog.remove("to miles")?);
+    assert!(!catalog.remove("to miles")?);
+    assert!(catalog.load()?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_skip_corrupt_function_files() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let dir = temp_dir("should_skip_corrupt_function_files");
+    let catalog = Arc::new(DirectoryCatalog::try_new(dir.path())?);
+    let factory = Arc::new(PythonFunctionFactory::default().with_catalog(catalog.clone()));
+    let ctx = SessionContext::new();
+    ctx.register_function_factory(factory.clone()).await?;
+    ctx.python_sql(
+        "CREATE FUNCTION km_to_miles(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON AS 'def km_to_miles(km):\n    return km\n'",
+    )
+    .await?;
+    std::fs::write(dir.path().join("corrupt.function"), b"not a function definition")?;
+
+    assert_eq!(1, catalog.load()?.len());
+    let other_ctx = SessionContext::new();
+    other_ctx.register_function_factory(factory).await?;
+    assert!(other_ctx.udf("km_to_miles").is_ok());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_load_catalog_functions_into_new_sessions() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let dir = temp_dir("should_load_catalog_functions_into_new_sessions");
+    let catalog = Arc::new(DirectoryCatalog::try_new(dir.path())?);
+    let factory = Arc::new(PythonFunctionFactory::default().with_catalog(catalog.clone()));
+
+    let ctx = SessionContext::new();
+    ctx.register_function_factory(factory.clone()).await?;
+    ctx.python_sql(
+        "CREATE FUNCTION km_to_miles(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON IMMUTABLE STRICT \
+         OPTIONS (handler 'to_miles') AS 'def to_miles(km):\n    return km\n'",
+    )
+    .await?;
+    ctx.sql("CREATE TEMPORARY FUNCTION session_only(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON AS 'def session_only(a):\n    return a\n'")
+        .await?;
+    assert!(ctx.udf("session_only").is_ok());
+
+    let definitions = catalog.load()?;
+    assert_eq!(1, definitions.len());
+    assert!(definitions[0].strict);
+
+    let other_ctx = SessionContext::new();
+    other_ctx.register_function_factory(factory).await?;
+    let udf = other_ctx.udf("km_to_miles")?;
+    assert_eq!(Volatility::Immutable, udf.signature().volatility);
+    assert!(other_ctx.udf("session_only").is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_replace_and_drop_catalog_functions() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let dir = temp_dir("should_replace_and_drop_catalog_functions");
+    let catalog = Arc::new(DirectoryCatalog::try_new(dir.path())?);
+    let factory = Arc::new(PythonFunctionFactory::default().with_catalog(catalog.clone()));
+    let ctx = SessionContext::new();
+    ctx.register_function_factory(factory.clone()).await?;
+
+    let create =
+        "FUNCTION km_to_miles(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON AS 'def km_to_miles(km):\n    return km\n'";
+    ctx.python_sql(&format!("CREATE {create}")).await?;
+    let error = ctx.python_sql(&format!("CREATE {create}")).await.unwrap_err();
+    assert!(error.to_string().contains("already exists"));
+
+    ctx.python_sql(&format!("CREATE OR REPLACE {create} IMMUTABLE")).await?;
+    assert_eq!(Volatility::Immutable, ctx.udf("km_to_miles")?.signature().volatility);
+    assert_eq!(Some(Volatility::Immutable), catalog.load()?[0].volatility);
+
+    ctx.python_sql("DROP FUNCTION km_to_miles").await?;
+    assert!(ctx.udf("km_to_miles").is_err());
+    assert!(catalog.load()?.is_empty());
+
+    assert!(ctx.python_sql("DROP FUNCTION km_to_miles").await.is_err());
+    ctx.python_sql("DROP FUNCTION IF EXISTS km_to_miles").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_keep_function_dropped_with_sql_in_catalog() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let dir = temp_dir("should_keep_function_dropped_with_sql_in_catalog");
+    let catalog = Arc::new(DirectoryCatalog::try_new(dir.path())?);
+    let factory = Arc::new(PythonFunctionFactory::default().with_catalog(catalog.clone()));
+    let ctx = SessionContext::new();
+    ctx.register_function_factory(factory.clone()).await?;
+
+    ctx.sql(
+        "CREATE FUNCTION km_to_miles(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON AS 'def km_to_miles(km):\n    return km\n'",
+    )
+    .await?;
+    assert_eq!(1, catalog.load()?.len());
+
+    // factory is not notified, function is dropped from the session only
+    ctx.sql("DROP FUNCTION km_to_miles").await?;
+    assert!(ctx.udf("km_to_miles").is_err());
+    let other_ctx = SessionContext::new();
+    other_ctx.register_function_factory(factory.clone()).await?;
+    assert!(other_ctx.udf("km_to_miles").is_ok());
+    assert_eq!(1, catalog.load()?.len());
+
+    Ok(())
+}
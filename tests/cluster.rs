@@ -0,0 +1,261 @@
+mod common;
+
+use ballista_python::cluster::{
+    ClusterFunctionCommand, ClusterFunctionExec, ClusterFunctionNode, ClusterFunctionRegistry, Namespace,
+};
+use ballista_python::codec::{PyLogicalCodec, PyPhysicalCodec};
+use ballista_python::factory::PythonFunctionFactory;
+use ballista_python::map_batches::PythonQueryPlanner;
+use ballista_python::setup_python;
+use ballista_python::sql::PythonSessionExt;
+use ballista_scheduler::scheduler_server::SessionBuilder;
+use common::temp_dir;
+use datafusion::common::Result;
+use datafusion::execution::context::QueryPlanner;
+use datafusion::execution::FunctionRegistry;
+use datafusion::execution::{SessionState, SessionStateBuilder};
+use datafusion::logical_expr::{Extension, LogicalPlan};
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::prelude::{DataFrame, SessionConfig, SessionContext};
+use datafusion_proto::logical_plan::LogicalExtensionCodec;
+use datafusion_proto::physical_plan::PhysicalExtensionCodec;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// sessions planning cluster registry commands like scheduler
+/// sessions do, so they act as both client and scheduler
+fn scheduler_session(registry: &Arc<ClusterFunctionRegistry>, schema: &str) -> Result<SessionContext> {
+    let config = SessionConfig::new().with_default_catalog_and_schema("datafusion", schema);
+    let state = registry.session_builder(None)(config)?;
+
+    Ok(SessionContext::new_with_state(state))
+}
+
+#[tokio::test]
+async fn should_share_functions_between_sessions() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let registry = Arc::new(ClusterFunctionRegistry::in_memory());
+    let factory = Arc::new(
+        PythonFunctionFactory::default()
+            .with_cluster_registry(true)
+            .with_cluster_registry_token("owner"),
+    );
+
+    let ctx = scheduler_session(&registry, "public")?;
+    ctx.register_function_factory(factory.clone()).await?;
+    ctx.python_sql(
+        "CREATE FUNCTION km_to_miles(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON \
+         AS 'def km_to_miles(km):\n    return km\n'",
+    )
+    .await?;
+    let definitions = registry.catalog(&Namespace::new("datafusion", "public"))?.load()?;
+    assert_eq!("km_to_miles", definitions[0].name);
+
+    let other_ctx = scheduler_session(&registry, "public")?;
+    other_ctx.register_function_factory(factory.clone()).await?;
+    assert!(other_ctx.udf("km_to_miles").is_ok());
+
+    // functions are namespaced per default catalog and schema
+    let other_schema_ctx = scheduler_session(&registry, "other")?;
+    other_schema_ctx.register_function_factory(factory.clone()).await?;
+    assert!(other_schema_ctx.udf("km_to_miles").is_err());
+
+    other_ctx.python_sql("DROP FUNCTION km_to_miles").await?;
+    assert!(registry
+        .catalog(&Namespace::new("datafusion", "public"))?
+        .load()?
+        .is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_modify_namespace_with_owner_token_only() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let directory = temp_dir("should_modify_namespace_with_owner_token_only");
+    let registry = Arc::new(ClusterFunctionRegistry::in_directory(directory.path()));
+    let create = "CREATE FUNCTION km_to_miles(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON \
+                  AS 'def km_to_miles(km):\n    return km\n'";
+
+    let owner = PythonFunctionFactory::default()
+        .with_cluster_registry(true)
+        .with_cluster_registry_token("owner");
+    let ctx = scheduler_session(&registry, "public")?;
+    ctx.register_function_factory(Arc::new(owner)).await?;
+    ctx.python_sql(create).await?;
+
+    // owner is kept in the directory, like functions
+    let registry = Arc::new(ClusterFunctionRegistry::in_directory(directory.path()));
+    for factory in [
+        PythonFunctionFactory::default().with_cluster_registry(true),
+        PythonFunctionFactory::default()
+            .with_cluster_registry(true)
+            .with_cluster_registry_token("other"),
+    ] {
+        let other_ctx = scheduler_session(&registry, "public")?;
+        other_ctx.register_function_factory(Arc::new(factory)).await?;
+        // functions can be loaded, not modified
+        assert!(other_ctx.udf("km_to_miles").is_ok());
+        let message = other_ctx
+            .python_sql(&create.replace("CREATE", "CREATE OR REPLACE"))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("namespace: datafusion.public"), "{message}");
+    }
+    assert_eq!(
+        1,
+        registry.catalog(&Namespace::new("datafusion", "public"))?.load()?.len()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_execute_command_once_and_on_its_own() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let registry = Arc::new(ClusterFunctionRegistry::in_memory());
+    let ctx = scheduler_session(&registry, "public")?;
+    ctx.register_function_factory(Arc::new(
+        PythonFunctionFactory::default()
+            .with_cluster_registry(true)
+            .with_cluster_registry_token("owner"),
+    ))
+    .await?;
+    ctx.python_sql(
+        "CREATE FUNCTION km_to_miles(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON \
+         AS 'def km_to_miles(km):\n    return km\n'",
+    )
+    .await?;
+
+    // explained command is not executed
+    let node = ClusterFunctionNode::try_new(
+        Namespace::new("datafusion", "public"),
+        ClusterFunctionCommand::Remove("km_to_miles".to_string()),
+    )?
+    .with_token("owner");
+    let plan = LogicalPlan::Extension(Extension {
+        node: Arc::new(node.clone()),
+    });
+    let explained = DataFrame::new(ctx.state(), plan.clone()).explain(false, false)?;
+    let explained = format!("{:?}", explained.collect().await);
+    assert!(explained.contains("has to be executed on its own"), "{explained}");
+    assert_eq!(
+        1,
+        registry.catalog(&Namespace::new("datafusion", "public"))?.load()?.len()
+    );
+
+    // planned command is not executed until the plan is
+    let physical = ctx.state().create_physical_plan(&plan).await?;
+    assert_eq!(
+        1,
+        registry.catalog(&Namespace::new("datafusion", "public"))?.load()?.len()
+    );
+    assert!(format!("{node:?}").contains("<redacted>"));
+    assert!(!format!("{node:?}").contains("owner"));
+    assert!(!format!("{physical:?}").contains("\"owner\""));
+
+    // command planned again is not applied again
+    let result = DataFrame::new(ctx.state(), plan.clone()).collect().await?;
+    assert_eq!(1, result[0].num_rows());
+    let result = DataFrame::new(ctx.state(), plan).collect().await?;
+    assert_eq!(1, result[0].num_rows());
+    assert_eq!(vec![("km_to_miles".to_string(), None)], registry.execute(&node)?);
+
+    Ok(())
+}
+
+/// query planner configured by user session builder
+#[derive(Debug, Default)]
+struct CountingQueryPlanner {
+    planned: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl QueryPlanner for CountingQueryPlanner {
+    async fn create_physical_plan(
+        &self,
+        logical_plan: &LogicalPlan,
+        session_state: &SessionState,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        self.planned.fetch_add(1, Ordering::SeqCst);
+        PythonQueryPlanner::default()
+            .create_physical_plan(logical_plan, session_state)
+            .await
+    }
+}
+
+#[tokio::test]
+async fn should_keep_query_planner_of_session_builder() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let registry = Arc::new(ClusterFunctionRegistry::in_memory());
+    let planner = Arc::new(CountingQueryPlanner::default());
+    let user_planner = planner.clone();
+    let builder: SessionBuilder = Arc::new(move |config| {
+        Ok(SessionStateBuilder::new()
+            .with_default_features()
+            .with_config(config)
+            .with_query_planner(user_planner.clone())
+            .build())
+    });
+    let state = registry.session_builder(Some(builder))(SessionConfig::new())?;
+    let ctx = SessionContext::new_with_state(state);
+    ctx.register_function_factory(Arc::new(
+        PythonFunctionFactory::default()
+            .with_cluster_registry(true)
+            .with_cluster_registry_token("owner"),
+    ))
+    .await?;
+
+    // commands are executed against the registry
+    ctx.python_sql(
+        "CREATE FUNCTION km_to_miles(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON \
+         AS 'def km_to_miles(km):\n    return km\n'",
+    )
+    .await?;
+    assert_eq!(
+        1,
+        registry.catalog(&Namespace::new("datafusion", "public"))?.load()?.len()
+    );
+    let planned = planner.planned.load(Ordering::SeqCst);
+
+    // other plans are planned by planner of the builder
+    ctx.sql("SELECT 1").await?.collect().await?;
+    assert_eq!(planned + 1, planner.planned.load(Ordering::SeqCst));
+
+    Ok(())
+}
+
+#[test]
+fn should_round_trip_cluster_function_plans() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let ctx = SessionContext::new();
+
+    let node = ClusterFunctionNode::try_new(
+        Namespace::new("datafusion", "public"),
+        ClusterFunctionCommand::Remove("km_to_miles".to_string()),
+    )?;
+    let extension = Extension { node: Arc::new(node) };
+    let codec = PyLogicalCodec::default();
+    let mut buf = vec![];
+    codec.try_encode(&extension, &mut buf)?;
+    let decoded = codec.try_decode(&buf, &[], &ctx)?;
+    assert_eq!(extension, decoded);
+
+    let exec = Arc::new(ClusterFunctionExec::new(vec![("km_to_miles".to_string(), None)]));
+    let codec = PyPhysicalCodec::default();
+    let mut buf = vec![];
+    codec.try_encode(exec.clone(), &mut buf)?;
+    let decoded = codec.try_decode(&buf, &[], &ctx)?;
+    assert_eq!(exec.name(), decoded.name());
+    assert_eq!(
+        exec.encode_to_vec()?,
+        decoded
+            .as_any()
+            .downcast_ref::<ClusterFunctionExec>()
+            .unwrap()
+            .encode_to_vec()?
+    );
+
+    Ok(())
+}
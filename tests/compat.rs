@@ -0,0 +1,170 @@
+use ballista_python::codec::{PyLogicalCodec, PyPhysicalCodec};
+use ballista_python::compat::{from_datafusion_udf, udf_function, PICKLED_TUPLE_MAGIC};
+use ballista_python::setup_python;
+use ballista_python::signing::UdfSigner;
+use ballista_python::udf::{CallMode, PythonUDF};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::logical_expr::ScalarUDF;
+use datafusion::logical_expr::{ScalarUDFImpl, Volatility};
+use datafusion_proto::logical_plan::LogicalExtensionCodec;
+use datafusion_proto::physical_plan::PhysicalExtensionCodec;
+use pyo3::ffi::c_str;
+use pyo3::types::{PyAnyMethods, PyModule};
+use pyo3::Python;
+
+/// module mimicking datafusion-python `ScalarUDF`,
+/// which keeps only native wrapper of the function
+static DATAFUSION_CODE: &std::ffi::CStr = c_str!(
+    r#"
+class ScalarUDF:
+    def __init__(self, name, func, input_types, return_type, volatility):
+        self._udf = object()
+
+    def __datafusion_scalar_udf__(self):
+        raise NotImplementedError()
+
+def udf(func, input_types, return_type, volatility, name=None):
+    return ScalarUDF(name=name, func=func, input_types=input_types, return_type=return_type, volatility=volatility)
+"#
+);
+
+#[test]
+fn should_convert_datafusion_udf_created_with_udf_function() -> datafusion::common::Result<()> {
+    setup_python().expect("python environment to be set");
+    Python::with_gil(|py| -> datafusion::common::Result<()> {
+        let user_defined = PyModule::from_code(
+            py,
+            DATAFUSION_CODE,
+            c_str!("user_defined.py"),
+            c_str!("datafusion.user_defined"),
+        )
+        .unwrap();
+        let package = PyModule::new(py, "datafusion").unwrap();
+        package.setattr("udf", user_defined.getattr("udf").unwrap()).unwrap();
+        let modules = py.import("sys").unwrap().getattr("modules").unwrap();
+        modules.set_item("datafusion", package).unwrap();
+
+        let pyarrow = py.import("pyarrow").unwrap();
+        let float64 = pyarrow.getattr("float64").unwrap().call0().unwrap();
+        let func = py.eval(c_str!("lambda km: km"), None, None).unwrap();
+
+        // python function of datafusion-python function is not exposed
+        let native = user_defined
+            .getattr("udf")
+            .unwrap()
+            .call1((&func, vec![&float64], &float64, "stable"))
+            .unwrap();
+        let message = from_datafusion_udf(&native).unwrap_err().to_string();
+        assert!(message.contains("ballista_python.udf"), "{message}");
+
+        let udf = udf_function(py)?;
+        let created = udf.call1((&func, &float64, &float64, "immutable", "to_miles")).unwrap();
+        // datafusion-python function is created, with definition next to it
+        assert!(created.hasattr("__datafusion_scalar_udf__").unwrap());
+        let converted = from_datafusion_udf(&created)?;
+        assert_eq!("to_miles", converted.name);
+        assert_eq!(vec![DataType::Float64], converted.input_types);
+        assert_eq!(DataType::Float64, converted.return_type);
+        assert_eq!(Volatility::Immutable, converted.signature().volatility);
+
+        // used as decorator
+        let decorator = udf.call1((vec![&float64], &float64, "stable")).unwrap();
+        let decorated = decorator.call1((&func,)).unwrap();
+        let converted = from_datafusion_udf(&decorated)?;
+        assert_eq!("<lambda>", converted.name);
+        assert_eq!(Volatility::Stable, converted.signature().volatility);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn should_reject_objects_which_are_not_datafusion_udfs() {
+    setup_python().expect("python environment to be set");
+    Python::with_gil(|py| {
+        let func = py.eval(c_str!("lambda km: km"), None, None).unwrap();
+        let error = from_datafusion_udf(&func).unwrap_err();
+        assert!(error.to_string().contains("is not a datafusion-python function"));
+    });
+}
+
+#[test]
+fn should_decode_function_in_pickled_tuple_format() -> datafusion::common::Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = "def to_miles(km):\n    return km\n";
+    let udf = ScalarUDF::from(PythonUDF::from_code("to_miles", code)?);
+
+    let mut buf = vec![];
+    PyLogicalCodec::default()
+        .with_pickled_tuple_format(true)
+        .try_encode_udf(&udf, &mut buf)?;
+    assert!(buf.starts_with(PICKLED_TUPLE_MAGIC));
+
+    let decoded = PyPhysicalCodec::default().try_decode_udf("to_miles", &buf)?;
+    let decoded = decoded.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+    assert_eq!("to_miles", decoded.name);
+    assert_eq!(vec![DataType::Float64], decoded.input_types);
+    assert_eq!(DataType::Float64, decoded.return_type);
+
+    Ok(())
+}
+
+#[test]
+fn should_not_encode_function_state_pickled_tuple_format_can_not_carry() -> datafusion::common::Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = "def to_miles(km):\n    return km\n";
+    let udf = PythonUDF::from_code("to_miles", code)?
+        .with_mode(CallMode::Capsule)
+        .with_strict(true);
+    let udf = ScalarUDF::from(udf);
+
+    let message = PyLogicalCodec::default()
+        .with_pickled_tuple_format(true)
+        .try_encode_udf(&udf, &mut vec![])
+        .unwrap_err()
+        .to_string();
+    assert!(message.contains("it has: strict, mode"), "{message}");
+
+    // codec would reject the function it has encoded
+    let udf = ScalarUDF::from(PythonUDF::from_code("to_miles", code)?);
+    let message = PyLogicalCodec::default()
+        .with_pickled_tuple_format(true)
+        .with_signer(UdfSigner::new("secret"))
+        .try_encode_udf(&udf, &mut vec![])
+        .unwrap_err()
+        .to_string();
+    assert!(message.contains("can't be signed"), "{message}");
+
+    Ok(())
+}
+
+#[test]
+fn should_reject_pickled_tuple_format_if_it_can_not_be_trusted() {
+    setup_python().expect("python environment to be set");
+    let pickle: Vec<u8> = Python::with_gil(|py| {
+        py.eval(
+            c_str!("__import__('pickle').dumps(('to_miles', None, [], None, 'stable'), protocol=4)"),
+            None,
+            None,
+        )
+        .unwrap()
+        .extract()
+        .unwrap()
+    });
+    let buf = [PICKLED_TUPLE_MAGIC, &pickle].concat();
+
+    let error = PyPhysicalCodec::default()
+        .with_by_value(false)
+        .try_decode_udf("to_miles", &buf)
+        .unwrap_err();
+    assert!(error.to_string().contains("rejected"));
+
+    let error = PyPhysicalCodec::default()
+        .with_signer(UdfSigner::new("secret"))
+        .try_decode_udf("to_miles", &buf)
+        .unwrap_err();
+    assert!(error.to_string().contains("can't be verified"));
+
+    // pickle without the prefix is not decoded as a pickled tuple
+    assert!(PyPhysicalCodec::default().try_decode_udf("to_miles", &pickle).is_err());
+}
@@ -0,0 +1,320 @@
+use ballista_python::catalog::FunctionDefinition;
+use ballista_python::codec::{evict_decoded, PyPhysicalCodec};
+use ballista_python::coroutine::AsyncOptions;
+use ballista_python::setup_python;
+use ballista_python::sql::PythonSessionExt;
+use ballista_python::udf::{CallMode, PythonUDF};
+use datafusion::arrow::array::{AsArray, RecordBatch};
+use datafusion::arrow::datatypes::Float64Type;
+use datafusion::common::Result;
+use datafusion::execution::FunctionRegistry;
+use datafusion::logical_expr::ScalarUDF;
+use datafusion::prelude::SessionContext;
+use datafusion_proto::physical_plan::PhysicalExtensionCodec;
+use pyo3::types::PyAnyMethods;
+use pyo3::{PyObject, Python};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+/// calls function with values 1.0 to 20.0
+async fn call(udf: PythonUDF) -> Result<Vec<RecordBatch>> {
+    let ctx = SessionContext::new();
+    ctx.register_udf(ScalarUDF::from(udf));
+    ctx.sql("select f(cast(value as double)) from generate_series(1, 20)")
+        .await?
+        .collect()
+        .await
+}
+
+fn doubles(batches: &[RecordBatch]) -> Vec<Option<f64>> {
+    batches
+        .iter()
+        .flat_map(|b| b.column(0).as_primitive::<Float64Type>().iter())
+        .collect()
+}
+
+/// `stats` global of the function module
+fn stat(func: &PyObject, name: &str) -> usize {
+    Python::with_gil(|py| {
+        let globals = func.bind(py).getattr("__globals__").unwrap();
+        globals
+            .get_item("stats")
+            .unwrap()
+            .get_item(name)
+            .unwrap()
+            .extract()
+            .unwrap()
+    })
+}
+
+#[tokio::test]
+async fn should_await_calls_concurrently_within_limit() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+import asyncio
+
+stats = {"active": 0, "max_active": 0}
+
+async def f(x):
+    stats["active"] += 1
+    stats["max_active"] = max(stats["max_active"], stats["active"])
+    await asyncio.sleep(0.05)
+    stats["active"] -= 1
+    return x * 2
+"#;
+    let udf = PythonUDF::from_code("f", code)?.with_async_options(AsyncOptions::default().with_concurrency(4));
+    let func = Python::with_gil(|py| udf.func.clone_ref(py));
+
+    let started = Instant::now();
+    let result = call(udf).await?;
+    // 20 calls of 50ms, 4 at a time
+    assert!(started.elapsed() < Duration::from_millis(800));
+    assert_eq!(
+        (1..=20).map(|v| Some(v as f64 * 2.0)).collect::<Vec<_>>(),
+        doubles(&result)
+    );
+    assert_eq!(4, stat(&func, "max_active"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_call_local_service_row_by_row() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    // service doubling numbers, one line per connection
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            std::thread::spawn(move || {
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).unwrap();
+                let value: f64 = line.trim().parse().unwrap();
+                writeln!(&stream, "{}", value * 2.0).unwrap();
+            });
+        }
+    });
+
+    let code = format!(
+        r#"
+import asyncio
+
+async def f(x):
+    reader, writer = await asyncio.open_connection("127.0.0.1", {port})
+    writer.write(f"{{x}}\n".encode())
+    await writer.drain()
+    response = await reader.readline()
+    writer.close()
+    return float(response)
+"#
+    );
+    let udf = PythonUDF::from_code("f", &code)?;
+
+    let result = call(udf).await?;
+    assert_eq!(
+        (1..=20).map(|v| Some(v as f64 * 2.0)).collect::<Vec<_>>(),
+        doubles(&result)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_fail_calls_which_time_out() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+import asyncio
+
+async def f(x):
+    await asyncio.sleep(10)
+    return x
+"#;
+    let udf = PythonUDF::from_code("f", code)?
+        .with_async_options(AsyncOptions::default().with_timeout(Some(Duration::from_millis(50))));
+
+    let started = Instant::now();
+    let error = call(udf).await.unwrap_err();
+    assert!(error.to_string().contains("did not complete"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_cancel_running_calls_once_one_fails() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+import asyncio
+
+stats = {"completed": 0, "cancelled": 0}
+
+async def f(x):
+    if x == 1:
+        raise ValueError("first call failed")
+    try:
+        await asyncio.sleep(0.5)
+    except asyncio.CancelledError:
+        stats["cancelled"] += 1
+        raise
+    stats["completed"] += 1
+    return x
+"#;
+    let udf = PythonUDF::from_code("f", code)?;
+    let func = Python::with_gil(|py| udf.func.clone_ref(py));
+
+    let started = Instant::now();
+    let error = call(udf).await.unwrap_err();
+    assert!(error.to_string().contains("first call failed"), "{error}");
+    assert!(started.elapsed() < Duration::from_millis(500));
+    // cancelled calls do not complete later on
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_eq!(0, stat(&func, "completed"));
+    // 15 running calls, and the one started in place of the failed one
+    assert!(stat(&func, "cancelled") >= 15);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_fail_calls_which_do_not_complete_before_deadline() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+import asyncio
+
+async def f(x):
+    await asyncio.sleep(10)
+    return x
+"#;
+    // there is no timeout of a single call
+    let udf = PythonUDF::from_code("f", code)?
+        .with_async_options(AsyncOptions::default().with_deadline(Duration::from_millis(100)));
+
+    let started = Instant::now();
+    let error = call(udf).await.unwrap_err();
+    assert!(error.to_string().contains("did not complete in 0.1 seconds"), "{error}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_call_function_with_chunks() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+import asyncio
+
+stats = {"calls": 0}
+
+async def f(values):
+    stats["calls"] += 1
+    await asyncio.sleep(0)
+    return values
+"#;
+    let udf = PythonUDF::from_code("f", code)?
+        .with_mode(CallMode::Capsule)
+        .with_async_options(AsyncOptions::default().with_chunk_size(Some(8)));
+    let func = Python::with_gil(|py| udf.func.clone_ref(py));
+
+    let result = call(udf).await?;
+    assert_eq!((1..=20).map(|v| Some(v as f64)).collect::<Vec<_>>(), doubles(&result));
+    assert_eq!(3, stat(&func, "calls"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_create_async_function_with_options() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let ctx = SessionContext::new();
+    ctx.python_sql(
+        "CREATE FUNCTION f(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON \
+         OPTIONS (concurrency '4', timeout '1.5', deadline '30', chunk_size '100') \
+         AS 'async def f(x):\n    return x\n'",
+    )
+    .await?;
+    let udf = ctx.udf("f")?;
+    let expected = AsyncOptions::default()
+        .with_concurrency(4)
+        .with_timeout(Some(Duration::from_millis(1500)))
+        .with_deadline(Duration::from_secs(30))
+        .with_chunk_size(Some(100));
+    let function = udf.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+    assert_eq!(expected, function.async_options);
+
+    let codec = PyPhysicalCodec::default();
+    let mut buf = vec![];
+    codec.try_encode_udf(&udf, &mut buf)?;
+    let decoded = codec.try_decode_udf("f", &buf)?;
+    let decoded = decoded.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+    assert_eq!(expected, decoded.async_options);
+
+    // catalog definition keeps the options
+    let definition = FunctionDefinition::from_udf(function).unwrap();
+    assert!(definition.options.contains(&("deadline".to_string(), "30".to_string())));
+    let other_ctx = SessionContext::new();
+    other_ctx.python_sql(&definition.to_sql()?).await?;
+    let created = other_ctx.udf("f")?;
+    let created = created.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+    assert_eq!(expected, created.async_options);
+
+    assert!(ctx
+        .python_sql(
+            "CREATE FUNCTION g(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON OPTIONS (timeout '-1') \
+             AS 'async def g(x):\n    return x\n'",
+        )
+        .await
+        .is_err());
+
+    Ok(())
+}
+
+/// keys of semaphores of the coroutine module
+fn semaphore_keys() -> Vec<String> {
+    Python::with_gil(|py| {
+        py.import("sys")
+            .unwrap()
+            .getattr("modules")
+            .unwrap()
+            .get_item("ballista_python_coroutine")
+            .unwrap()
+            .getattr("_semaphores")
+            .unwrap()
+            .call_method0("keys")
+            .unwrap()
+            .try_iter()
+            .unwrap()
+            .map(|k| k.unwrap().extract().unwrap())
+            .collect()
+    })
+}
+
+#[tokio::test]
+async fn should_key_semaphores_by_digest_and_evict_them() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = "async def evicted_f(x):\n    return x\n";
+    let udf = ScalarUDF::from(PythonUDF::from_code("evicted_f", code)?);
+    let codec = PyPhysicalCodec::default();
+    let mut buf = vec![];
+    codec.try_encode_udf(&udf, &mut buf)?;
+    let decoded = codec.try_decode_udf("evicted_f", &buf)?;
+    let digest = decoded
+        .inner()
+        .as_any()
+        .downcast_ref::<PythonUDF>()
+        .unwrap()
+        .digest
+        .clone()
+        .unwrap();
+
+    let ctx = SessionContext::new();
+    ctx.register_udf(decoded.as_ref().clone());
+    ctx.sql("select evicted_f(1.0)").await?.collect().await?;
+    assert!(semaphore_keys().contains(&digest));
+
+    // semaphore is dropped with the decoded function
+    evict_decoded("evicted_f");
+    assert!(!semaphore_keys().contains(&digest));
+
+    Ok(())
+}
@@ -1,6 +1,11 @@
 #[cfg(test)]
 mod test {
 
+    use datafusion::arrow::datatypes::DataType;
+    use datafusion::execution::context::{FunctionFactory, RegisterFunction};
+    use datafusion::execution::{FunctionRegistry, SessionState};
+    use datafusion::logical_expr::Volatility;
+    use datafusion::logical_expr::{create_udf, ColumnarValue, CreateFunction};
     use datafusion::{
         assert_batches_eq,
         execution::SessionStateBuilder,
@@ -12,13 +17,29 @@ mod test {
         logical_plan_from_bytes_with_extension_codec, logical_plan_to_bytes_with_extension_codec,
         physical_plan_from_bytes_with_extension_codec, physical_plan_to_bytes_with_extension_codec,
     };
+    use datafusion_proto::logical_plan::LogicalExtensionCodec;
+    use datafusion_proto::physical_plan::PhysicalExtensionCodec;
+    use prost::Message;
+    use pyo3::types::{PyAnyMethods, PyModule};
+    use pyo3::Python;
+    use std::str::FromStr;
     use std::sync::Arc;
+    use std::time::Duration;
 
     use ballista_python::{
-        codec::{PyLogicalCodec, PyPhysicalCodec},
-        factory::PythonFunctionFactory,
+        codec::{
+            evict_decoded,
+            serde::{SignedUdfProto, UdfProto},
+            PyLogicalCodec, PyPhysicalCodec,
+        },
+        factory::{FunctionOptions, LanguageHandler, PythonFunctionFactory},
+        pickle::{PickleSerializer, FORMAT_PICKLE},
+        registry::{FunctionReference, PyFunctionRegistry},
+        requirements::PythonRequirement,
         setup_python_path,
-        udf::PythonUDF,
+        signing::UdfSigner,
+        sql::PythonSessionExt,
+        udf::{CallMode, PythonSource, PythonUDF},
     };
 
     #[tokio::test]
@@ -128,7 +149,7 @@ def to_miles(km_data):
         let result = df.select(vec![col("a"), udf.call(vec![col("a")])])?;
 
         let plan = result.logical_plan();
-        let bytes = logical_plan_to_bytes_with_extension_codec(&plan, &codec)?;
+        let bytes = logical_plan_to_bytes_with_extension_codec(plan, &codec)?;
         let new_plan = logical_plan_from_bytes_with_extension_codec(&bytes, &ctx, &codec)?;
 
         assert_eq!(plan, &new_plan);
@@ -167,6 +188,582 @@ def to_miles(km_data):
         Ok(())
     }
 
+    #[tokio::test]
+    async fn should_round_trip_signed_logical_plan() -> datafusion::error::Result<()> {
+        let ctx = context();
+        let codec = PyLogicalCodec::default().with_signer(UdfSigner::new("secret"));
+
+        let udf = PythonUDF::from_code("to_miles", TO_MILES).expect("udf created");
+        let udf = ScalarUDF::from(udf);
+
+        let df = ctx.sql("select unnest([1, 2, 3, 4, 5, 6, 7, 8, 9, 0]) as a").await?;
+        let result = df.select(vec![col("a"), udf.call(vec![col("a")])])?;
+
+        let plan = result.logical_plan();
+        let bytes = logical_plan_to_bytes_with_extension_codec(plan, &codec)?;
+        let new_plan = logical_plan_from_bytes_with_extension_codec(&bytes, &ctx, &codec)?;
+
+        assert_eq!(plan, &new_plan);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_reject_unsigned_or_wrongly_signed_function() -> datafusion::error::Result<()> {
+        let ctx = context();
+        let verifying_codec = PyLogicalCodec::default().with_signer(UdfSigner::new("secret"));
+
+        let udf = PythonUDF::from_code("to_miles", TO_MILES).expect("udf created");
+        let udf = ScalarUDF::from(udf);
+
+        let df = ctx.sql("select unnest([1, 2, 3, 4, 5, 6, 7, 8, 9, 0]) as a").await?;
+        let result = df.select(vec![col("a"), udf.call(vec![col("a")])])?;
+        let plan = result.logical_plan();
+
+        let unsigned_bytes = logical_plan_to_bytes_with_extension_codec(plan, &PyLogicalCodec::default())?;
+        assert!(logical_plan_from_bytes_with_extension_codec(&unsigned_bytes, &ctx, &verifying_codec).is_err());
+
+        let other_codec = PyLogicalCodec::default().with_signer(UdfSigner::new("other secret"));
+        let wrongly_signed_bytes = logical_plan_to_bytes_with_extension_codec(plan, &other_codec)?;
+        assert!(logical_plan_from_bytes_with_extension_codec(&wrongly_signed_bytes, &ctx, &verifying_codec).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_verify_signature_over_received_payload() -> datafusion::error::Result<()> {
+        setup_python_path().expect("python path to be set");
+        let signer = UdfSigner::new("secret");
+        let codec = PyPhysicalCodec::default().with_signer(signer.clone());
+        let udf = ScalarUDF::from(PythonUDF::from_code("to_miles", TO_MILES)?.with_mode(CallMode::Capsule));
+
+        let mut buf = vec![];
+        codec.try_encode_udf(&udf, &mut buf)?;
+        let mut signed = SignedUdfProto::decode(buf.as_slice()).expect("envelope decoded");
+        // field unknown to this version is kept in signed payload
+        signed.payload.extend_from_slice(&[0xf8, 0x06, 0x01]);
+        signed.signature = signer.sign("to_miles", &signed.payload);
+        let decoded = codec.try_decode_udf("to_miles", &signed.encode_to_vec())?;
+        assert_eq!("to_miles", decoded.name());
+
+        // any change of payload bytes fails verification
+        *signed.payload.last_mut().unwrap() = 0x02;
+        assert!(codec.try_decode_udf("to_miles", &signed.encode_to_vec()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_ship_function_with_source_as_source() -> datafusion::error::Result<()> {
+        setup_python_path().expect("python path to be set");
+        let codec = PyLogicalCodec::default();
+
+        let udf = PythonUDF::from_code("to_miles", TO_MILES).expect("udf created");
+        let udf = ScalarUDF::from(udf);
+
+        let mut buf = vec![];
+        codec.try_encode_udf(&udf, &mut buf)?;
+
+        let proto = udf_proto(&buf);
+        assert!(proto.blob.is_empty());
+        assert_eq!("to_miles", proto.source.expect("source to be shipped").entry_point);
+
+        let decoded = codec.try_decode_udf("to_miles", &buf)?;
+        let decoded = decoded.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+        assert_eq!(Some(PythonSource::new(TO_MILES, "to_miles")), decoded.source);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_decode_function_again_when_it_is_replaced() -> datafusion::error::Result<()> {
+        setup_python_path().expect("python path to be set");
+        let codec = PyPhysicalCodec::default();
+
+        let mut buf = vec![];
+        let udf = ScalarUDF::from(PythonUDF::from_code("to_miles", TO_MILES).expect("udf created"));
+        codec.try_encode_udf(&udf, &mut buf)?;
+
+        let decoded = codec.try_decode_udf("to_miles", &buf)?;
+        assert!(Arc::ptr_eq(&decoded, &codec.try_decode_udf("to_miles", &buf)?));
+
+        let replaced_code = "def to_miles(km_data):\n    return km_data\n";
+        let mut replaced_buf = vec![];
+        let replaced = ScalarUDF::from(PythonUDF::from_code("to_miles", replaced_code).expect("udf created"));
+        codec.try_encode_udf(&replaced, &mut replaced_buf)?;
+
+        let replaced = codec.try_decode_udf("to_miles", &replaced_buf)?;
+        let source = replaced
+            .inner()
+            .as_any()
+            .downcast_ref::<PythonUDF>()
+            .unwrap()
+            .source
+            .clone();
+        assert_eq!(Some(PythonSource::new(replaced_code, "to_miles")), source);
+        assert!(Arc::ptr_eq(
+            &replaced,
+            &codec.try_decode_udf("to_miles", &replaced_buf)?
+        ));
+
+        assert!(codec.invalidate("to_miles"));
+        assert!(!codec.invalidate("to_miles"));
+        assert!(!Arc::ptr_eq(
+            &replaced,
+            &codec.try_decode_udf("to_miles", &replaced_buf)?
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_evict_least_recently_used_and_dropped_functions() -> datafusion::error::Result<()> {
+        setup_python_path().expect("python path to be set");
+        let codec = PyPhysicalCodec::default().with_decode_cache_size(2);
+        let encode = |name: &str| -> datafusion::error::Result<Vec<u8>> {
+            let code = format!("def {name}(values):\n    return values\n");
+            let mut buf = vec![];
+            let udf = ScalarUDF::from(PythonUDF::from_code(name, &code)?);
+            codec.try_encode_udf(&udf, &mut buf)?;
+            Ok(buf)
+        };
+        let (first, second, third) = (encode("lru_first")?, encode("lru_second")?, encode("lru_third")?);
+
+        let decoded_first = codec.try_decode_udf("lru_first", &first)?;
+        let decoded_second = codec.try_decode_udf("lru_second", &second)?;
+        assert!(Arc::ptr_eq(&decoded_first, &codec.try_decode_udf("lru_first", &first)?));
+        // second function is least recently used one
+        codec.try_decode_udf("lru_third", &third)?;
+        assert!(Arc::ptr_eq(&decoded_first, &codec.try_decode_udf("lru_first", &first)?));
+        assert!(!Arc::ptr_eq(
+            &decoded_second,
+            &codec.try_decode_udf("lru_second", &second)?
+        ));
+
+        // dropped function is evicted from all codecs
+        assert!(evict_decoded("lru_first"));
+        assert!(!Arc::ptr_eq(
+            &decoded_first,
+            &codec.try_decode_udf("lru_first", &first)?
+        ));
+
+        // functions not used for ttl are evicted
+        let codec = PyPhysicalCodec::default().with_decode_cache_ttl(Duration::ZERO);
+        let decoded = codec.try_decode_udf("lru_first", &first)?;
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(!Arc::ptr_eq(&decoded, &codec.try_decode_udf("lru_first", &first)?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_compile_source_when_function_can_not_be_unpickled() -> datafusion::error::Result<()> {
+        setup_python_path().expect("python path to be set");
+        let codec = PyLogicalCodec::default().with_source_fallback(true);
+
+        let udf = PythonUDF::from_code("to_miles", TO_MILES).expect("udf created");
+        let udf = ScalarUDF::from(udf);
+
+        let mut buf = vec![];
+        codec.try_encode_udf(&udf, &mut buf)?;
+
+        let mut proto = udf_proto(&buf);
+        assert!(!proto.blob.is_empty());
+        assert!(proto.source.is_some());
+
+        // function pickled with incompatible python version
+        proto.blob = b"not a pickle".to_vec();
+        let decoded = codec.try_decode_udf("to_miles", &unsigned(&proto))?;
+        assert_eq!("to_miles", decoded.name());
+
+        proto.source = None;
+        assert!(codec.try_decode_udf("to_miles", &unsigned(&proto)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_unpickle_function_pickled_with_different_serializer() -> datafusion::error::Result<()> {
+        setup_python_path().expect("python path to be set");
+        let pickle = Python::with_gil(PickleSerializer::std_pickle).expect("serializer created");
+        let pickle_codec = PyPhysicalCodec::default().with_serializer(Arc::new(pickle));
+
+        // function without source is pickled, by reference,
+        // so it has to be importable where it is unpickled
+        let func = Python::with_gil(|py| {
+            let code = c"def to_miles(km_data):\n    return km_data\n";
+            PyModule::from_code(py, code, c"pickled_udfs.py", c"pickled_udfs")?
+                .getattr("to_miles")
+                .map(|f| f.unbind())
+        })
+        .expect("module created");
+        let udf = PythonUDF::new(
+            "to_miles",
+            vec![DataType::Float64],
+            DataType::Float64,
+            Volatility::Immutable,
+            func,
+        );
+        let udf = ScalarUDF::from(udf);
+
+        let mut buf = vec![];
+        pickle_codec.try_encode_udf(&udf, &mut buf)?;
+
+        let proto = udf_proto(&buf);
+        assert_eq!(FORMAT_PICKLE, proto.format);
+
+        let decoded = PyPhysicalCodec::default().try_decode_udf("to_miles", &buf)?;
+        assert_eq!("to_miles", decoded.name());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_not_pickle_compiled_function_with_other_serializer() -> datafusion::error::Result<()> {
+        setup_python_path().expect("python path to be set");
+        let pickle = Python::with_gil(PickleSerializer::std_pickle).expect("serializer created");
+        let codec = PyLogicalCodec::default()
+            .with_serializer(Arc::new(pickle))
+            .with_source_fallback(true);
+
+        // module of compiled function can't be imported at the executor
+        let udf = PythonUDF::from_code("to_miles", TO_MILES).expect("udf created");
+        let udf = PythonUDF::new(
+            "to_miles",
+            udf.input_types,
+            udf.return_type,
+            Volatility::Immutable,
+            udf.func,
+        );
+        let message = codec
+            .try_encode_udf(&ScalarUDF::from(udf), &mut vec![])
+            .unwrap_err()
+            .to_string();
+        assert!(
+            message.contains("can't be pickled with serializer: pickle"),
+            "{message}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_compile_functions_with_the_same_source_in_own_modules() -> datafusion::error::Result<()> {
+        setup_python_path().expect("python path to be set");
+        let code = "counter = 0\ndef count():\n    global counter\n    counter += 1\n    return counter\n";
+        let first = PythonUDF::from_code_with_entry_point("first", code, "count", vec![], DataType::Int64)?;
+        let second = PythonUDF::from_code_with_entry_point("second", code, "count", vec![], DataType::Int64)?;
+        let source = first.source.as_ref().unwrap();
+        assert_ne!(source.module_name("first"), source.module_name("second"));
+
+        // functions do not share globals
+        Python::with_gil(|py| {
+            assert_eq!(1, first.func.call0(py).unwrap().extract::<i64>(py).unwrap());
+            assert_eq!(2, first.func.call0(py).unwrap().extract::<i64>(py).unwrap());
+            assert_eq!(1, second.func.call0(py).unwrap().extract::<i64>(py).unwrap());
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_resolve_function_shipped_by_reference() -> datafusion::error::Result<()> {
+        setup_python_path().expect("python path to be set");
+        Python::with_gil(|py| {
+            // module pre-installed at executor
+            let code = c"__version__ = '1.0'\ndef to_miles(km_data):\n    return km_data\n";
+            PyModule::from_code(py, code, c"vetted_udfs.py", c"vetted_udfs").map(|_| ())
+        })
+        .expect("module created");
+
+        let reference = FunctionReference::from_str("vetted_udfs:to_miles@1.0")?;
+        let udf = PythonUDF::from_reference(
+            "to_miles",
+            reference,
+            vec![DataType::Float64],
+            DataType::Float64,
+            Volatility::Immutable,
+        )?;
+        let udf = ScalarUDF::from(udf);
+
+        let mut buf = vec![];
+        PyLogicalCodec::default().try_encode_udf(&udf, &mut buf)?;
+
+        let proto = udf_proto(&buf);
+        assert!(proto.blob.is_empty());
+        assert!(proto.source.is_none());
+        assert_eq!("vetted_udfs:to_miles@1.0", proto.reference);
+
+        let codec = PyPhysicalCodec::default()
+            .with_function_registry(PyFunctionRegistry::new(["vetted_udfs"]))
+            .with_by_value(false);
+        let decoded = codec.try_decode_udf("to_miles", &buf)?;
+        assert_eq!("to_miles", decoded.name());
+
+        // registry not configured
+        assert!(PyPhysicalCodec::default().try_decode_udf("to_miles", &buf).is_err());
+
+        // module not registered
+        let other_codec = PyPhysicalCodec::default().with_function_registry(PyFunctionRegistry::new(["other_udfs"]));
+        assert!(other_codec.try_decode_udf("to_miles", &buf).is_err());
+
+        // version not installed
+        let mut other_version = proto.clone();
+        other_version.reference = "vetted_udfs:to_miles@2.0".to_string();
+        assert!(codec.try_decode_udf("to_miles", &unsigned(&other_version)).is_err());
+
+        // function shipped by value
+        let udf = ScalarUDF::from(PythonUDF::from_code("to_miles", TO_MILES)?);
+        let mut buf = vec![];
+        PyLogicalCodec::default().try_encode_udf(&udf, &mut buf)?;
+        assert!(codec.try_decode_udf("to_miles", &buf).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_check_function_requirements_on_decode() -> datafusion::error::Result<()> {
+        setup_python_path().expect("python path to be set");
+        let udf = PythonUDF::from_code("to_miles", TO_MILES)?
+            .with_requirements(PythonRequirement::parse_list("pip; package_which_does_not_exist>=1.0")?);
+        let udf = ScalarUDF::from(udf);
+
+        let mut buf = vec![];
+        PyLogicalCodec::default().try_encode_udf(&udf, &mut buf)?;
+
+        let proto = udf_proto(&buf);
+        assert_eq!(vec!["pip", "package_which_does_not_exist>=1.0"], proto.requirements);
+
+        let message = PyPhysicalCodec::default()
+            .try_decode_udf("to_miles", &buf)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("package_which_does_not_exist>=1.0"));
+        assert!(!message.contains("pip,"));
+
+        let mut satisfied = proto.clone();
+        satisfied.requirements = vec!["pip".to_string()];
+        let decoded = PyPhysicalCodec::default().try_decode_udf("to_miles", &unsigned(&satisfied))?;
+        assert_eq!("to_miles", decoded.name());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_create_function_with_options() -> datafusion::error::Result<()> {
+        let ctx = context();
+
+        let sql = r#"
+CREATE FUNCTION to_miles(DOUBLE)
+RETURNS DOUBLE
+LANGUAGE PYTHON
+OPTIONS (requirements 'package_which_does_not_exist')
+AS '
+import pyarrow.compute as pc
+conversation_rate_multiplier = 0.62137119
+def to_miles(km_data):
+    return pc.multiply(km_data, conversation_rate_multiplier)
+'
+"#;
+
+        ctx.python_sql(sql).await?;
+
+        let udf = ctx.udf("to_miles")?;
+        let mut buf = vec![];
+        PyLogicalCodec::default().try_encode_udf(&udf, &mut buf)?;
+        let proto = udf_proto(&buf);
+        assert_eq!(vec!["package_which_does_not_exist"], proto.requirements);
+
+        // unknown options are rejected
+        let sql = sql.replace("requirements", "unknown_option");
+        assert!(ctx.python_sql(&sql).await.is_err());
+
+        Ok(())
+    }
+
+    /// handler creating identity function, checking statement options
+    #[derive(Debug)]
+    struct IdentityHandler {}
+
+    #[async_trait::async_trait]
+    impl LanguageHandler for IdentityHandler {
+        async fn create(
+            &self,
+            _state: &SessionState,
+            statement: CreateFunction,
+            options: &FunctionOptions,
+        ) -> datafusion::error::Result<RegisterFunction> {
+            assert_eq!(Some("value"), options.get("key"));
+            let udf = create_udf(
+                &statement.name,
+                vec![DataType::Int64],
+                DataType::Int64,
+                Volatility::Immutable,
+                Arc::new(|args: &[ColumnarValue]| Ok(args[0].clone())),
+            );
+            Ok(RegisterFunction::Scalar(Arc::new(udf)))
+        }
+    }
+
+    /// function factory which is not python function factory
+    #[derive(Debug)]
+    struct OtherFactory {}
+
+    #[async_trait::async_trait]
+    impl FunctionFactory for OtherFactory {
+        async fn create(
+            &self,
+            state: &SessionState,
+            statement: CreateFunction,
+        ) -> datafusion::error::Result<RegisterFunction> {
+            IdentityHandler {}
+                .create(
+                    state,
+                    statement,
+                    &FunctionOptions::new([("key".into(), "value".into())]),
+                )
+                .await
+        }
+    }
+
+    #[tokio::test]
+    async fn should_create_function_with_installed_function_factory() -> datafusion::error::Result<()> {
+        let factory = PythonFunctionFactory::default().with_handler("identity", Arc::new(IdentityHandler {}));
+        let ctx = SessionContext::new().with_function_factory(Arc::new(factory));
+
+        let sql = "CREATE FUNCTION same(BIGINT) RETURNS BIGINT LANGUAGE IDENTITY OPTIONS (key 'value') AS 'x'";
+        ctx.python_sql(sql).await?;
+        let result = ctx.sql("select same(42) as a").await?.collect().await?;
+        let expected = ["+----+", "| a  |", "+----+", "| 42 |", "+----+"];
+        assert_batches_eq!(expected, &result);
+
+        // options can't be passed to other function factory
+        let ctx = SessionContext::new().with_function_factory(Arc::new(OtherFactory {}));
+        let message = ctx.python_sql(sql).await.unwrap_err().to_string();
+        assert!(message.contains("does not support OPTIONS"), "{message}");
+        ctx.python_sql("CREATE FUNCTION same(BIGINT) RETURNS BIGINT LANGUAGE IDENTITY AS 'x'")
+            .await?;
+        assert!(ctx.udf("same").is_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_create_function_with_handler_and_null_behavior() -> datafusion::error::Result<()> {
+        let ctx = context();
+
+        let sql = r#"
+CREATE FUNCTION km_to_miles(DOUBLE)
+RETURNS DOUBLE
+LANGUAGE PYTHON
+IMMUTABLE
+RETURNS NULL ON NULL INPUT
+OPTIONS (handler 'to_miles')
+AS '
+import pyarrow.compute as pc
+def to_miles(km_data):
+    assert km_data.null_count == 0
+    return pc.multiply(km_data, 0.62137119)
+'
+"#;
+
+        ctx.python_sql(sql).await?;
+        assert_eq!(Volatility::Immutable, ctx.udf("km_to_miles")?.signature().volatility);
+
+        let result = ctx
+            .sql("select a, km_to_miles(a) as m from (select unnest([1.0, null, 2.0]) as a)")
+            .await?
+            .collect()
+            .await?;
+        let expected = [
+            "+-----+------------+",
+            "| a   | m          |",
+            "+-----+------------+",
+            "| 1.0 | 0.62137119 |",
+            "|     |            |",
+            "| 2.0 | 1.24274238 |",
+            "+-----+------------+",
+        ];
+        assert_batches_eq!(expected, &result);
+
+        // immutable function with constant arguments is evaluated at planning
+        let plan = ctx.sql("select km_to_miles(1.0)").await?.into_optimized_plan()?;
+        assert!(!format!("{plan}").contains("km_to_miles(Float64"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_call_function_in_pandas_mode() -> datafusion::error::Result<()> {
+        let ctx = context();
+
+        let sql = r#"
+CREATE FUNCTION to_miles(DOUBLE)
+RETURNS DOUBLE
+LANGUAGE PYTHON
+OPTIONS (mode 'pandas')
+AS '
+def to_miles(km_data):
+    return km_data * 0.62137119
+'
+"#;
+
+        ctx.python_sql(sql).await?;
+
+        let result = ctx
+            .sql("select to_miles(a) as m from (select unnest([1.0, 2.0]) as a)")
+            .await?
+            .collect()
+            .await?;
+        let expected = [
+            "+------------+",
+            "| m          |",
+            "+------------+",
+            "| 0.62137119 |",
+            "| 1.24274238 |",
+            "+------------+",
+        ];
+        assert_batches_eq!(expected, &result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_round_trip_null_behavior_and_mode() -> datafusion::error::Result<()> {
+        setup_python_path().expect("python path to be set");
+        let code = "def to_miles(km_data):\n    return km_data\n";
+        let udf = PythonUDF::from_code_with_entry_point("km_to_miles", code, "to_miles", vec![], DataType::Float64)?
+            .with_volatility(Volatility::Immutable)
+            .with_strict(true)
+            .with_mode(CallMode::Pandas);
+        let udf = ScalarUDF::from(udf);
+
+        let mut buf = vec![];
+        PyLogicalCodec::default().try_encode_udf(&udf, &mut buf)?;
+        let decoded = PyPhysicalCodec::default().try_decode_udf("km_to_miles", &buf)?;
+        let decoded = decoded
+            .inner()
+            .as_any()
+            .downcast_ref::<PythonUDF>()
+            .expect("python function");
+
+        assert!(decoded.strict);
+        assert_eq!(CallMode::Pandas, decoded.mode);
+        assert_eq!(Volatility::Immutable, decoded.signature.volatility);
+        assert_eq!("to_miles", decoded.source.as_ref().unwrap().entry_point);
+
+        Ok(())
+    }
+
+    static TO_MILES: &str = r#"
+import pyarrow.compute as pc
+
+conversation_rate_multiplier = 0.62137119
+
+def to_miles(km_data):
+    return pc.multiply(km_data, conversation_rate_multiplier)
+"#;
+
     fn context() -> SessionContext {
         setup_python_path().expect("python path to be set");
         let state = SessionStateBuilder::new()
@@ -176,4 +773,19 @@ def to_miles(km_data):
 
         SessionContext::new_with_state(state)
     }
+
+    /// function proto carried in encoded function
+    fn udf_proto(buf: &[u8]) -> UdfProto {
+        let signed = SignedUdfProto::decode(buf).expect("envelope decoded");
+        UdfProto::decode(signed.payload.as_slice()).expect("proto decoded")
+    }
+
+    /// encoded function carrying unsigned proto
+    fn unsigned(proto: &UdfProto) -> Vec<u8> {
+        SignedUdfProto {
+            payload: proto.encode_to_vec(),
+            signature: vec![],
+        }
+        .encode_to_vec()
+    }
 }
@@ -0,0 +1,57 @@
+mod common;
+
+use ballista_python::{env::PythonEnvConfig, setup_python_with};
+use common::temp_dir;
+use pyo3::types::PyAnyMethods;
+use pyo3::Python;
+use std::path::PathBuf;
+
+#[test]
+fn should_add_venv_and_extra_paths() {
+    let root = temp_dir("should_add_venv_and_extra_paths");
+    let extra_path = root.join("extra");
+    std::fs::create_dir_all(&extra_path).unwrap();
+
+    let venv = root.join("venv");
+    let (major, minor) = Python::with_gil(|py| (py.version_info().major, py.version_info().minor));
+    let site_packages = venv
+        .join("lib")
+        .join(format!("python{major}.{minor}"))
+        .join("site-packages");
+    std::fs::create_dir_all(&site_packages).unwrap();
+
+    let config = PythonEnvConfig::new().with_venv(&venv).with_extra_path(&extra_path);
+    setup_python_with(&config).expect("python environment to be set");
+    // applying configuration again does not duplicate entries
+    setup_python_with(&config).expect("python environment to be set");
+
+    Python::with_gil(|py| {
+        // entries have to be strings to be used by importers
+        let path: Vec<String> = py.import("sys").unwrap().getattr("path").unwrap().extract().unwrap();
+        let path: Vec<PathBuf> = path.into_iter().map(PathBuf::from).collect();
+
+        assert!(path.contains(&site_packages));
+        assert_eq!(1, path.iter().filter(|p| *p == &extra_path).count());
+    });
+}
+
+#[test]
+fn should_reject_missing_paths_and_ignore_missing_site_packages() {
+    let root = temp_dir("should_reject_missing_paths");
+
+    assert!(PythonEnvConfig::new()
+        .with_venv(root.join("missing"))
+        .validate()
+        .is_err());
+    assert!(PythonEnvConfig::new()
+        .with_extra_path(root.join("missing"))
+        .validate()
+        .is_err());
+    assert!(PythonEnvConfig::new()
+        .with_python_home(root.join("missing"))
+        .validate()
+        .is_err());
+
+    // venv without site-packages of the running interpreter is not used
+    assert!(setup_python_with(&PythonEnvConfig::new().with_venv(root.path())).is_ok());
+}
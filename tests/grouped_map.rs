@@ -0,0 +1,225 @@
+use ballista_python::codec::{PyLogicalCodec, PyPhysicalCodec};
+use ballista_python::grouped_map::{ApplyPythonExec, ApplyPythonNode, GroupedMapExt};
+use ballista_python::map_batches::{MapBatchesFunction, PythonQueryPlanner};
+use ballista_python::setup_python;
+use ballista_python::udf::{CallMode, PythonUDF};
+use datafusion::arrow::array::{AsArray, Int32Array, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Int64Type, Schema, SchemaRef};
+use datafusion::common::Result;
+use datafusion::datasource::MemTable;
+use datafusion::execution::SessionStateBuilder;
+use datafusion::logical_expr::{col, Extension, LogicalPlan};
+use datafusion::physical_plan::{collect, displayable};
+use datafusion::prelude::{lit, SessionConfig, SessionContext};
+use datafusion_proto::logical_plan::LogicalExtensionCodec;
+use datafusion_proto::physical_plan::PhysicalExtensionCodec;
+use pyo3::types::PyAnyMethods;
+use pyo3::{PyObject, Python};
+use std::sync::Arc;
+
+fn context() -> Result<SessionContext> {
+    let state = SessionStateBuilder::new()
+        .with_config(SessionConfig::new().with_target_partitions(3))
+        .with_default_features()
+        .with_query_planner(Arc::new(PythonQueryPlanner::default()))
+        .build();
+    let ctx = SessionContext::new_with_state(state);
+    // rows of each group are spread across two partitions
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("k", DataType::Int32, false),
+        Field::new("a", DataType::Int32, false),
+    ]));
+    let partitions = [(vec![1, 2, 1], vec![1, 2, 3]), (vec![2, 3, 1], vec![4, 5, 6])]
+        .into_iter()
+        .map(|(keys, values)| {
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from(keys)), Arc::new(Int32Array::from(values))],
+            )
+            .map(|batch| vec![batch])
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    ctx.register_table("t", Arc::new(MemTable::try_new(schema, partitions)?))?;
+
+    Ok(ctx)
+}
+
+/// output schema of test functions, columns are cast to declared types
+fn output_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("k", DataType::Int64, false),
+        Field::new("a", DataType::Int64, false),
+    ]))
+}
+
+fn function(name: &str, code: &str) -> Result<MapBatchesFunction> {
+    let udf = PythonUDF::from_code_with_types(name, code, vec![], DataType::Null)?.with_mode(CallMode::Capsule);
+
+    Ok(MapBatchesFunction::from_udf(udf, output_schema()))
+}
+
+/// sorted `(k, a)` rows
+fn rows(batches: &[RecordBatch]) -> Vec<(i64, i64)> {
+    let mut rows = batches
+        .iter()
+        .flat_map(|b| {
+            let keys = b.column(0).as_primitive::<Int64Type>().values().to_vec();
+            let values = b.column(1).as_primitive::<Int64Type>().values().to_vec();
+            keys.into_iter().zip(values)
+        })
+        .collect::<Vec<_>>();
+    rows.sort();
+    rows
+}
+
+/// `groups` global of the function module, sorted
+fn groups(function: &MapBatchesFunction) -> Vec<(Vec<i64>, usize)> {
+    let func: &PyObject = &function.python_udf().func;
+    Python::with_gil(|py| {
+        let globals = func.bind(py).getattr("__globals__").unwrap();
+        let mut groups: Vec<(Vec<i64>, usize)> = globals.get_item("groups").unwrap().extract().unwrap();
+        groups.sort();
+        groups
+    })
+}
+
+#[tokio::test]
+async fn should_apply_function_to_each_group() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+groups = []
+
+def count(key, group):
+    groups.append((list(key), len(group)))
+    return group
+"#;
+    let ctx = context()?;
+    let function = function("count", code)?;
+
+    let df = ctx
+        .table("t")
+        .await?
+        .group_by(vec![col("k")])
+        .apply_python(function.clone())?;
+    assert_eq!(output_schema().as_ref(), df.schema().as_arrow());
+    let result = df.collect().await?;
+    assert_eq!(vec![(1, 1), (1, 3), (1, 6), (2, 2), (2, 4), (3, 5)], rows(&result));
+    // each group is passed once, with all its rows
+    assert_eq!(vec![(vec![1], 3), (vec![2], 2), (vec![3], 1)], groups(&function));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_apply_function_without_keys_argument() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def repeated(group):
+    return group if len(group) > 1 else None
+"#;
+    let ctx = context()?;
+
+    let result = ctx
+        .table("t")
+        .await?
+        .group_by(vec![col("k")])
+        .apply_python(function("repeated", code)?)?
+        .collect()
+        .await?;
+    assert_eq!(vec![(1, 1), (1, 3), (1, 6), (2, 2), (2, 4)], rows(&result));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_plan_groups_with_repartition_and_sort() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def identity(group):
+    return group
+"#;
+    let ctx = context()?;
+
+    let plan = ctx
+        .table("t")
+        .await?
+        .group_by(vec![col("k"), col("a") % lit(2)])
+        .apply_python(function("identity", code)?)?
+        .create_physical_plan()
+        .await?;
+    let displayed = displayable(plan.as_ref()).indent(true).to_string();
+    assert!(displayed.contains("ApplyPythonExec: function=identity"));
+    assert!(displayed.contains("SortExec"));
+    assert!(displayed.contains("RepartitionExec: partitioning=Hash"));
+
+    assert!(ctx
+        .table("t")
+        .await?
+        .group_by(vec![])
+        .apply_python(function("identity", code)?)
+        .is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_fail_query_if_group_function_fails() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def fail(key, group):
+    raise ValueError(f"bad group {key}")
+"#;
+    let ctx = context()?;
+
+    let error = ctx
+        .table("t")
+        .await?
+        .group_by(vec![col("k")])
+        .apply_python(function("fail", code)?)?
+        .collect()
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("python function: fail failed"));
+    assert!(error.to_string().contains("bad group"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_round_trip_apply_python_plans() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def identity(key, group):
+    return group
+"#;
+    let ctx = context()?;
+    let function = function("identity", code)?;
+    let input = ctx.table("t").await?;
+
+    let node = ApplyPythonNode::try_new(input.logical_plan().clone(), vec![col("k")], function.clone())?;
+    let extension = Extension { node: Arc::new(node) };
+    let codec = PyLogicalCodec::default();
+    let mut buf = vec![];
+    codec.try_encode(&extension, &mut buf)?;
+    let decoded = codec.try_decode(&buf, &[input.logical_plan().clone()], &ctx)?;
+    assert_eq!(extension, decoded);
+
+    let plan = ctx
+        .state()
+        .create_physical_plan(&LogicalPlan::Extension(extension))
+        .await?;
+    let exec = plan.as_any().downcast_ref::<ApplyPythonExec>().unwrap();
+    let codec = PyPhysicalCodec::default();
+    let mut buf = vec![];
+    codec.try_encode(plan.clone(), &mut buf)?;
+    let decoded = codec.try_decode(&buf, &[exec.input().clone()], &ctx)?;
+    let decoded_exec = decoded.as_any().downcast_ref::<ApplyPythonExec>().unwrap();
+    assert_eq!(&function, decoded_exec.function());
+    assert_eq!(exec.keys().len(), decoded_exec.keys().len());
+    assert_eq!(exec.keys()[0].to_string(), decoded_exec.keys()[0].to_string());
+
+    let result = collect(decoded, ctx.task_ctx()).await?;
+    assert_eq!(vec![(1, 1), (1, 3), (1, 6), (2, 2), (2, 4), (3, 5)], rows(&result));
+
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use ballista_python::setup_python;
+use ballista_python::sql::PythonSessionExt;
+use ballista_python::udf::PythonUDF;
+use datafusion::arrow::array::{AsArray, RecordBatch};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::util::pretty::pretty_format_batches;
+use datafusion::common::Result;
+use datafusion::logical_expr::{ScalarUDF, Volatility};
+use datafusion::prelude::{SessionConfig, SessionContext};
+use pyo3::types::{PyAnyMethods, PyModule};
+use pyo3::Python;
+
+const CREATE_FUNCTION: &str =
+    "CREATE FUNCTION km_to_miles(km DOUBLE, INT) RETURNS DOUBLE LANGUAGE PYTHON IMMUTABLE STRICT \
+     OPTIONS (handler 'to_miles', mode 'pandas') AS 'def to_miles(km, n):\n    return km * 0.62\n'";
+
+fn context() -> SessionContext {
+    SessionContext::new_with_config(SessionConfig::new().with_information_schema(true))
+}
+
+fn strings(batches: &[RecordBatch], column: usize) -> Vec<String> {
+    batches
+        .iter()
+        .flat_map(|b| b.column(column).as_string::<i32>().iter())
+        .map(|v| v.unwrap_or_default().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn should_describe_python_functions_in_information_schema() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let ctx = context();
+    ctx.python_sql(CREATE_FUNCTION).await?;
+
+    let routines = ctx
+        .sql("select description from information_schema.routines where routine_name = 'km_to_miles'")
+        .await?
+        .collect()
+        .await?;
+    let description = &strings(&routines, 0)[0];
+    assert!(description.contains("language: python, mode: pandas, volatility: immutable, null input: strict"));
+    assert!(description.contains("content: source sha256"));
+    assert!(description.contains("def to_miles(km, n):"));
+
+    let parameters = ctx
+        .sql(
+            "select parameter_name, data_type from information_schema.parameters \
+             where specific_name = 'km_to_miles' and parameter_mode = 'IN' order by ordinal_position",
+        )
+        .await?
+        .collect()
+        .await?;
+    assert_eq!(vec!["km", "arg2"], strings(&parameters, 0));
+    assert_eq!(vec!["Float64", "Int32"], strings(&parameters, 1));
+
+    let functions = ctx.sql("SHOW FUNCTIONS LIKE 'km_to_miles'").await?.collect().await?;
+    assert!(pretty_format_batches(&functions)?
+        .to_string()
+        .contains("python function"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_describe_pickled_function_without_pickling_it() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let ctx = context();
+    // function without known source, it would be pickled when encoded
+    let func = Python::with_gil(|py| {
+        let code = c"def to_miles(km):\n    return km\n";
+        PyModule::from_code(py, code, c"described_udfs.py", c"described_udfs")?
+            .getattr("to_miles")
+            .map(|f| f.unbind())
+    })
+    .expect("module created");
+    let udf = PythonUDF::new(
+        "to_miles",
+        vec![DataType::Float64],
+        DataType::Float64,
+        Volatility::Immutable,
+        func,
+    );
+    ctx.register_udf(ScalarUDF::from(udf));
+
+    let routines = ctx
+        .sql("select description from information_schema.routines where routine_name = 'to_miles'")
+        .await?
+        .collect()
+        .await?;
+    let description = &strings(&routines, 0)[0];
+    assert!(description.contains("content: pickle, digest is known once the function is encoded"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_show_create_function() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let ctx = context();
+    ctx.python_sql(CREATE_FUNCTION).await?;
+
+    let result = ctx
+        .python_sql("SHOW CREATE FUNCTION KM_TO_MILES")
+        .await?
+        .collect()
+        .await?;
+    assert_eq!(vec!["km_to_miles"], strings(&result, 0));
+    let definition = strings(&result, 1).remove(0);
+    assert!(definition.starts_with("CREATE FUNCTION \"km_to_miles\"(\"km\" DOUBLE, INTEGER)\nRETURNS DOUBLE"));
+
+    // statement creates the same function again
+    let other_ctx = context();
+    other_ctx.python_sql(&definition).await?;
+    let result = other_ctx
+        .python_sql("SHOW CREATE FUNCTION km_to_miles")
+        .await?
+        .collect()
+        .await?;
+    assert_eq!(vec![definition], strings(&result, 1));
+
+    assert!(ctx.python_sql("SHOW CREATE FUNCTION abs").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_truncate_long_source_in_description() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let ctx = context();
+    let comment = "#".repeat(1000);
+    ctx.python_sql(&format!(
+        "CREATE FUNCTION long_source(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON \
+         AS '{comment}\ndef long_source(a):\n    return a  # end of source\n'"
+    ))
+    .await?;
+
+    let routines = ctx
+        .sql("select description from information_schema.routines where routine_name = 'long_source'")
+        .await?
+        .collect()
+        .await?;
+    let description = &strings(&routines, 0)[0];
+    assert!(description.contains("truncated, see SHOW CREATE FUNCTION"));
+    assert!(!description.contains("end of source"));
+
+    let result = ctx
+        .python_sql("SHOW CREATE FUNCTION long_source")
+        .await?
+        .collect()
+        .await?;
+    assert!(strings(&result, 1)[0].contains("end of source"));
+
+    Ok(())
+}
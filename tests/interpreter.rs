@@ -0,0 +1,26 @@
+use ballista_python::{env::PythonEnvConfig, setup_python_with};
+use pyo3::types::PyAnyMethods;
+use pyo3::Python;
+
+// interpreter can be initialized once per process,
+// so this file should have a single test
+
+#[test]
+fn should_initialize_isolated_interpreter() {
+    setup_python_with(&PythonEnvConfig::new().with_isolated(true)).expect("python environment to be set");
+
+    Python::with_gil(|py| {
+        let flags = py.import("sys").unwrap().getattr("flags").unwrap();
+        let isolated: i32 = flags.getattr("isolated").unwrap().extract().unwrap();
+        let no_user_site: i32 = flags.getattr("no_user_site").unwrap().extract().unwrap();
+        assert_eq!(1, isolated);
+        assert_eq!(1, no_user_site);
+    });
+    // configured for interpreter only, not for child processes
+    assert!(std::env::var_os("PYTHONNOUSERSITE").is_none());
+    assert!(std::env::var_os("PYTHONSAFEPATH").is_none());
+
+    // interpreter is already initialized, other python home can't be set
+    let home = std::env::temp_dir();
+    assert!(setup_python_with(&PythonEnvConfig::new().with_python_home(home)).is_err());
+}
@@ -0,0 +1,178 @@
+use ballista_python::codec::{PyLogicalCodec, PyPhysicalCodec};
+use ballista_python::map_batches::{
+    MapBatchesExec, MapBatchesExt, MapBatchesFunction, MapBatchesNode, MapBatchesTableFunction, PythonQueryPlanner,
+};
+use ballista_python::setup_python;
+use ballista_python::udf::{CallMode, PythonUDF};
+use datafusion::arrow::array::{AsArray, Int32Array, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Int64Type, Schema, SchemaRef};
+use datafusion::common::Result;
+use datafusion::datasource::MemTable;
+use datafusion::execution::SessionStateBuilder;
+use datafusion::logical_expr::{Extension, LogicalPlan};
+use datafusion::physical_plan::collect;
+use datafusion::prelude::SessionContext;
+use datafusion_proto::logical_plan::LogicalExtensionCodec;
+use datafusion_proto::physical_plan::PhysicalExtensionCodec;
+use std::sync::Arc;
+
+fn context() -> Result<SessionContext> {
+    let state = SessionStateBuilder::new()
+        .with_default_features()
+        .with_query_planner(Arc::new(PythonQueryPlanner::default()))
+        .build();
+    let ctx = SessionContext::new_with_state(state);
+    // single partition with batches of 3 and 2 rows
+    let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+    let batches = [vec![1, 2, 3], vec![4, 5]]
+        .into_iter()
+        .map(|values| RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    ctx.register_table("t", Arc::new(MemTable::try_new(schema, vec![batches])?))?;
+
+    Ok(ctx)
+}
+
+/// output schema of test functions, `a` is cast to declared type
+fn output_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![Field::new("b", DataType::Int64, false)]))
+}
+
+fn function(name: &str, code: &str) -> Result<MapBatchesFunction> {
+    let udf = PythonUDF::from_code_with_types(name, code, vec![], DataType::Null)?.with_mode(CallMode::Capsule);
+
+    Ok(MapBatchesFunction::from_udf(udf, output_schema()))
+}
+
+fn values(batches: &[RecordBatch]) -> Vec<i64> {
+    batches
+        .iter()
+        .flat_map(|b| b.column(0).as_primitive::<Int64Type>().values().to_vec())
+        .collect()
+}
+
+#[tokio::test]
+async fn should_map_each_batch() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def duplicate(batch):
+    return [batch, batch]
+"#;
+    let ctx = context()?;
+
+    let df = ctx.table("t").await?.map_batches(function("duplicate", code)?)?;
+    assert_eq!(output_schema().as_ref(), df.schema().as_arrow());
+    let result = df.collect().await?;
+    assert_eq!(output_schema(), result[0].schema());
+    assert_eq!(vec![1, 2, 3, 1, 2, 3, 4, 5, 4, 5], values(&result));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_map_iterator_of_batches() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def first(batches):
+    count = 0
+    for batch in batches:
+        count += 1
+        if count == 1:
+            yield batch
+    assert count == 2
+"#;
+    let ctx = context()?;
+
+    let function = function("first", code)?.with_iterator(true);
+    let result = ctx.table("t").await?.map_batches(function)?.collect().await?;
+    assert_eq!(vec![1, 2, 3], values(&result));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_map_subquery_with_table_function() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def skip(batch):
+    return None if len(batch) < 3 else batch
+"#;
+    let ctx = context()?;
+    ctx.register_udtf("skip", Arc::new(MapBatchesTableFunction::new(function("skip", code)?)));
+
+    let result = ctx
+        .sql("select b from skip((select a from t)) where b > 1")
+        .await?
+        .collect()
+        .await?;
+    assert_eq!(vec![2, 3], values(&result));
+
+    assert!(ctx.sql("select * from skip('t')").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_fail_query_if_function_fails() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def fail(batch):
+    raise ValueError("bad batch")
+"#;
+    let ctx = context()?;
+
+    let error = ctx
+        .table("t")
+        .await?
+        .map_batches(function("fail", code)?)?
+        .collect()
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("python function: fail failed"));
+    assert!(error.to_string().contains("bad batch"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_round_trip_map_batches_plans() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let code = r#"
+def duplicate(batches):
+    for batch in batches:
+        yield batch
+        yield batch
+"#;
+    let ctx = context()?;
+    let function = function("duplicate", code)?.with_iterator(true);
+    let input = ctx.sql("select * from (values (1), (2)) as v(a)").await?;
+
+    let node = MapBatchesNode::try_new(input.logical_plan().clone(), function.clone())?;
+    let extension = Extension { node: Arc::new(node) };
+    let codec = PyLogicalCodec::default();
+    let mut buf = vec![];
+    codec.try_encode(&extension, &mut buf)?;
+    let decoded = codec.try_decode(&buf, &[input.logical_plan().clone()], &ctx)?;
+    assert_eq!(extension, decoded);
+
+    let plan = ctx
+        .state()
+        .create_physical_plan(&LogicalPlan::Extension(extension))
+        .await?;
+    let exec = plan.as_any().downcast_ref::<MapBatchesExec>().unwrap();
+    let codec = PyPhysicalCodec::default();
+    let mut buf = vec![];
+    codec.try_encode(plan.clone(), &mut buf)?;
+    let decoded = codec.try_decode(&buf, &[exec.input().clone()], &ctx)?;
+    let decoded_exec = decoded.as_any().downcast_ref::<MapBatchesExec>().unwrap();
+    assert_eq!(&function, decoded_exec.function());
+    assert_eq!(
+        function.python_udf().source,
+        decoded_exec.function().python_udf().source
+    );
+
+    let result = collect(decoded, ctx.task_ctx()).await?;
+    assert_eq!(vec![1, 2, 1, 2], values(&result));
+
+    Ok(())
+}
@@ -1,4 +1,8 @@
-use ballista_python::{pickle::CloudPickle, setup_python, udf::PythonUDF};
+use ballista_python::{
+    pickle::{serializer_for_format, PickleSerializer, PySerializer, FORMAT_DILL},
+    setup_python,
+    udf::PythonUDF,
+};
 use datafusion::common::Result;
 use pyo3::{prelude::*, types::PyString};
 
@@ -20,7 +24,7 @@ def to_miles(km_data):
 
     let udf = PythonUDF::from_code("to_miles", code).expect("udf created");
     Python::with_gil(|py| {
-        let c = CloudPickle::try_new(py).unwrap();
+        let c = PickleSerializer::cloudpickle(py).unwrap();
         let blob = c.pickle(py, &udf.func).unwrap();
         let unpickled = c.unpickle(py, &blob[..]).unwrap();
         let name = unpickled.getattr(py, "__name__").unwrap();
@@ -31,3 +35,92 @@ def to_miles(km_data):
 
     Ok(())
 }
+
+#[tokio::test]
+async fn round_trip_std_pickle() -> Result<()> {
+    setup_python().expect("python environment to be set");
+
+    let code = r#"
+def to_miles(km_data):
+    return km_data
+"#;
+
+    let udf = PythonUDF::from_code("to_miles", code).expect("udf created");
+    Python::with_gil(|py| {
+        let c = PickleSerializer::std_pickle(py).unwrap();
+        let blob = c.pickle(py, &udf.func).unwrap();
+        let unpickled = serializer_for_format(py, c.format())
+            .unwrap()
+            .unpickle(py, &blob[..])
+            .unwrap();
+        let name = unpickled.getattr(py, "__name__").unwrap();
+        let name = name.downcast_bound::<PyString>(py).unwrap().to_str().unwrap();
+
+        assert_eq!("to_miles", name);
+        assert!(serializer_for_format(py, "unknown").is_err());
+    });
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn round_trip_dill() -> Result<()> {
+    setup_python().expect("python environment to be set");
+
+    let code = r#"
+def to_miles(km_data):
+    return km_data
+"#;
+
+    let udf = PythonUDF::from_code("to_miles", code).expect("udf created");
+    Python::with_gil(|py| {
+        // dill is optional
+        let Ok(c) = PickleSerializer::dill(py) else {
+            eprintln!("dill is not installed, skipping the test");
+            return;
+        };
+        assert_eq!(FORMAT_DILL, c.format());
+        let blob = c.pickle(py, &udf.func).unwrap();
+        let unpickled = serializer_for_format(py, FORMAT_DILL)
+            .unwrap()
+            .unpickle(py, &blob[..])
+            .unwrap();
+        let name = unpickled.getattr(py, "__name__").unwrap();
+        let name = name.downcast_bound::<PyString>(py).unwrap().to_str().unwrap();
+
+        assert_eq!("to_miles", name);
+    });
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_isolate_globals_of_functions() -> Result<()> {
+    setup_python().expect("python environment to be set");
+
+    let code = |multiplier: f64| {
+        format!("SCALE = {multiplier}\n\ndef helper():\n    return SCALE\n\ndef scale(x):\n    return x * helper()\n")
+    };
+    let first = PythonUDF::from_code("scale", &code(2.0))?;
+    let second = PythonUDF::from_code("scale", &code(3.0))?;
+
+    Python::with_gil(|py| {
+        assert_eq!(4.0, first.func.call1(py, (2.0,)).unwrap().extract::<f64>(py).unwrap());
+        assert_eq!(6.0, second.func.call1(py, (2.0,)).unwrap().extract::<f64>(py).unwrap());
+
+        // functions do not leak into client's `__main__`
+        let main = py.import("__main__").unwrap();
+        assert!(!main.hasattr("helper").unwrap());
+
+        let module = first.func.getattr(py, "__module__").unwrap();
+        let module = module.extract::<String>(py).unwrap();
+        assert!(module.starts_with("ballista_udf_scale_"));
+
+        let c = PickleSerializer::cloudpickle(py).unwrap();
+        let blob = c.pickle(py, &first.func).unwrap();
+        let unpickled = c.unpickle(py, &blob[..]).unwrap();
+        assert_eq!(4.0, unpickled.call1(py, (2.0,)).unwrap().extract::<f64>(py).unwrap());
+    });
+
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use ballista_core::error::Result as BallistaResult;
+use ballista_core::serde::scheduler::{ExecutorData, ExecutorMetadata, ExecutorSpecification};
+use ballista_executor::executor_process::ExecutorProcessConfig;
+use ballista_executor::shutdown::ShutdownNotifier;
+use ballista_python::capabilities::PythonCapabilities;
+use ballista_python::flight::{fetch_capabilities, PythonFlightService};
+use ballista_python::placement::{python_requirements, stage_requirements, PythonDistributionPolicy};
+use ballista_python::requirements::PythonRequirement;
+use ballista_python::setup_python;
+use ballista_python::udf::PythonUDF;
+use ballista_scheduler::cluster::memory::InMemoryClusterState;
+use ballista_scheduler::cluster::ClusterState;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::Result;
+use datafusion::logical_expr::ScalarUDF;
+use datafusion::prelude::SessionContext;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+#[tokio::test]
+async fn should_find_requirements_of_python_functions() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let ctx = SessionContext::new();
+    let code = "def to_miles(km_data):\n    return km_data\n";
+    let udf = PythonUDF::from_code_with_types("to_miles", code, vec![DataType::Int64], DataType::Int64)?
+        .with_requirements(PythonRequirement::parse_list("pyarrow>=15")?);
+    ctx.register_udf(ScalarUDF::from(udf));
+
+    let plan = ctx
+        .sql("select to_miles(a) from (select unnest([1, 2, 3]) as a)")
+        .await?
+        .create_physical_plan()
+        .await?;
+    assert_eq!(
+        Some(PythonRequirement::parse_list("pyarrow>=15")?),
+        python_requirements(&plan)
+    );
+
+    let plan = ctx
+        .sql("select a from (select unnest([1, 2, 3]) as a)")
+        .await?
+        .create_physical_plan()
+        .await?;
+    assert_eq!(None, python_requirements(&plan));
+
+    // functions in windows and join conditions
+    let queries = [
+        "select row_number() over (partition by to_miles(a)) from (select unnest([1, 2, 3]) as a)",
+        "select * from (select unnest([1, 2]) as a) l join (select unnest([1, 2]) as b) r on to_miles(l.a) < r.b",
+        "select * from (select unnest([1, 2]) as a) l join (select unnest([1, 2]) as b) r on to_miles(l.a) = r.b",
+    ];
+    for query in queries {
+        let plan = ctx.sql(query).await?.create_physical_plan().await?;
+        assert_eq!(
+            Some(PythonRequirement::parse_list("pyarrow>=15")?),
+            python_requirements(&plan),
+            "{query}"
+        );
+        assert_eq!(
+            vec![PythonRequirement::parse_list("pyarrow>=15")?],
+            stage_requirements(&plan),
+            "{query}"
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_fail_plans_which_no_registered_executor_can_run() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let capabilities = PythonCapabilities::collect()?;
+    let (port, _notifier, server) = start_flight_service(&capabilities).await?;
+
+    let policy = Arc::new(PythonDistributionPolicy::new());
+    let cluster_state: Arc<dyn ClusterState> = Arc::new(InMemoryClusterState::default());
+    policy.watch(cluster_state.clone()).await?;
+
+    let plan = |requirements: &str| {
+        let requirements = PythonRequirement::parse_list(requirements).unwrap();
+        async move {
+            let ctx = SessionContext::new();
+            let code = "def to_miles(km_data):\n    return km_data\n";
+            let udf = PythonUDF::from_code_with_types("to_miles", code, vec![DataType::Int64], DataType::Int64)?
+                .with_requirements(requirements);
+            ctx.register_udf(ScalarUDF::from(udf));
+            ctx.sql("select to_miles(a) from (select unnest([1, 2, 3]) as a)")
+                .await?
+                .create_physical_plan()
+                .await
+        }
+    };
+    let satisfied = plan("python>=3").await?;
+    let not_satisfied = plan("package_which_does_not_exist").await?;
+
+    // no executor registered
+    assert!(policy.check(&satisfied).is_err());
+
+    let metadata = ExecutorMetadata {
+        id: "executor".to_string(),
+        host: "127.0.0.1".to_string(),
+        port,
+        grpc_port: 0,
+        specification: ExecutorSpecification { task_slots: 1 },
+    };
+    let data = ExecutorData {
+        executor_id: metadata.id.clone(),
+        total_task_slots: 1,
+        available_task_slots: 1,
+    };
+    cluster_state.register_executor(metadata, data).await.unwrap();
+    for _ in 0..50 {
+        if policy.executor_capabilities("executor").is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(
+        Some(capabilities),
+        policy.executor_capabilities("executor").as_deref().cloned()
+    );
+
+    policy.check(&satisfied)?;
+    let message = policy.check(&not_satisfied).unwrap_err().to_string();
+    assert!(message.contains("package_which_does_not_exist"), "{message}");
+
+    // capabilities of removed executor are dropped
+    cluster_state.remove_executor("executor").await.unwrap();
+    for _ in 0..50 {
+        if policy.executor_capabilities("executor").is_none() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(policy.check(&satisfied).is_err());
+
+    server.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_advertise_capabilities_with_flight_service() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let capabilities = PythonCapabilities::collect()?;
+    assert!(!capabilities.python_version.is_empty());
+
+    let (port, _notifier, server) = start_flight_service(&capabilities).await?;
+
+    let fetched = fetch_capabilities("127.0.0.1", port).await?;
+    assert_eq!(Some(capabilities), fetched);
+
+    server.abort();
+    Ok(())
+}
+
+/// starts flight service, running until returned notifier is dropped
+async fn start_flight_service(
+    capabilities: &PythonCapabilities,
+) -> Result<(u16, ShutdownNotifier, JoinHandle<BallistaResult<()>>)> {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    let provider = PythonFlightService::provider(capabilities, &ExecutorProcessConfig::default());
+    let notifier = ShutdownNotifier::new();
+    let server = provider(
+        format!("127.0.0.1:{port}").parse().unwrap(),
+        notifier.subscribe_for_shutdown(),
+    );
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    Ok((port, notifier, server))
+}
@@ -0,0 +1,40 @@
+use ballista_python::python::extension_module;
+use ballista_python::setup_python;
+use pyo3::types::{PyAnyMethods, PyDict};
+use pyo3::{wrap_pymodule, PyResult, Python};
+
+#[test]
+fn should_expose_ballista_client_module() -> PyResult<()> {
+    setup_python().expect("python environment to be set");
+    Python::with_gil(|py| {
+        let module = wrap_pymodule!(extension_module)(py);
+        let module = module.bind(py);
+        assert_eq!(
+            env!("CARGO_PKG_VERSION"),
+            module.getattr("__version__")?.extract::<String>()?
+        );
+
+        // scheduler is not contacted until a query is executed
+        let ctx = module.call_method1("connect", ("df://localhost:50050",))?;
+        ctx.call_method1(
+            "sql",
+            ("CREATE FUNCTION to_miles(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON \
+              AS 'def to_miles(km):\n    return km\n'",),
+        )?;
+        let df = ctx.call_method1("sql", ("SELECT to_miles(1.0) AS miles",))?;
+        assert!(df.repr()?.to_string().contains("miles"));
+
+        // types are valid, so volatility is the one rejected
+        let float64 = py.import("pyarrow")?.call_method0("float64")?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("volatility", "unknown")?;
+        let func = py.eval(pyo3::ffi::c_str!("lambda km: km"), None, None)?;
+        let message = ctx
+            .call_method("register_udf", (func, vec![&float64], &float64), Some(&kwargs))
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("unsupported volatility: unknown"), "{message}");
+
+        Ok(())
+    })
+}
@@ -0,0 +1,289 @@
+mod common;
+
+use ballista_core::execution_plans::ShuffleWriterExec;
+use ballista_executor::execution_engine::ExecutionEngine;
+use ballista_python::codec::PyPhysicalCodec;
+use ballista_python::setup_python;
+use ballista_python::sql::PythonSessionExt;
+use ballista_python::stateful::{
+    close_instances, evict_instances, set_instance_ttl, InstanceScope, PythonExecutionEngine, PythonInstance,
+    DEFAULT_INSTANCE_TTL,
+};
+use ballista_python::udf::{CallMode, PythonUDF};
+use common::temp_dir;
+use datafusion::arrow::array::{AsArray, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Float64Type};
+use datafusion::common::Result;
+use datafusion::execution::{FunctionRegistry, TaskContext};
+use datafusion::logical_expr::ScalarUDF;
+use datafusion::prelude::{SessionConfig, SessionContext};
+use datafusion_proto::physical_plan::PhysicalExtensionCodec;
+use pyo3::types::{PyAnyMethods, PyDict, PyDictMethods, PyTuple};
+use pyo3::{PyObject, Python};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// process instances are shared by tests of this file
+static PROCESS_INSTANCES: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+static CODE: &str = r#"
+stats = {"init": 0, "setup": 0, "calls": 0, "closed": 0}
+
+class Model:
+    def __init__(self, factor=1, scale=1):
+        stats["init"] += 1
+        stats["factor"] = factor
+        stats["scale"] = scale
+
+    def setup(self):
+        stats["setup"] += 1
+
+    def __call__(self, x):
+        stats["calls"] += 1
+        return x
+
+    def close(self):
+        stats["closed"] += 1
+"#;
+
+/// function calling `Model` instance created with `Model(2, scale=3)`
+fn model(scope: InstanceScope) -> Result<PythonUDF> {
+    model_with_factor(scope, 2)
+}
+
+/// function calling `Model` instance created with `Model(factor, scale=3)`
+fn model_with_factor(scope: InstanceScope, factor: usize) -> Result<PythonUDF> {
+    let udf =
+        PythonUDF::from_code_with_entry_point("model", CODE, "Model", vec![DataType::Float64], DataType::Float64)?
+            .with_mode(CallMode::Capsule);
+    let instance = Python::with_gil(|py| {
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("scale", 3)?;
+        PythonInstance::try_new(py, &udf.func, &PyTuple::new(py, [factor])?, Some(&kwargs))
+    })
+    .expect("instance to be created");
+
+    Ok(udf.with_instance(instance.with_scope(scope)))
+}
+
+/// calls function with values 1.0 to 3.0
+async fn call(udf: Arc<ScalarUDF>) -> Result<Vec<RecordBatch>> {
+    let ctx = SessionContext::new();
+    ctx.register_udf(udf.as_ref().clone());
+    ctx.sql("select model(cast(value as double)) from generate_series(1, 3)")
+        .await?
+        .collect()
+        .await
+}
+
+/// executes stage calling the function with values 1.0 to 3.0,
+/// as executor task with given id
+async fn execute_task(udf: Arc<ScalarUDF>, task_id: &str) -> Result<()> {
+    let ctx = SessionContext::new();
+    ctx.register_udf(udf.as_ref().clone());
+    let plan = ctx
+        .sql("select model(cast(value as double)) from generate_series(1, 3)")
+        .await?
+        .create_physical_plan()
+        .await?;
+    let work_dir = temp_dir(&format!("stateful_{task_id}"));
+    let work_dir = work_dir.to_str().unwrap();
+    let stage = ShuffleWriterExec::try_new("job".into(), 1, plan, work_dir.into(), None)?;
+    let stage = PythonExecutionEngine::default().create_query_stage_exec("job".into(), 1, Arc::new(stage), work_dir)?;
+    let context = TaskContext::new(
+        Some(task_id.into()),
+        "session".into(),
+        SessionConfig::new(),
+        HashMap::new(),
+        HashMap::new(),
+        HashMap::new(),
+        ctx.runtime_env(),
+    );
+    stage.execute_query_stage(0, Arc::new(context)).await?;
+    Ok(())
+}
+
+fn doubles(batches: &[RecordBatch]) -> Vec<Option<f64>> {
+    batches
+        .iter()
+        .flat_map(|b| b.column(0).as_primitive::<Float64Type>().iter())
+        .collect()
+}
+
+/// `stats` global of the class module
+fn stat(class: &PyObject, name: &str) -> usize {
+    Python::with_gil(|py| {
+        let globals = class
+            .bind(py)
+            .getattr("__init__")
+            .unwrap()
+            .getattr("__globals__")
+            .unwrap();
+        globals
+            .get_item("stats")
+            .unwrap()
+            .get_item(name)
+            .unwrap()
+            .extract()
+            .unwrap()
+    })
+}
+
+fn class(udf: &ScalarUDF) -> PyObject {
+    let udf = udf.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+    Python::with_gil(|py| udf.func.clone_ref(py))
+}
+
+#[tokio::test]
+async fn should_share_instance_within_process() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let _lock = PROCESS_INSTANCES.lock().await;
+    let udf = Arc::new(ScalarUDF::from(model(InstanceScope::Process)?));
+    let class = class(&udf);
+
+    call(udf.clone()).await?;
+    let result = call(udf.clone()).await?;
+    assert_eq!(vec![Some(1.0), Some(2.0), Some(3.0)], doubles(&result));
+    assert_eq!(1, stat(&class, "init"));
+    assert_eq!(1, stat(&class, "setup"));
+    assert_eq!(2, stat(&class, "calls"));
+    assert_eq!(2, stat(&class, "factor"));
+    assert_eq!(3, stat(&class, "scale"));
+
+    // decoded function with the same class and arguments shares the instance,
+    // decoding compiles the module again, so its stats are reset
+    let codec = PyPhysicalCodec::default();
+    let mut buf = vec![];
+    codec.try_encode_udf(&udf, &mut buf)?;
+    let decoded = codec.try_decode_udf("model", &buf)?;
+    call(decoded).await?;
+    assert_eq!(0, stat(&class, "init"));
+    assert_eq!(1, stat(&class, "calls"));
+
+    assert_eq!(1, close_instances());
+    assert_eq!(1, stat(&class, "closed"));
+    // instance is created again once closed
+    call(udf).await?;
+    assert_eq!(1, stat(&class, "init"));
+    close_instances();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_evict_superseded_and_unused_instances() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let _lock = PROCESS_INSTANCES.lock().await;
+    let udf = Arc::new(ScalarUDF::from(model(InstanceScope::Process)?));
+    call(udf.clone()).await?;
+
+    // function replaced by one with other arguments supersedes its
+    // instance, which gets closed. the same source is compiled again,
+    // so both classes share reset stats
+    let replaced = Arc::new(ScalarUDF::from(model_with_factor(InstanceScope::Process, 5)?));
+    let class = class(&replaced);
+    call(replaced.clone()).await?;
+    assert_eq!(1, stat(&class, "init"));
+    assert_eq!(5, stat(&class, "factor"));
+    assert_eq!(1, stat(&class, "closed"));
+
+    // instance is not closed while it is referenced
+    let instance = Python::with_gil(|py| {
+        let replaced = replaced.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+        replaced.callable(py).unwrap()
+    });
+    set_instance_ttl(Duration::ZERO);
+    assert_eq!(1, evict_instances());
+    assert_eq!(1, stat(&class, "closed"));
+    drop(instance);
+    assert_eq!(0, evict_instances());
+    assert_eq!(2, stat(&class, "closed"));
+
+    set_instance_ttl(DEFAULT_INSTANCE_TTL);
+    assert_eq!(0, close_instances());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_create_instance_per_task_in_task_scope() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let udf = ScalarUDF::from(model(InstanceScope::Task)?);
+    let codec = PyPhysicalCodec::default();
+    let mut buf = vec![];
+    codec.try_encode_udf(&udf, &mut buf)?;
+
+    // decoded function is shared by tasks, like tasks
+    // of a multi task definition, each task gets own instance
+    let decoded = codec.try_decode_udf("model", &buf)?;
+    let function = decoded.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+    let original = udf.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+    assert_eq!(original.instance, function.instance);
+    assert!(Arc::ptr_eq(&decoded, &codec.try_decode_udf("model", &buf)?));
+
+    let class = class(&decoded);
+    execute_task(decoded.clone(), "task_1").await?;
+    assert_eq!(1, stat(&class, "init"));
+    assert_eq!(1, stat(&class, "setup"));
+    assert_eq!(1, stat(&class, "calls"));
+    assert_eq!(2, stat(&class, "factor"));
+    // instance is closed once the task completes
+    assert_eq!(1, stat(&class, "closed"));
+
+    execute_task(decoded.clone(), "task_2").await?;
+    assert_eq!(2, stat(&class, "init"));
+    assert_eq!(2, stat(&class, "closed"));
+
+    // called outside of tasks, instance lives with the function
+    call(decoded.clone()).await?;
+    call(decoded.clone()).await?;
+    assert_eq!(3, stat(&class, "init"));
+    assert_eq!(2, stat(&class, "closed"));
+    drop(decoded);
+    codec.invalidate("model");
+    assert_eq!(3, stat(&class, "closed"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn should_create_class_based_function() -> Result<()> {
+    setup_python().expect("python environment to be set");
+    let ctx = SessionContext::new();
+    ctx.python_sql(
+        "CREATE FUNCTION model(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON \
+         OPTIONS (handler 'Model', mode 'capsule', instance 'task') \
+         AS 'class Model:\n    def __call__(self, x):\n        return x\n'",
+    )
+    .await?;
+    let udf = ctx.udf("model")?;
+    let function = udf.inner().as_any().downcast_ref::<PythonUDF>().unwrap();
+    let instance = function.instance.as_ref().unwrap();
+    assert_eq!(InstanceScope::Task, instance.scope());
+    assert!(instance.arguments().is_empty());
+
+    let result = ctx
+        .sql("select model(cast(value as double)) from generate_series(1, 3)")
+        .await?
+        .collect()
+        .await?;
+    assert_eq!(vec![Some(1.0), Some(2.0), Some(3.0)], doubles(&result));
+
+    assert!(ctx
+        .python_sql(
+            "CREATE FUNCTION f(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON OPTIONS (instance 'task') \
+             AS 'def f(x):\n    return x\n'",
+        )
+        .await
+        .is_err());
+    assert!(ctx
+        .python_sql(
+            "CREATE FUNCTION g(DOUBLE) RETURNS DOUBLE LANGUAGE PYTHON OPTIONS (handler 'G', instance 'thread') \
+             AS 'class G:\n    def __call__(self, x):\n        return x\n'",
+        )
+        .await
+        .is_err());
+
+    Ok(())
+}
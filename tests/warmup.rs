@@ -0,0 +1,50 @@
+use ballista_python::{registry::FunctionReference, setup_python, warmup::PythonWarmUp};
+use datafusion::common::Result;
+use std::str::FromStr;
+
+#[test]
+fn should_import_modules_and_run_warm_up() -> Result<()> {
+    setup_python().expect("python environment to be set");
+
+    PythonWarmUp::new()
+        .with_module("json")
+        .with_module("decimal")
+        .with_callable(FunctionReference::from_str("gc:collect")?)
+        .run()
+}
+
+#[test]
+fn should_report_all_warm_up_failures() -> Result<()> {
+    setup_python().expect("python environment to be set");
+
+    let result = PythonWarmUp::new()
+        .with_module("json")
+        .with_module("module_which_does_not_exist")
+        .with_callable(FunctionReference::from_str("json:function_which_does_not_exist")?)
+        .run();
+
+    let message = result.unwrap_err().to_string();
+    assert!(message.contains("module_which_does_not_exist"));
+    assert!(message.contains("function_which_does_not_exist"));
+
+    Ok(())
+}
+
+#[test]
+fn should_check_warm_up_callable_version() -> Result<()> {
+    setup_python().expect("python environment to be set");
+
+    // `decimal.__version__` is `1.70`
+    PythonWarmUp::new()
+        .with_callable(FunctionReference::from_str("decimal:getcontext@1.70.0")?)
+        .run()?;
+
+    let message = PythonWarmUp::new()
+        .with_callable(FunctionReference::from_str("decimal:getcontext@2.0")?)
+        .run()
+        .unwrap_err()
+        .to_string();
+    assert!(message.contains("installed version is: 1.70"));
+
+    Ok(())
+}